@@ -1,19 +1,34 @@
 import {wgsl} from './wgsl-plugin';
-import {copyFileSync} from 'fs';
+import {copyFileSync, cpSync} from 'fs';
 
 const isDev = process.argv.includes('--dev');
+// The threaded wasm spawns its pool workers from the pkg's own module URLs,
+// so the pkg is copied to dist/pkg and imported from there instead of bundled
+const isThreads = process.argv.includes('--threads');
+
+const unbundledPkg = {
+    name: "Unbundled wasm pkg",
+    setup(build) {
+        build.onResolve({filter: /^src\/my-lib\/pkg$/}, () => ({
+            path: './pkg/my_lib.js',
+            external: true,
+        }));
+    }
+};
 
 const result = await Bun.build({
     entrypoints: ['./src/index.html', './src/worker.ts'],
     outdir: './dist',
     plugins: [
-        wgsl({minify: !isDev})
+        wgsl({minify: !isDev}),
+        ...(isThreads ? [unbundledPkg] : [])
     ],
     target: 'browser',
     minify: !isDev,
     sourcemap: isDev ? 'inline' : undefined,
     define: {
-        'process.env.NODE_ENV': JSON.stringify(isDev ? 'development' : 'production')
+        'process.env.NODE_ENV': JSON.stringify(isDev ? 'development' : 'production'),
+        'process.env.WASM_THREADS': JSON.stringify(isThreads ? 'true' : 'false')
     },
     naming: {
         chunk: '[name].[ext]'
@@ -25,7 +40,10 @@ if (!result.success) {
     for (const message of result.logs) {
         console.error(message);
     }
+} else if (isThreads) {
+    // Copy the whole pkg, workerHelpers are loaded from its snippets
+    cpSync('./src/my-lib/pkg', './dist/pkg', {recursive: true});
 } else {
     // Copy WASM file to dist
     copyFileSync('./src/my-lib/pkg/my_lib_bg.wasm', './dist/my_lib_bg.wasm');
-}
\ No newline at end of file
+}
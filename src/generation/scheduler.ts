@@ -1,5 +1,12 @@
 import {Result} from "./message";
 
+// Threaded builds mesh each chunk on a pool inside the worker, so they use a
+// few workers splitting the cores between them instead of one per core
+export const WORKERS = process.env.WASM_THREADS === 'true' ? 2 : navigator.hardwareConcurrency;
+export const THREADS_PER_WORKER = Math.max(1, Math.ceil(navigator.hardwareConcurrency / WORKERS));
+// Voxel cache shared out between the workers, each keeps its own slice
+export const VOXEL_CACHE_BYTES = 256 * 1024 * 1024;
+
 interface Task {
 	id: number
 	operation: string
@@ -17,7 +24,7 @@ export class Scheduler {
 
 	constructor() {
 
-		for (let i = 0; i < navigator.hardwareConcurrency; i++) {
+		for (let i = 0; i < WORKERS; i++) {
 
 			const worker = new Worker("./worker.js", {type: 'module'});
 
@@ -0,0 +1,41 @@
+use rustc_hash::FxHashMap;
+
+use crate::mesh::Chunk;
+
+/// Meshed chunks kept alive in wasm memory so JS can read their buffers in
+/// place. Handles are never 0 and are not reused until the counter wraps, so
+/// a stale handle fails the lookup instead of reading another chunk.
+#[derive(Default)]
+pub(crate) struct MeshArena {
+    meshes: FxHashMap<u32, Chunk>,
+    next_handle: u32,
+}
+
+impl MeshArena {
+    pub(crate) fn insert(&mut self, chunk: Chunk) -> u32 {
+        loop {
+            self.next_handle = self.next_handle.wrapping_add(1);
+            if self.next_handle != 0 && !self.meshes.contains_key(&self.next_handle) {
+                break;
+            }
+        }
+        self.meshes.insert(self.next_handle, chunk);
+        self.next_handle
+    }
+
+    pub(crate) fn get(&self, handle: u32) -> Option<&Chunk> {
+        self.meshes.get(&handle)
+    }
+
+    pub(crate) fn get_mut(&mut self, handle: u32) -> Option<&mut Chunk> {
+        self.meshes.get_mut(&handle)
+    }
+
+    pub(crate) fn remove(&mut self, handle: u32) -> Option<Chunk> {
+        self.meshes.remove(&handle)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.meshes.len()
+    }
+}
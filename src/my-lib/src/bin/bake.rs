@@ -0,0 +1,470 @@
+//! Pre-generates chunk meshes to disk so the client can stream them instead
+//! of meshing on the fly.
+//!
+//! ```text
+//! bake --min -2,-1,-2 --max 1,0,1 --lods 0,1,2 --seed 7 --skirt down --out dist/baked
+//! bake --min 0,0,0 --max 3,0,3 --world world.json --param octaves=6 --pack world.ocmp
+//! ```
+//!
+//! Every chunk is a mesh blob (see blob.rs) that `load_mesh_blob` reads back.
+//! With `--out` they are written as `lod<L>/<x>_<y>_<z>.ocmb` next to a
+//! `manifest.json`, a directory `server.js` serves as-is when it sits inside
+//! `dist`. With `--pack` they go into one file: magic "OCMP", version and
+//! entry count, then per entry x, y, z, lod, byte offset (low and high word)
+//! and byte length, all u32 little-endian, followed by the 16-byte aligned
+//! blobs. The index has room for every baked chunk so blobs are appended as
+//! they are meshed, unused entries stay zero. Chunks without triangles are
+//! listed in the manifest but not stored.
+//! `--export glb|obj` also writes each chunk for external tools next to its
+//! blob, one group per meshlet.
+
+use std::fs;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde_json::{json, Map, Value};
+
+use my_lib::{
+    mesh_chunk, parse_graph, write_blob, write_glb, write_obj, Chunk, DensityGraph, IndexFormat,
+    MeshOptions, Mesher, Skirt, VertexFormat, BLOB_VERSION, FORMAT_VERSION,
+};
+
+const USAGE: &str = "usage: bake --min X,Y,Z --max X,Y,Z (--out DIR | --pack FILE) [options]
+
+  --min, --max X,Y,Z      chunk region, both corners included
+  --lods L,...            LODs to bake (default 0)
+  --seed N                seed of the built-in terrain (default 0)
+  --param NAME=VALUE      terrain setting from the world format, e.g. octaves=6
+  --world FILE            world definition JSON instead of the built-in terrain
+  --skirt down|inward     add skirt draws, hides cracks between LODs
+  --mesher marching-cubes|surface-nets|dual-contouring
+  --packed                16-byte packed vertices instead of float
+  --u32-indices           u32 indices, no draw splitting
+  --out DIR               one blob per chunk plus manifest.json
+  --pack FILE             all blobs in one pack file
+  --export glb|obj        with --out, also write each chunk as .glb or .obj";
+
+const PACK_MAGIC: u32 = u32::from_le_bytes(*b"OCMP");
+const PACK_VERSION: u32 = 1;
+const PACK_ENTRY_WORDS: usize = 7;
+const PACK_ALIGN: u64 = 16;
+
+#[derive(Clone, Copy)]
+enum Export {
+    Glb,
+    Obj,
+}
+
+enum Output {
+    Directory(PathBuf),
+    Pack(PathBuf),
+}
+
+struct Args {
+    min: [i32; 3],
+    max: [i32; 3],
+    lods: Vec<u32>,
+    seed: u32,
+    params: Map<String, Value>,
+    world: Option<PathBuf>,
+    options: MeshOptions,
+    output: Output,
+    export: Option<Export>,
+}
+
+/// One baked chunk, as listed in the manifest or pack index
+struct Baked {
+    chunk: [i32; 3],
+    lod: u32,
+    triangles: usize,
+    vertices: usize,
+    // Blob size, None when the chunk has no triangles and was not stored
+    bytes: Option<usize>,
+}
+
+/// Where chunks go as soon as they are meshed, so only their index entries
+/// stay in memory and a failed bake keeps what it finished
+enum Sink {
+    Directory(PathBuf),
+    Pack(Pack),
+}
+
+struct Pack {
+    path: PathBuf,
+    out: BufWriter<fs::File>,
+    entries: Vec<[u32; PACK_ENTRY_WORDS]>,
+    written: u64,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Err(error) = parse_args(&args).and_then(|args| bake(&args)) {
+        eprintln!("bake: {}", error);
+        std::process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut min = None;
+    let mut max = None;
+    let mut lods = vec![0];
+    let mut seed = 0;
+    let mut params = Map::new();
+    let mut world = None;
+    let mut options = MeshOptions::default();
+    let mut output = None;
+    let mut export = None;
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} needs a value\n\n{}", flag, USAGE))
+        };
+        match flag.as_str() {
+            "--min" => min = Some(parse_coords(value()?)?),
+            "--max" => max = Some(parse_coords(value()?)?),
+            "--lods" => {
+                lods = value()?
+                    .split(',')
+                    .map(|lod| match lod.trim().parse() {
+                        Ok(lod) if lod <= 5 => Ok(lod),
+                        _ => Err(format!("invalid LOD '{}', expected 0 to 5", lod)),
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            "--seed" => {
+                let text = value()?;
+                seed = text
+                    .parse()
+                    .map_err(|_| format!("invalid seed '{}'", text))?
+            }
+            "--param" => {
+                let text = value()?;
+                let (name, raw) = text
+                    .split_once('=')
+                    .ok_or_else(|| format!("--param expects NAME=VALUE, got '{}'", text))?;
+                // Numbers stay numbers, anything else (like noise=perlin) is a string
+                let value = serde_json::from_str(raw).unwrap_or_else(|_| json!(raw));
+                params.insert(name.to_string(), value);
+            }
+            "--world" => world = Some(PathBuf::from(value()?)),
+            "--skirt" => {
+                options.skirt = Some(match value()?.as_str() {
+                    "down" => Skirt::Down,
+                    "inward" => Skirt::Inward,
+                    other => return Err(format!("unknown skirt '{}'", other)),
+                })
+            }
+            "--mesher" => {
+                options.mesher = match value()?.as_str() {
+                    "marching-cubes" => Mesher::MarchingCubes,
+                    "surface-nets" => Mesher::SurfaceNets,
+                    "dual-contouring" => Mesher::DualContouring,
+                    other => return Err(format!("unknown mesher '{}'", other)),
+                }
+            }
+            "--packed" => options.vertex_format = VertexFormat::Packed,
+            "--u32-indices" => options.index_format = IndexFormat::Uint32,
+            "--out" => output = Some(Output::Directory(PathBuf::from(value()?))),
+            "--pack" => output = Some(Output::Pack(PathBuf::from(value()?))),
+            "--export" => {
+                export = Some(match value()?.as_str() {
+                    "glb" => Export::Glb,
+                    "obj" => Export::Obj,
+                    other => return Err(format!("unknown export format '{}'", other)),
+                })
+            }
+            other => return Err(format!("unknown argument '{}'\n\n{}", other, USAGE)),
+        }
+    }
+
+    let (Some(min), Some(max)) = (min, max) else {
+        return Err(format!("--min and --max are required\n\n{}", USAGE));
+    };
+    if (0..3).any(|axis| min[axis] > max[axis]) {
+        return Err("--min must not exceed --max on any axis".to_string());
+    }
+    let output = output.ok_or_else(|| format!("--out or --pack is required\n\n{}", USAGE))?;
+    if export.is_some() && matches!(output, Output::Pack(_)) {
+        return Err("--export needs --out".to_string());
+    }
+    if world.is_some() && (seed != 0 || !params.is_empty()) {
+        return Err(
+            "--seed and --param only apply to the built-in terrain, not --world".to_string(),
+        );
+    }
+
+    Ok(Args {
+        min,
+        max,
+        lods,
+        seed,
+        params,
+        world,
+        options,
+        output,
+        export,
+    })
+}
+
+fn parse_coords(text: &str) -> Result<[i32; 3], String> {
+    let coords: Vec<i32> = text
+        .split(',')
+        .map(|c| c.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid chunk coordinates '{}', expected X,Y,Z", text))?;
+    coords
+        .try_into()
+        .map_err(|_| format!("invalid chunk coordinates '{}', expected X,Y,Z", text))
+}
+
+/// The world to bake, parsed through the world format so `--param` gets the
+/// same validation and error messages as a terrain node in a world file
+fn generator(args: &Args) -> Result<DensityGraph, String> {
+    let json = match &args.world {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?,
+        None => {
+            let mut terrain = args.params.clone();
+            terrain.insert("type".to_string(), json!("terrain"));
+            terrain.insert("seed".to_string(), json!(args.seed));
+            json!({ "version": FORMAT_VERSION, "root": 0, "nodes": [terrain] }).to_string()
+        }
+    };
+    parse_graph(&json).map_err(|e| e.to_string())
+}
+
+fn bake(args: &Args) -> Result<(), String> {
+    let generator = generator(args)?;
+
+    let mut chunks = Vec::new();
+    for &lod in &args.lods {
+        for z in args.min[2]..=args.max[2] {
+            for y in args.min[1]..=args.max[1] {
+                for x in args.min[0]..=args.max[0] {
+                    chunks.push(([x, y, z], lod));
+                }
+            }
+        }
+    }
+
+    let mut sink = Sink::open(&args.output, &args.lods, chunks.len())?;
+    let started = Instant::now();
+    let mut baked = Vec::with_capacity(chunks.len());
+    let mut written = 0;
+    for (i, &(chunk, lod)) in chunks.iter().enumerate() {
+        let start = Instant::now();
+        let [x, y, z] = chunk;
+        let mesh = mesh_chunk(&generator, x, y, z, lod, args.options);
+        let triangles = triangle_count(&mesh);
+        let blob = (triangles > 0).then(|| write_blob(&mesh));
+        let export = args
+            .export
+            .filter(|_| triangles > 0)
+            .map(|export| match export {
+                Export::Glb => write_glb(&mesh, true),
+                Export::Obj => write_obj(&mesh, true).into_bytes(),
+            });
+
+        let size = blob.as_ref().map_or(0, Vec::len);
+        written += size;
+        println!(
+            "[{}/{}] lod {} ({}, {}, {}): {} triangles, {} vertices, {} in {} ms",
+            i + 1,
+            chunks.len(),
+            lod,
+            x,
+            y,
+            z,
+            triangles,
+            mesh.vertex_count(),
+            if blob.is_some() {
+                format_bytes(size)
+            } else {
+                "empty".to_string()
+            },
+            start.elapsed().as_millis()
+        );
+        let b = Baked {
+            chunk,
+            lod,
+            triangles,
+            vertices: mesh.vertex_count(),
+            bytes: blob.as_ref().map(Vec::len),
+        };
+        if let Some(blob) = &blob {
+            sink.write(&b, blob, export.as_deref().zip(args.export))?;
+        }
+        baked.push(b);
+    }
+    sink.finish(args, &baked)?;
+
+    println!(
+        "baked {} chunks ({} with geometry, {} triangles, {}) in {:.1} s",
+        baked.len(),
+        baked.iter().filter(|b| b.bytes.is_some()).count(),
+        baked.iter().map(|b| b.triangles).sum::<usize>(),
+        format_bytes(written),
+        started.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
+
+// Every draw, including skirts and split parts, holds whole triangles
+fn triangle_count(chunk: &Chunk) -> usize {
+    chunk
+        .commands()
+        .iter()
+        .map(|command| command.index_count as usize / 3)
+        .sum()
+}
+
+fn chunk_file(baked: &Baked, extension: &str) -> String {
+    let [x, y, z] = baked.chunk;
+    format!("lod{}/{}_{}_{}.{}", baked.lod, x, y, z, extension)
+}
+
+impl Sink {
+    fn open(output: &Output, lods: &[u32], chunks: usize) -> Result<Sink, String> {
+        match output {
+            Output::Directory(dir) => {
+                for lod in lods {
+                    let path = dir.join(format!("lod{}", lod));
+                    fs::create_dir_all(&path).map_err(|e| io_error(&path, e))?;
+                }
+                Ok(Sink::Directory(dir.clone()))
+            }
+            Output::Pack(path) => {
+                let file = fs::File::create(path).map_err(|e| io_error(path, e))?;
+                let mut pack = Pack {
+                    path: path.clone(),
+                    out: BufWriter::new(file),
+                    entries: Vec::new(),
+                    written: 0,
+                };
+                // Room for the header and an entry per chunk, filled in by finish
+                let header_bytes = (3 + PACK_ENTRY_WORDS * chunks) as u64 * 4;
+                pack.pad(header_bytes.next_multiple_of(PACK_ALIGN))?;
+                Ok(Sink::Pack(pack))
+            }
+        }
+    }
+
+    // Stores the blob of a chunk with triangles, and its export next to it
+    fn write(
+        &mut self,
+        b: &Baked,
+        blob: &[u8],
+        export: Option<(&[u8], Export)>,
+    ) -> Result<(), String> {
+        match self {
+            Sink::Directory(dir) => {
+                let path = dir.join(chunk_file(b, "ocmb"));
+                fs::write(&path, blob).map_err(|e| io_error(&path, e))?;
+                if let Some((bytes, export)) = export {
+                    let extension = match export {
+                        Export::Glb => "glb",
+                        Export::Obj => "obj",
+                    };
+                    let path = dir.join(chunk_file(b, extension));
+                    fs::write(&path, bytes).map_err(|e| io_error(&path, e))?;
+                }
+                Ok(())
+            }
+            Sink::Pack(pack) => {
+                let offset = pack.written.next_multiple_of(PACK_ALIGN);
+                pack.pad(offset)?;
+                pack.entries.push([
+                    b.chunk[0] as u32,
+                    b.chunk[1] as u32,
+                    b.chunk[2] as u32,
+                    b.lod,
+                    offset as u32,
+                    (offset >> 32) as u32,
+                    blob.len() as u32,
+                ]);
+                pack.out
+                    .write_all(blob)
+                    .map_err(|e| io_error(&pack.path, e))?;
+                pack.written += blob.len() as u64;
+                Ok(())
+            }
+        }
+    }
+
+    // Writes the manifest or the pack index once every chunk is stored
+    fn finish(self, args: &Args, baked: &[Baked]) -> Result<(), String> {
+        match self {
+            Sink::Directory(dir) => {
+                let entries: Vec<Value> = baked
+                    .iter()
+                    .map(|b| {
+                        let mut entry = json!({
+                            "x": b.chunk[0],
+                            "y": b.chunk[1],
+                            "z": b.chunk[2],
+                            "lod": b.lod,
+                            "triangles": b.triangles,
+                            "vertices": b.vertices,
+                        });
+                        if let Some(bytes) = b.bytes {
+                            entry["file"] = json!(chunk_file(b, "ocmb"));
+                            entry["bytes"] = json!(bytes);
+                        }
+                        entry
+                    })
+                    .collect();
+                let manifest = json!({
+                    "blob_version": BLOB_VERSION,
+                    "min": args.min,
+                    "max": args.max,
+                    "lods": args.lods,
+                    "chunks": entries,
+                });
+                let path = dir.join("manifest.json");
+                let text =
+                    serde_json::to_string_pretty(&manifest).expect("manifests always serialize");
+                fs::write(&path, text).map_err(|e| io_error(&path, e))
+            }
+            Sink::Pack(mut pack) => {
+                let mut words = vec![PACK_MAGIC, PACK_VERSION, pack.entries.len() as u32];
+                words.extend(pack.entries.iter().flatten());
+                let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+                let error = |e| io_error(&pack.path, e);
+                pack.out.seek(SeekFrom::Start(0)).map_err(error)?;
+                pack.out.write_all(&bytes).map_err(error)?;
+                pack.out.flush().map_err(error)
+            }
+        }
+    }
+}
+
+impl Pack {
+    // Appends zeros up to byte `end`
+    fn pad(&mut self, end: u64) -> Result<(), String> {
+        let zeros = vec![0; (end - self.written) as usize];
+        self.out
+            .write_all(&zeros)
+            .map_err(|e| io_error(&self.path, e))?;
+        self.written = end;
+        Ok(())
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> String {
+    format!("cannot write {}: {}", path.display(), e)
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1 << 20 {
+        format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+    } else {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    }
+}
@@ -0,0 +1,377 @@
+use std::fmt;
+
+use crate::bounds::BOUNDS_STRIDE;
+use crate::mesh::{Chunk, Command, IndexFormat, VertexFormat};
+use crate::packed::{PACKED_WORDS, POSITION_STEPS};
+
+/// Current version of the mesh blob layout
+pub const BLOB_VERSION: u32 = 1;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"OCMB");
+
+// Sections start on 16 bytes, so every one can back a typed array or GPU upload directly
+const ALIGN: usize = 16;
+
+// Words before the section table
+const HEADER_WORDS: usize = 13;
+
+/// Sections in blob order: vertex data, indices, draws, then per-meshlet data.
+/// Counts are in elements like `MeshMetadata`, commands count their u32 words.
+#[derive(Clone, Copy)]
+enum Section {
+    Vertices,
+    Normals,
+    Colors,
+    MaterialColors,
+    PackedVertices,
+    Indices,
+    Commands,
+    SplitFrom,
+    Bounds,
+    Densities,
+    VertexCounts,
+}
+
+const SECTIONS: [Section; 11] = [
+    Section::Vertices,
+    Section::Normals,
+    Section::Colors,
+    Section::MaterialColors,
+    Section::PackedVertices,
+    Section::Indices,
+    Section::Commands,
+    Section::SplitFrom,
+    Section::Bounds,
+    Section::Densities,
+    Section::VertexCounts,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlobError {
+    /// The data does not start with the blob magic
+    NotABlob,
+    /// The blob was written by an unknown version
+    UnsupportedVersion(u32),
+    /// The blob ends before its header or a section does
+    Truncated,
+    /// Header fields or sections contradict each other
+    Malformed(String),
+}
+
+impl fmt::Display for BlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlobError::NotABlob => write!(f, "not a mesh blob"),
+            BlobError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported mesh blob version {} (expected {})",
+                version, BLOB_VERSION
+            ),
+            BlobError::Truncated => write!(f, "mesh blob is truncated"),
+            BlobError::Malformed(message) => write!(f, "malformed mesh blob: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for BlobError {}
+
+/// Serializes a chunk into one little-endian blob, the layout JS sees in
+/// wasm memory, so typed arrays can view the sections without conversion.
+///
+/// Header, in u32 words: magic "OCMB", version, header bytes, total bytes,
+/// vertex format, index format, vertex count, draw count, position origin
+/// x, y, z and scale (f32), section count, then an (offset in bytes, count)
+/// pair per section.
+pub fn write_blob(chunk: &Chunk) -> Vec<u8> {
+    let sections = SECTIONS.map(|section| encode(chunk, section));
+    let header_bytes = align((HEADER_WORDS + 2 * SECTIONS.len()) * 4);
+
+    let mut table = Vec::with_capacity(2 * SECTIONS.len());
+    let mut offset = header_bytes;
+    for (bytes, count) in &sections {
+        table.extend([offset as u32, *count as u32]);
+        offset = align(offset + bytes.len());
+    }
+    let total_bytes = offset;
+
+    let origin = chunk.position_origin;
+    let header = [
+        MAGIC,
+        BLOB_VERSION,
+        header_bytes as u32,
+        total_bytes as u32,
+        chunk.vertex_format as u32,
+        chunk.index_format as u32,
+        chunk.vertex_count() as u32,
+        chunk.commands.len() as u32,
+        origin[0].to_bits(),
+        origin[1].to_bits(),
+        origin[2].to_bits(),
+        (1.0 / POSITION_STEPS).to_bits(),
+        SECTIONS.len() as u32,
+    ];
+
+    let mut blob = Vec::with_capacity(total_bytes);
+    blob.extend(header.iter().chain(&table).flat_map(|w| w.to_le_bytes()));
+    for (bytes, _) in &sections {
+        blob.resize(align(blob.len()), 0);
+        blob.extend_from_slice(bytes);
+    }
+    blob.resize(total_bytes, 0);
+    blob
+}
+
+/// Rebuilds a chunk from a blob, checking every section against the header
+/// so a stale or damaged cache file fails here instead of on the GPU
+pub fn read_blob(blob: &[u8]) -> Result<Chunk, BlobError> {
+    let word = |i: usize| -> Result<u32, BlobError> {
+        blob.get(i * 4..i * 4 + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or(BlobError::Truncated)
+    };
+    if word(0)? != MAGIC {
+        return Err(BlobError::NotABlob);
+    }
+    let version = word(1)?;
+    if version != BLOB_VERSION {
+        return Err(BlobError::UnsupportedVersion(version));
+    }
+    if (word(3)? as usize) > blob.len() {
+        return Err(BlobError::Truncated);
+    }
+    if word(12)? as usize != SECTIONS.len() {
+        return Err(BlobError::Malformed(format!(
+            "expected {} sections, found {}",
+            SECTIONS.len(),
+            word(12)?
+        )));
+    }
+
+    let vertex_format = match word(4)? {
+        0 => VertexFormat::Float,
+        1 => VertexFormat::Packed,
+        other => return Err(BlobError::Malformed(format!("vertex format {}", other))),
+    };
+    let index_format = match word(5)? {
+        0 => IndexFormat::Uint16,
+        1 => IndexFormat::Uint32,
+        other => return Err(BlobError::Malformed(format!("index format {}", other))),
+    };
+
+    let section = |section: Section, element_bytes: usize| -> Result<&[u8], BlobError> {
+        let entry = HEADER_WORDS + 2 * section as usize;
+        let (offset, count) = (word(entry)? as usize, word(entry + 1)? as usize);
+        if offset % ALIGN != 0 {
+            return Err(BlobError::Malformed("unaligned section".to_string()));
+        }
+        count
+            .checked_mul(element_bytes)
+            .and_then(|bytes| blob.get(offset..offset.checked_add(bytes)?))
+            .ok_or(BlobError::Truncated)
+    };
+    let u32s = |s: Section| -> Result<Vec<u32>, BlobError> {
+        Ok(section(s, 4)?
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    };
+    let f32s = |s: Section| -> Result<Vec<f32>, BlobError> {
+        Ok(u32s(s)?.into_iter().map(f32::from_bits).collect())
+    };
+
+    let mut chunk = Chunk {
+        densities: u32s(Section::Densities)?,
+        vertex_counts: u32s(Section::VertexCounts)?,
+        commands: u32s(Section::Commands)?
+            .chunks(5)
+            .map(|w| w.try_into().map(Command::from_words))
+            .collect::<Result<_, _>>()
+            .map_err(|_| BlobError::Malformed("partial draw command".to_string()))?,
+        split_from: u32s(Section::SplitFrom)?,
+        bounds: f32s(Section::Bounds)?,
+        vertex_format,
+        position_origin: [
+            f32::from_bits(word(8)?),
+            f32::from_bits(word(9)?),
+            f32::from_bits(word(10)?),
+        ],
+        vertices: f32s(Section::Vertices)?,
+        normals: f32s(Section::Normals)?,
+        material_colors: u32s(Section::MaterialColors)?,
+        colors: u32s(Section::Colors)?,
+        packed_vertices: u32s(Section::PackedVertices)?,
+        index_format,
+        indices: Vec::new(),
+        indices_u32: Vec::new(),
+    };
+    match index_format {
+        IndexFormat::Uint16 => {
+            chunk.indices = section(Section::Indices, 2)?
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect()
+        }
+        IndexFormat::Uint32 => chunk.indices_u32 = u32s(Section::Indices)?,
+    }
+
+    validate(&chunk, word(6)? as usize, word(7)? as usize)?;
+    Ok(chunk)
+}
+
+fn validate(chunk: &Chunk, vertex_count: usize, draws: usize) -> Result<(), BlobError> {
+    let malformed = |message: &str| Err(BlobError::Malformed(message.to_string()));
+
+    // Vertices are either all float or all packed
+    let (float, packed) = match chunk.vertex_format {
+        VertexFormat::Float => (vertex_count, 0),
+        VertexFormat::Packed => (0, vertex_count),
+    };
+    if chunk.vertices.len() != float * 4
+        || chunk.normals.len() != float * 4
+        || chunk.colors.len() != float
+        || chunk.material_colors.len() != float
+        || chunk.packed_vertices.len() != packed * PACKED_WORDS
+    {
+        return malformed("vertex sections disagree with the vertex count");
+    }
+    if chunk.commands.len() != draws
+        || chunk.vertex_counts.len() != draws
+        || chunk.bounds.len() != draws * BOUNDS_STRIDE
+        || chunk.split_from.len() > draws
+        || chunk
+            .split_from
+            .iter()
+            .any(|owner| *owner as usize >= draws)
+    {
+        return malformed("draw sections disagree with the draw count");
+    }
+
+    let index_count = match chunk.index_format {
+        IndexFormat::Uint16 => chunk.indices.len(),
+        IndexFormat::Uint32 => chunk.indices_u32.len(),
+    };
+    for (command, vertices) in chunk.commands.iter().zip(&chunk.vertex_counts) {
+        let [count, _, first, base, _] = command.to_words();
+        if first as usize + count as usize > index_count
+            || base as usize + *vertices as usize > vertex_count
+        {
+            return malformed("draw command outside the index or vertex data");
+        }
+    }
+    Ok(())
+}
+
+// Little-endian bytes of a section and its element count
+fn encode(chunk: &Chunk, section: Section) -> (Vec<u8>, usize) {
+    fn words(values: impl Iterator<Item = u32>) -> Vec<u8> {
+        values.flat_map(u32::to_le_bytes).collect()
+    }
+    let floats = |values: &[f32]| words(values.iter().map(|v| v.to_bits()));
+
+    match section {
+        Section::Vertices => (floats(&chunk.vertices), chunk.vertices.len()),
+        Section::Normals => (floats(&chunk.normals), chunk.normals.len()),
+        Section::Colors => (words(chunk.colors.iter().copied()), chunk.colors.len()),
+        Section::MaterialColors => (
+            words(chunk.material_colors.iter().copied()),
+            chunk.material_colors.len(),
+        ),
+        Section::PackedVertices => (
+            words(chunk.packed_vertices.iter().copied()),
+            chunk.packed_vertices.len(),
+        ),
+        Section::Indices => match chunk.index_format {
+            IndexFormat::Uint16 => (
+                chunk.indices.iter().flat_map(|i| i.to_le_bytes()).collect(),
+                chunk.indices.len(),
+            ),
+            IndexFormat::Uint32 => (
+                words(chunk.indices_u32.iter().copied()),
+                chunk.indices_u32.len(),
+            ),
+        },
+        Section::Commands => (
+            words(chunk.commands.iter().flat_map(Command::to_words)),
+            chunk.commands.len() * 5,
+        ),
+        Section::SplitFrom => (
+            words(chunk.split_from.iter().copied()),
+            chunk.split_from.len(),
+        ),
+        Section::Bounds => (floats(&chunk.bounds), chunk.bounds.len()),
+        Section::Densities => (
+            words(chunk.densities.iter().copied()),
+            chunk.densities.len(),
+        ),
+        Section::VertexCounts => (
+            words(chunk.vertex_counts.iter().copied()),
+            chunk.vertex_counts.len(),
+        ),
+    }
+}
+
+fn align(offset: usize) -> usize {
+    offset.div_ceil(ALIGN) * ALIGN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{default_generator, mesh_chunk, MeshOptions};
+
+    fn chunk(index_format: IndexFormat, vertex_format: VertexFormat) -> Chunk {
+        let options = MeshOptions {
+            index_format,
+            vertex_format,
+            ..MeshOptions::default()
+        };
+        mesh_chunk(&default_generator(), 0, 0, 0, 2, options)
+    }
+
+    #[test]
+    fn round_trip() {
+        for index_format in [IndexFormat::Uint16, IndexFormat::Uint32] {
+            for vertex_format in [VertexFormat::Float, VertexFormat::Packed] {
+                let chunk = chunk(index_format, vertex_format);
+                assert!(chunk.vertex_count() > 0);
+                let blob = write_blob(&chunk);
+                assert_eq!(blob.len() % ALIGN, 0);
+                let read = read_blob(&blob).unwrap();
+                assert_eq!(write_blob(&read), blob);
+                assert_eq!(read.vertices, chunk.vertices);
+                assert_eq!(read.packed_vertices, chunk.packed_vertices);
+                assert_eq!(read.indices, chunk.indices);
+                assert_eq!(read.indices_u32, chunk.indices_u32);
+                assert_eq!(read.commands, chunk.commands);
+            }
+        }
+    }
+
+    #[test]
+    fn damaged_blobs_are_rejected() {
+        let blob = write_blob(&chunk(IndexFormat::Uint16, VertexFormat::Float));
+        for length in [0, 7, HEADER_WORDS * 4, blob.len() - 1] {
+            assert_eq!(read_blob(&blob[..length]).err(), Some(BlobError::Truncated));
+        }
+
+        let mut wrong_magic = blob.clone();
+        wrong_magic[0] ^= 1;
+        assert_eq!(read_blob(&wrong_magic).err(), Some(BlobError::NotABlob));
+
+        let mut wrong_version = blob.clone();
+        wrong_version[4..8].copy_from_slice(&(BLOB_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            read_blob(&wrong_version).err(),
+            Some(BlobError::UnsupportedVersion(BLOB_VERSION + 1))
+        );
+
+        // One more vertex than the sections hold
+        let mut wrong_count = blob.clone();
+        let vertices = u32::from_le_bytes(blob[24..28].try_into().unwrap());
+        wrong_count[24..28].copy_from_slice(&(vertices + 1).to_le_bytes());
+        assert!(matches!(
+            read_blob(&wrong_count),
+            Err(BlobError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,136 @@
+/// Floats per draw in the bounds buffer: four vec4s, so WGSL can read it as
+/// `array<MeshletBounds>` without padding surprises
+pub const BOUNDS_STRIDE: usize = 16;
+
+/// Culling volumes of one draw, computed from its triangles in world space.
+///
+/// Layout per draw: `aabb_min.xyz, 0`, `aabb_max.xyz, 0`, `center.xyz, radius`,
+/// `axis.xyz, cutoff`. The axis is the average outward facing direction of the
+/// triangles and `cutoff` the sine of the widest angle between it and any of
+/// them. Every triangle faces away from the camera when
+/// `dot(center - camera, axis) >= cutoff * length(center - camera) + radius`.
+/// A cutoff of 1 means the triangles face too many ways for cone culling.
+pub(crate) struct MeshletBounds {
+    min: [f32; 3],
+    max: [f32; 3],
+    center: [f32; 3],
+    radius: f32,
+    axis: [f32; 3],
+    cutoff: f32,
+}
+
+impl MeshletBounds {
+    /// Bounds of a draw without triangles, an empty box that is never cone culled
+    pub(crate) fn empty() -> MeshletBounds {
+        MeshletBounds {
+            min: [0.0; 3],
+            max: [0.0; 3],
+            center: [0.0; 3],
+            radius: 0.0,
+            axis: [0.0; 3],
+            cutoff: 1.0,
+        }
+    }
+
+    pub(crate) fn from_triangles(positions: &[[f32; 3]], indices: &[u32]) -> MeshletBounds {
+        if indices.is_empty() {
+            return MeshletBounds::empty();
+        }
+
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for &i in indices {
+            let p = positions[i as usize];
+            for a in 0..3 {
+                min[a] = min[a].min(p[a]);
+                max[a] = max[a].max(p[a]);
+            }
+        }
+
+        // Sphere around the box center, tight enough for the small, compact meshlets
+        let center = [0, 1, 2].map(|a| (min[a] + max[a]) * 0.5);
+        let radius = indices
+            .iter()
+            .map(|i| distance(positions[*i as usize], center))
+            .fold(0.0f32, f32::max);
+
+        // Marching cubes winds triangles clockwise seen from the air side
+        let normals: Vec<[f32; 3]> = indices
+            .chunks_exact(3)
+            .filter_map(|t| {
+                let [a, b, c] = [t[0], t[1], t[2]].map(|i| positions[i as usize]);
+                normalize(cross(sub(c, a), sub(b, a)))
+            })
+            .collect();
+        let sum = normals
+            .iter()
+            .fold([0.0; 3], |s, n| [0, 1, 2].map(|a| s[a] + n[a]));
+        let (axis, cutoff) = match normalize(sum) {
+            Some(axis) => {
+                let min_dot = normals.iter().map(|n| dot(*n, axis)).fold(1.0f32, f32::min);
+                if min_dot <= 0.0 {
+                    (axis, 1.0)
+                } else {
+                    (axis, (1.0 - min_dot * min_dot).max(0.0).sqrt())
+                }
+            }
+            None => ([0.0; 3], 1.0),
+        };
+
+        MeshletBounds {
+            min,
+            max,
+            center,
+            radius,
+            axis,
+            cutoff,
+        }
+    }
+
+    pub(crate) fn to_floats(&self) -> [f32; BOUNDS_STRIDE] {
+        [
+            self.min[0],
+            self.min[1],
+            self.min[2],
+            0.0,
+            self.max[0],
+            self.max[1],
+            self.max[2],
+            0.0,
+            self.center[0],
+            self.center[1],
+            self.center[2],
+            self.radius,
+            self.axis[0],
+            self.axis[1],
+            self.axis[2],
+            self.cutoff,
+        ]
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    dot(sub(a, b), sub(a, b)).sqrt()
+}
+
+// None for degenerate (zero area) triangles and cancelling normals
+fn normalize(v: [f32; 3]) -> Option<[f32; 3]> {
+    let length = dot(v, v).sqrt();
+    (length > 1e-12).then(|| v.map(|c| c / length))
+}
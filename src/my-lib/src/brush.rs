@@ -0,0 +1,342 @@
+use crate::graph::{add, dot, length, normalize_or_up, scale, sdf_box, sub, DensityGraph};
+use crate::noise::{Density, SIZE};
+
+/// Largest averaging radius of a smooth brush
+pub const MAX_SMOOTH_RADIUS: f32 = 32.0;
+
+/// Largest size of a brush's box along any axis, four chunks
+pub const MAX_BRUSH_EXTENT: f32 = 4.0 * SIZE as f32;
+
+/// Widest transition of a brush to the untouched terrain
+pub const MAX_BLEND: f32 = 64.0;
+
+/// Region a brush acts on, as an SDF in world space (negative inside)
+#[derive(Clone)]
+pub enum BrushShape {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+    Box {
+        center: [f32; 3],
+        half_extents: [f32; 3],
+    },
+    /// Flat-capped cylinder around the segment `a..b`
+    Cylinder {
+        a: [f32; 3],
+        b: [f32; 3],
+        radius: f32,
+    },
+    Capsule {
+        a: [f32; 3],
+        b: [f32; 3],
+        radius: f32,
+    },
+    /// Cone from a disc of `radius` at `base` to its apex at `tip`
+    Cone {
+        base: [f32; 3],
+        tip: [f32; 3],
+        radius: f32,
+    },
+    /// Any density graph, with a box that contains everything inside it
+    Sdf {
+        graph: DensityGraph,
+        min: [f32; 3],
+        max: [f32; 3],
+    },
+}
+
+/// What a brush does inside its shape. `blend` is the width of the
+/// transition to the untouched terrain, 0 gives a hard edge.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BrushOperation {
+    /// Smooth union: fills the shape with rock
+    Add { blend: f32 },
+    /// Smooth subtraction: carves the shape out of the terrain
+    Subtract { blend: f32 },
+    /// Moves the surface inside the shape onto the plane `dot(p, normal) = height`
+    Flatten {
+        normal: [f32; 3],
+        height: f32,
+        blend: f32,
+    },
+    /// Replaces the density inside the shape with its average over `radius`,
+    /// which rounds off ridges and fills small dents
+    Smooth { radius: f32, blend: f32 },
+    /// Recolours the surface inside the shape (0xAABBGGRR like the material
+    /// colours), leaving the density alone
+    Paint { color: u32, blend: f32 },
+}
+
+impl BrushShape {
+    pub(crate) fn sample(&self, p: [f32; 3]) -> (f32, [f32; 3]) {
+        match self {
+            BrushShape::Sphere { center, radius } => {
+                let d = sub(p, *center);
+                let length = length(d);
+                (length - radius, normalize_or_up(d, length))
+            }
+            BrushShape::Box {
+                center,
+                half_extents,
+            } => sdf_box(sub(p, *center), *half_extents),
+            BrushShape::Cylinder { a, b, radius } => {
+                let (y, rho, axis, radial) = around_axis(p, scale(add(*a, *b), 0.5), sub(*b, *a));
+                let half_height = length(sub(*b, *a)) * 0.5;
+                let (value, g) = sdf_rectangle([rho - radius, y.abs() - half_height]);
+                (
+                    value,
+                    add(scale(radial, g[0]), scale(axis, g[1] * y.signum())),
+                )
+            }
+            BrushShape::Capsule { a, b, radius } => {
+                let pa = sub(p, *a);
+                let ba = sub(*b, *a);
+                let h = (dot(pa, ba) / dot(ba, ba).max(1e-12)).clamp(0.0, 1.0);
+                let d = sub(pa, scale(ba, h));
+                let length = length(d);
+                (length - radius, normalize_or_up(d, length))
+            }
+            BrushShape::Cone { base, tip, radius } => {
+                let (y, rho, axis, radial) = around_axis(p, *base, sub(*tip, *base));
+                let height = length(sub(*tip, *base));
+                let (value, g) = sdf_cone([rho, y], *radius, height);
+                (value, add(scale(radial, g[0]), scale(axis, g[1])))
+            }
+            BrushShape::Sdf { graph, .. } => graph.sample(p),
+        }
+    }
+
+    /// Lower bound of the shape's SDF over the box `min..=max`
+    pub(crate) fn bounds_min(&self, min: [f32; 3], max: [f32; 3]) -> f32 {
+        match self {
+            BrushShape::Sphere { center, radius } => {
+                let nearest = [0, 1, 2].map(|axis| {
+                    let c = center[axis];
+                    c.clamp(min[axis], max[axis]) - c
+                });
+                length(nearest) - radius
+            }
+            BrushShape::Sdf { graph, .. } => graph.bounds(min, max).min,
+            // Exact SDFs change by at most the distance moved
+            _ => {
+                let center = scale(add(min, max), 0.5);
+                self.sample(center).0 - length(sub(max, min)) * 0.5
+            }
+        }
+    }
+
+    /// Axis-aligned box around the inside of the shape
+    pub fn aabb(&self) -> ([f32; 3], [f32; 3]) {
+        let around = |points: &[[f32; 3]], margin: f32| {
+            let mut min = [f32::INFINITY; 3];
+            let mut max = [f32::NEG_INFINITY; 3];
+            for p in points {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(p[axis] - margin);
+                    max[axis] = max[axis].max(p[axis] + margin);
+                }
+            }
+            (min, max)
+        };
+        match self {
+            BrushShape::Sphere { center, radius } => around(&[*center], *radius),
+            BrushShape::Box {
+                center,
+                half_extents,
+            } => (sub(*center, *half_extents), add(*center, *half_extents)),
+            BrushShape::Cylinder { a, b, radius } | BrushShape::Capsule { a, b, radius } => {
+                around(&[*a, *b], *radius)
+            }
+            BrushShape::Cone { base, tip, radius } => {
+                let (min, max) = around(&[*base], *radius);
+                let (tip_min, tip_max) = around(&[*tip], 0.0);
+                (
+                    [0, 1, 2].map(|axis| min[axis].min(tip_min[axis])),
+                    [0, 1, 2].map(|axis| max[axis].max(tip_max[axis])),
+                )
+            }
+            BrushShape::Sdf { min, max, .. } => (*min, *max),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let finite = |points: &[[f32; 3]]| points.iter().flatten().all(|v| v.is_finite());
+        let positive = |v: f32| v > 0.0 && v.is_finite();
+        match self {
+            BrushShape::Sphere { center, radius } => {
+                if !finite(&[*center]) {
+                    return Err("center must be finite".to_string());
+                }
+                if !positive(*radius) {
+                    return Err("radius must be positive".to_string());
+                }
+            }
+            BrushShape::Box {
+                center,
+                half_extents,
+            } => {
+                if !finite(&[*center]) {
+                    return Err("center must be finite".to_string());
+                }
+                if !half_extents.iter().all(|h| positive(*h)) {
+                    return Err("half extents must be positive".to_string());
+                }
+            }
+            BrushShape::Cylinder { a, b, radius }
+            | BrushShape::Capsule { a, b, radius }
+            | BrushShape::Cone {
+                base: a,
+                tip: b,
+                radius,
+            } => {
+                if !finite(&[*a, *b]) {
+                    return Err("end points must be finite".to_string());
+                }
+                if !positive(*radius) {
+                    return Err("radius must be positive".to_string());
+                }
+                let capsule = matches!(self, BrushShape::Capsule { .. });
+                if !capsule && a == b {
+                    return Err("end points must differ".to_string());
+                }
+            }
+            BrushShape::Sdf { min, max, .. } => {
+                if !finite(&[*min, *max]) {
+                    return Err("bounding box must be finite".to_string());
+                }
+                if (0..3).any(|axis| min[axis] > max[axis]) {
+                    return Err("bounding box min must not exceed max".to_string());
+                }
+            }
+        }
+        // Every chunk near a brush keeps a reference to it, so huge brushes are refused
+        let (min, max) = self.aabb();
+        if (0..3).any(|axis| max[axis] - min[axis] > MAX_BRUSH_EXTENT) {
+            return Err(format!(
+                "brush must fit in {} units along every axis",
+                MAX_BRUSH_EXTENT
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl BrushOperation {
+    /// Width of the transition to the untouched terrain
+    pub(crate) fn blend(&self) -> f32 {
+        match *self {
+            BrushOperation::Add { blend }
+            | BrushOperation::Subtract { blend }
+            | BrushOperation::Flatten { blend, .. }
+            | BrushOperation::Smooth { blend, .. }
+            | BrushOperation::Paint { blend, .. } => blend,
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let blend = self.blend();
+        if !(blend >= 0.0 && blend.is_finite()) {
+            return Err("blend must not be negative".to_string());
+        }
+        if blend > MAX_BLEND {
+            return Err(format!("blend must be at most {}", MAX_BLEND));
+        }
+        match *self {
+            BrushOperation::Flatten { normal, height, .. } => {
+                if !normal.iter().all(|v| v.is_finite()) || length(normal) == 0.0 {
+                    return Err("plane normal must not be zero".to_string());
+                }
+                if !height.is_finite() {
+                    return Err("plane height must be finite".to_string());
+                }
+            }
+            BrushOperation::Smooth { radius, .. } => {
+                if !(radius > 0.0 && radius <= MAX_SMOOTH_RADIUS) {
+                    return Err(format!(
+                        "smooth radius must be positive and at most {}",
+                        MAX_SMOOTH_RADIUS
+                    ));
+                }
+            }
+            BrushOperation::Add { .. }
+            | BrushOperation::Subtract { .. }
+            | BrushOperation::Paint { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+// Splits p into its height along the axis through `origin` and its distance
+// from it, with the unit axis and the unit direction away from the axis
+fn around_axis(p: [f32; 3], origin: [f32; 3], axis: [f32; 3]) -> (f32, f32, [f32; 3], [f32; 3]) {
+    let axis = normalize_or_up(axis, length(axis));
+    let q = sub(p, origin);
+    let y = dot(q, axis);
+    let radial = sub(q, scale(axis, y));
+    let rho = length(radial);
+    let radial = if rho > 1e-6 {
+        scale(radial, 1.0 / rho)
+    } else {
+        // On the axis any perpendicular will do
+        let other = if axis[0].abs() < 0.9 {
+            [1.0, 0.0, 0.0]
+        } else {
+            [0.0, 1.0, 0.0]
+        };
+        let perpendicular = sub(other, scale(axis, dot(other, axis)));
+        scale(perpendicular, 1.0 / length(perpendicular))
+    };
+    (y, rho, axis, radial)
+}
+
+// 2D box SDF from the per-axis distances to the box faces
+fn sdf_rectangle(d: [f32; 2]) -> (f32, [f32; 2]) {
+    if d[0] > 0.0 || d[1] > 0.0 {
+        let outside = [d[0].max(0.0), d[1].max(0.0)];
+        let length = (outside[0] * outside[0] + outside[1] * outside[1]).sqrt();
+        (length, [outside[0] / length, outside[1] / length])
+    } else if d[0] > d[1] {
+        (d[0], [1.0, 0.0])
+    } else {
+        (d[1], [0.0, 1.0])
+    }
+}
+
+// 2D SDF of the cone's cross-section, the triangle (0, 0), (radius, 0),
+// (0, height), at `q` = (distance from the axis, height above the base)
+fn sdf_cone(q: [f32; 2], radius: f32, height: f32) -> (f32, [f32; 2]) {
+    let slant = (radius * radius + height * height).sqrt();
+    let (base, base_direction) = to_segment(q, [0.0, 0.0], [radius, 0.0], [0.0, -1.0]);
+    let (side, side_direction) = to_segment(
+        q,
+        [radius, 0.0],
+        [0.0, height],
+        [height / slant, radius / slant],
+    );
+    let (distance, direction) = if base < side {
+        (base, base_direction)
+    } else {
+        (side, side_direction)
+    };
+    if q[1] >= 0.0 && q[0] * height <= radius * (height - q[1]) {
+        (-distance, [-direction[0], -direction[1]])
+    } else {
+        (distance, direction)
+    }
+}
+
+// Distance from q to the segment a..b and the unit direction from the
+// closest point to q, `fallback` when q lies on the segment
+fn to_segment(q: [f32; 2], a: [f32; 2], b: [f32; 2], fallback: [f32; 2]) -> (f32, [f32; 2]) {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let aq = [q[0] - a[0], q[1] - a[1]];
+    let t = ((aq[0] * ab[0] + aq[1] * ab[1]) / (ab[0] * ab[0] + ab[1] * ab[1])).clamp(0.0, 1.0);
+    let d = [aq[0] - ab[0] * t, aq[1] - ab[1] * t];
+    let distance = (d[0] * d[0] + d[1] * d[1]).sqrt();
+    if distance > 1e-6 {
+        (distance, [d[0] / distance, d[1] / distance])
+    } else {
+        (0.0, fallback)
+    }
+}
@@ -0,0 +1,263 @@
+use std::io;
+use std::path::PathBuf;
+
+use rustc_hash::FxHashMap;
+
+use crate::lod_resolution;
+use crate::noise::Density;
+use crate::octree::{VoxelOctree, BRICK_SIZE};
+
+/// Current version of the cached voxel layout
+pub const VOXEL_CACHE_VERSION: u32 = 1;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"OCVC");
+
+/// Identifies the voxels of one chunk: its coordinate, LOD and the
+/// `generator_hash` of the world they were sampled from
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct VoxelKey {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub lod: u32,
+    pub generator: u64,
+}
+
+/// Where `VoxelCache` spills chunks it evicts and looks for ones it misses
+pub trait FieldStore {
+    fn save(&mut self, key: VoxelKey, bytes: Vec<u8>) -> io::Result<()>;
+
+    /// The bytes saved for `key`, None when there are none
+    fn load(&mut self, key: VoxelKey) -> Option<Vec<u8>>;
+}
+
+/// Keeps spilled chunks as one file each in a directory, created on the first save
+pub struct DirectoryStore {
+    path: PathBuf,
+}
+
+impl DirectoryStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn file(&self, key: VoxelKey) -> PathBuf {
+        self.path.join(format!(
+            "{}_{}_{}_{}_{:016x}.voxels",
+            key.x, key.y, key.z, key.lod, key.generator
+        ))
+    }
+}
+
+impl FieldStore for DirectoryStore {
+    fn save(&mut self, key: VoxelKey, bytes: Vec<u8>) -> io::Result<()> {
+        std::fs::create_dir_all(&self.path)?;
+        std::fs::write(self.file(key), bytes)
+    }
+
+    fn load(&mut self, key: VoxelKey) -> Option<Vec<u8>> {
+        std::fs::read(self.file(key)).ok()
+    }
+}
+
+struct Entry {
+    octree: VoxelOctree,
+    bytes: usize,
+    // Clock value of the last lookup
+    used: u64,
+    // Whether the store already has it, so evicting it writes nothing
+    stored: bool,
+}
+
+/// Sampled voxels of recently meshed chunks, so meshing a chunk again after
+/// a LOD change or when it comes back into view skips the density. Only the
+/// bricks crossing the surface are kept (see `VoxelOctree`), uniform space
+/// costs nothing.
+///
+/// Chunks are evicted least recently used first once their total size is
+/// over the budget, into the store when there is one. A missing chunk is
+/// looked up in the store, then derived from the same chunk at a finer
+/// cached LOD, and sampled only when neither has it.
+pub struct VoxelCache {
+    budget: usize,
+    used: usize,
+    clock: u64,
+    entries: FxHashMap<VoxelKey, Entry>,
+    store: Option<Box<dyn FieldStore>>,
+}
+
+impl VoxelCache {
+    /// Empty cache holding up to `budget` bytes of voxels
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            used: 0,
+            clock: 0,
+            entries: FxHashMap::default(),
+            store: None,
+        }
+    }
+
+    /// Spills evicted chunks to `store` and reads misses back from it
+    pub fn with_store(mut self, store: impl FieldStore + 'static) -> Self {
+        self.store = Some(Box::new(store));
+        self
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Changes the budget, evicting chunks until they fit
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict(None);
+    }
+
+    /// Bytes of voxels currently held in memory
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Number of chunks held in memory
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, key: VoxelKey) -> bool {
+        self.entries.contains_key(&key)
+    }
+
+    /// Drops every chunk held in memory without spilling it, e.g. after the
+    /// generator changed. The store keeps what it has.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = 0;
+    }
+
+    /// Octree of chunk `key`, which must be sampled from `density`
+    pub(crate) fn octree<D: Density + ?Sized>(
+        &mut self,
+        density: &D,
+        key: VoxelKey,
+    ) -> &VoxelOctree {
+        self.clock += 1;
+        if !self.entries.contains_key(&key) {
+            let (resolution, scale) = lod_resolution(key.lod);
+            let loaded = self
+                .store
+                .as_mut()
+                .and_then(|store| store.load(key))
+                .and_then(|bytes| read_voxels(&bytes, key));
+            let stored = loaded.is_some();
+            let octree = loaded
+                .or_else(|| {
+                    // The nearest finer LOD needs the fewest samples evaluated again
+                    let finer = (0..key.lod)
+                        .rev()
+                        .find_map(|lod| self.entries.get(&VoxelKey { lod, ..key }))?;
+                    Some(VoxelOctree::from_finer(
+                        density,
+                        &finer.octree,
+                        key.x,
+                        key.y,
+                        key.z,
+                        resolution,
+                        scale,
+                    ))
+                })
+                .unwrap_or_else(|| {
+                    VoxelOctree::from_density(density, key.x, key.y, key.z, resolution, scale)
+                });
+            let bytes = octree.bytes();
+            self.used += bytes;
+            self.entries.insert(
+                key,
+                Entry {
+                    octree,
+                    bytes,
+                    used: 0,
+                    stored,
+                },
+            );
+            // The new chunk stays even when it alone is over the budget
+            self.evict(Some(key));
+        }
+
+        let entry = self.entries.get_mut(&key).expect("inserted above");
+        entry.used = self.clock;
+        &entry.octree
+    }
+
+    // Evicts least recently used chunks other than `keep` until the rest fits the budget
+    fn evict(&mut self, keep: Option<VoxelKey>) {
+        while self.used > self.budget {
+            let Some(key) = self
+                .entries
+                .iter()
+                .filter(|(key, _)| Some(**key) != keep)
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| *key)
+            else {
+                return;
+            };
+            let entry = self.entries.remove(&key).expect("key was just found");
+            self.used -= entry.bytes;
+            if let (Some(store), false) = (&mut self.store, entry.stored) {
+                // A failed spill only costs sampling the chunk again
+                let _ = store.save(key, write_voxels(&entry.octree, key));
+            }
+        }
+    }
+}
+
+// Little-endian u32 words: magic "OCVC", version, x, y, z, lod, generator
+// hash (low, high), bricks per axis, then the octree's nodes (see
+// `VoxelOctree::write_words`)
+fn write_voxels(octree: &VoxelOctree, key: VoxelKey) -> Vec<u8> {
+    let mut words = vec![
+        MAGIC,
+        VOXEL_CACHE_VERSION,
+        key.x as u32,
+        key.y as u32,
+        key.z as u32,
+        key.lod,
+        key.generator as u32,
+        (key.generator >> 32) as u32,
+        octree.bricks_per_axis(),
+    ];
+    octree.write_words(&mut words);
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+// None unless the bytes are voxels of exactly `key` in the current layout,
+// anything else counts as a miss and is sampled again
+fn read_voxels(bytes: &[u8], key: VoxelKey) -> Option<VoxelOctree> {
+    let words = bytes.chunks_exact(4);
+    if !words.remainder().is_empty() {
+        return None;
+    }
+    let mut words = words.map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]));
+    let header = [
+        MAGIC,
+        VOXEL_CACHE_VERSION,
+        key.x as u32,
+        key.y as u32,
+        key.z as u32,
+        key.lod,
+        key.generator as u32,
+        (key.generator >> 32) as u32,
+        lod_resolution(key.lod).0 / BRICK_SIZE,
+    ];
+    for expected in header {
+        if words.next()? != expected {
+            return None;
+        }
+    }
+    let octree = VoxelOctree::read_words(&mut words, header[8])?;
+    words.next().is_none().then_some(octree)
+}
@@ -0,0 +1,270 @@
+use crate::interval::Region;
+use crate::mesh::{
+    calculate_terrain_color, gradient_to_normal, interpolate_normal, interpolate_vertex, Mesher,
+    Vertex, CUBE_VERTICES, EDGE_VERTICES,
+};
+use crate::noise::{noise_for_region, Density, SIZE};
+use crate::octree::BRICK_SIZE;
+
+// Cells per block edge: the brick's own cells plus the first layer of the next brick (or chunk)
+const BLOCK_CELLS: usize = BRICK_SIZE as usize + 1;
+const BLOCK_POINTS: usize = BLOCK_CELLS + 1;
+
+// Jacobi sweeps for the 3x3 QEF matrix, converges long before this
+const JACOBI_SWEEPS: usize = 6;
+
+// Eigenvalues below this fraction of the largest are treated as zero (flat or
+// edge-like cells), so the vertex stays at the mass point along those axes
+const QEF_CUTOFF: f32 = 0.02;
+
+/// Meshes one brick with Surface Nets or Dual Contouring and returns the
+/// number of its cells the surface passes through.
+///
+/// Dual methods place one vertex inside every cell the surface crosses and
+/// join the four cells around every crossed lattice edge with a quad. A brick
+/// owns the edges starting inside it whose quads reach into the cells on its
+/// positive sides, so it samples one extra layer of cells past its far faces.
+/// Neighbouring bricks and chunks at the same LOD compute those shared cell
+/// vertices from the same samples, which keeps the mesh closed between them.
+pub(crate) fn dual_brick<D: Density + ?Sized>(
+    density: &D,
+    chunk: [i32; 3],
+    brick: [u32; 3],
+    scale: f32,
+    mesher: Mesher,
+    triangles: &mut Vec<[Vertex; 3]>,
+) -> u32 {
+    let origin = brick.map(|b| b * BRICK_SIZE);
+    let step = scale as i32;
+    let chunk_world_pos = chunk.map(|c| c * SIZE);
+
+    // Owned edges all lie inside the brick's box, so the bounds decide whether any is crossed
+    let min = [0, 1, 2].map(|a| (origin[a] as i32 * step + chunk_world_pos[a]) as f32);
+    let max = min.map(|v| v + BRICK_SIZE as f32 * scale);
+    if density.bounds(min, max).region() != Region::Surface {
+        return 0;
+    }
+
+    let field = noise_for_region(
+        density,
+        chunk[0],
+        chunk[1],
+        chunk[2],
+        scale,
+        origin,
+        [BLOCK_POINTS as u32; 3],
+    );
+    let point = |p: [usize; 3]| (p[2] * BLOCK_POINTS + p[1]) * BLOCK_POINTS + p[0];
+    let world = |p: [usize; 3]| {
+        [0, 1, 2].map(|a| (origin[a] as i32 + p[a] as i32) * step + chunk_world_pos[a])
+    };
+
+    let normals: Vec<[f32; 3]> = field
+        .gradients
+        .iter()
+        .map(|g| gradient_to_normal(*g))
+        .collect();
+
+    let mut cells = Vec::with_capacity(BLOCK_CELLS.pow(3));
+    let mut surface_cells = 0;
+    for z in 0..BLOCK_CELLS {
+        for y in 0..BLOCK_CELLS {
+            for x in 0..BLOCK_CELLS {
+                let cell = [x, y, z];
+                let corners =
+                    CUBE_VERTICES.map(|c| point([0, 1, 2].map(|a| cell[a] + c[a] as usize)));
+                let values = corners.map(|i| field.densities[i]);
+                let solid = values.iter().filter(|v| **v < 0.0).count();
+                if solid == 0 || solid == 8 {
+                    cells.push(None);
+                    continue;
+                }
+
+                if cell.iter().all(|c| *c < BRICK_SIZE as usize) {
+                    surface_cells += 1;
+                }
+                cells.push(Some(cell_vertex(
+                    density,
+                    &values,
+                    &corners.map(|i| normals[i]),
+                    world(cell),
+                    step,
+                    mesher,
+                )));
+            }
+        }
+    }
+
+    let cell = |p: [usize; 3]| {
+        cells[(p[2] * BLOCK_CELLS + p[1]) * BLOCK_CELLS + p[0]]
+            .expect("cells around a crossed edge contain the surface")
+    };
+    let brick_size = BRICK_SIZE as usize;
+    for axis in 0..3 {
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        for u in 1..=brick_size {
+            for v in 1..=brick_size {
+                for w in 0..brick_size {
+                    let mut start = [0; 3];
+                    start[axis] = w;
+                    start[a] = u;
+                    start[b] = v;
+                    let mut end = start;
+                    end[axis] += 1;
+
+                    let solid_start = field.densities[point(start)] < 0.0;
+                    if solid_start == (field.densities[point(end)] < 0.0) {
+                        continue;
+                    }
+
+                    // The four cells sharing the edge, counter-clockwise around the axis
+                    let mut quad = [start; 4];
+                    quad[1][a] -= 1;
+                    quad[2][a] -= 1;
+                    quad[2][b] -= 1;
+                    quad[3][b] -= 1;
+                    let mut quad = quad.map(cell);
+                    // Same winding as marching cubes: clockwise seen from the air side
+                    if solid_start {
+                        quad.reverse();
+                    }
+                    triangles.push([quad[0], quad[1], quad[2]]);
+                    triangles.push([quad[0], quad[2], quad[3]]);
+                }
+            }
+        }
+    }
+
+    surface_cells
+}
+
+// Vertex of a cell the surface passes through. `corner` is the cell's lowest
+// sample in world units, `step` the sample spacing.
+fn cell_vertex<D: Density + ?Sized>(
+    density: &D,
+    values: &[f32; 8],
+    normals: &[[f32; 3]; 8],
+    corner: [i32; 3],
+    step: i32,
+    mesher: Mesher,
+) -> Vertex {
+    let position =
+        |i: usize| [0, 1, 2].map(|a| (corner[a] + CUBE_VERTICES[i][a] as i32 * step) as f32);
+
+    let mut points = Vec::with_capacity(12);
+    let mut planes = Vec::with_capacity(12);
+    for [v1, v2] in EDGE_VERTICES {
+        if (values[v1] < 0.0) != (values[v2] < 0.0) {
+            let point = interpolate_vertex(position(v1), position(v2), values[v1], values[v2]);
+            match mesher {
+                // Interpolated corner gradients smear across sharp features, the tangent
+                // planes need the gradient at the crossing, after one Newton step onto the surface
+                Mesher::DualContouring => {
+                    let (value, gradient) = density.sample(point);
+                    let length_sq = gradient.iter().map(|g| g * g).sum::<f32>();
+                    let point = if length_sq > 1e-8 {
+                        [0, 1, 2].map(|a| point[a] - value * gradient[a] / length_sq)
+                    } else {
+                        point
+                    };
+                    points.push(point);
+                    planes.push(gradient_to_normal(gradient));
+                }
+                _ => {
+                    points.push(point);
+                    planes.push(interpolate_normal(
+                        normals[v1],
+                        normals[v2],
+                        values[v1],
+                        values[v2],
+                    ));
+                }
+            }
+        }
+    }
+
+    let count = points.len() as f32;
+    let mass = [0, 1, 2].map(|a| points.iter().map(|p| p[a]).sum::<f32>() / count);
+    let position = match mesher {
+        Mesher::DualContouring => {
+            // Keep the vertex inside its cell, QEF minimizers can land far away on near-parallel planes
+            let (cell_min, cell_max) = (position(0), position(6));
+            let vertex = solve_qef(&points, &planes, mass);
+            [0, 1, 2].map(|a| vertex[a].clamp(cell_min[a], cell_max[a]))
+        }
+        _ => mass,
+    };
+
+    let sum = [0, 1, 2].map(|a| planes.iter().map(|n| n[a]).sum::<f32>());
+    let length = (sum[0] * sum[0] + sum[1] * sum[1] + sum[2] * sum[2]).sqrt();
+    let normal = if length > 0.0001 {
+        sum.map(|n| n / length)
+    } else {
+        planes[0]
+    };
+
+    Vertex {
+        // Dual vertices sit inside cells, identified by the cell's lowest sample
+        edge: (corner, corner),
+        position,
+        normal,
+        color: calculate_terrain_color(position, normal),
+    }
+}
+
+/// Point minimizing the squared distance to the tangent planes `(point, normal)`.
+/// Solved around the mass point with a truncated pseudo-inverse, so directions the
+/// planes do not constrain (flat areas, along a ridge) fall back to the mass point.
+fn solve_qef(points: &[[f32; 3]], normals: &[[f32; 3]], mass: [f32; 3]) -> [f32; 3] {
+    let mut ata = [[0.0f32; 3]; 3];
+    let mut atb = [0.0f32; 3];
+    for (p, n) in points.iter().zip(normals) {
+        let d = n[0] * (p[0] - mass[0]) + n[1] * (p[1] - mass[1]) + n[2] * (p[2] - mass[2]);
+        for i in 0..3 {
+            for j in 0..3 {
+                ata[i][j] += n[i] * n[j];
+            }
+            atb[i] += n[i] * d;
+        }
+    }
+
+    let (eigenvalues, eigenvectors) = symmetric_eigen(ata);
+    let largest = eigenvalues.iter().fold(0.0f32, |m, e| m.max(e.abs()));
+    let mut offset = [0.0f32; 3];
+    for k in 0..3 {
+        if eigenvalues[k].abs() <= largest * QEF_CUTOFF {
+            continue;
+        }
+        let v = [eigenvectors[0][k], eigenvectors[1][k], eigenvectors[2][k]];
+        let projection = (v[0] * atb[0] + v[1] * atb[1] + v[2] * atb[2]) / eigenvalues[k];
+        for i in 0..3 {
+            offset[i] += v[i] * projection;
+        }
+    }
+    [0, 1, 2].map(|i| mass[i] + offset[i])
+}
+
+// Jacobi eigen decomposition of a symmetric 3x3 matrix, eigenvectors are the columns
+fn symmetric_eigen(mut a: [[f32; 3]; 3]) -> ([f32; 3], [[f32; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..JACOBI_SWEEPS {
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-9 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for row in a.iter_mut().chain(v.iter_mut()) {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            a[p] = [0, 1, 2].map(|k| c * row_p[k] - s * row_q[k]);
+            a[q] = [0, 1, 2].map(|k| s * row_p[k] + c * row_q[k]);
+        }
+    }
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
@@ -0,0 +1,397 @@
+use std::fmt;
+
+use rustc_hash::FxHashMap;
+
+use crate::brush::{BrushOperation, BrushShape, MAX_SMOOTH_RADIUS};
+use crate::graph::{add, dot, length, negate, scale, smooth_min};
+use crate::interval::Interval;
+use crate::noise::{Density, DensityField, SIZE};
+
+// Voxel size at the coarsest LOD. Unions and subtractions act on points this
+// far beyond their shape, so the values along surface-crossing cell edges see
+// them at every LOD, and a chunk's samples (transition seams included) stay
+// this close to its box.
+const REACH: f32 = 32.0;
+
+// Most chunks one edit may be listed for. Brushes are at most
+// MAX_BRUSH_EXTENT wide, which with the margins stays well below this.
+const MAX_EDIT_CHUNKS: i64 = 1024;
+
+// Sample offsets of a smooth edit, in units of its radius
+const SMOOTH_KERNEL: [[f32; 3]; 6] = [
+    [-1.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, 0.0, -1.0],
+    [0.0, 0.0, 1.0],
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EditError {
+    /// The edit has a parameter outside its valid range
+    InvalidParameter(String),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::InvalidParameter(message) => write!(f, "invalid edit: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// One brush stroke
+#[derive(Clone)]
+pub struct Edit {
+    pub shape: BrushShape,
+    pub operation: BrushOperation,
+}
+
+impl Edit {
+    pub fn new(shape: BrushShape, operation: BrushOperation) -> Edit {
+        Edit { shape, operation }
+    }
+
+    fn validate(&self) -> Result<(), EditError> {
+        self.shape
+            .validate()
+            .and_then(|_| self.operation.validate())
+            .map_err(EditError::InvalidParameter)?;
+        // Checked before any chunk is listed, far away brushes round to huge boxes
+        let (first, last) = chunk_range(self, REACH + MAX_SMOOTH_RADIUS);
+        let count: i64 = (0..3)
+            .map(|axis| last[axis] as i64 - first[axis] as i64 + 1)
+            .product();
+        if count > MAX_EDIT_CHUNKS {
+            return Err(EditError::InvalidParameter(format!(
+                "edit reaches {} chunks, at most {} are allowed",
+                count, MAX_EDIT_CHUNKS
+            )));
+        }
+        Ok(())
+    }
+
+    /// Box outside of which the edit leaves the terrain untouched
+    pub fn reach(&self) -> ([f32; 3], [f32; 3]) {
+        let (min, max) = self.shape.aabb();
+        // Unions and subtractions lower or raise the density outside the shape too
+        let margin = match self.operation {
+            BrushOperation::Add { blend } | BrushOperation::Subtract { blend } => REACH + blend,
+            _ => 0.0,
+        };
+        (min.map(|v| v - margin), max.map(|v| v + margin))
+    }
+
+    fn contains(&self, p: [f32; 3]) -> bool {
+        let (min, max) = self.reach();
+        (0..3).all(|axis| p[axis] >= min[axis] && p[axis] <= max[axis])
+    }
+
+    fn overlaps(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        let (lo, hi) = self.reach();
+        (0..3).all(|axis| max[axis] >= lo[axis] && min[axis] <= hi[axis])
+    }
+
+    /// How strongly the edit acts at `p` (1 deep inside the shape, 0 outside)
+    /// and the gradient of that weight
+    fn weight(&self, p: [f32; 3]) -> (f32, [f32; 3]) {
+        let (shape, gradient) = self.shape.sample(p);
+        let blend = self.operation.blend();
+        if blend <= 0.0 {
+            (if shape < 0.0 { 1.0 } else { 0.0 }, [0.0; 3])
+        } else if shape <= -blend {
+            (1.0, [0.0; 3])
+        } else if shape >= 0.0 {
+            (0.0, [0.0; 3])
+        } else {
+            (-shape / blend, scale(gradient, -1.0 / blend))
+        }
+    }
+
+    /// The density at `p` after this edit, given the density before it.
+    /// `smoothed` is the kernel average a smooth edit moves towards.
+    fn apply(
+        &self,
+        p: [f32; 3],
+        density: (f32, [f32; 3]),
+        smoothed: impl FnOnce() -> (f32, [f32; 3]),
+    ) -> (f32, [f32; 3]) {
+        if !self.contains(p) {
+            return density;
+        }
+        match self.operation {
+            BrushOperation::Add { blend } => smooth_min(density, self.shape.sample(p), blend),
+            BrushOperation::Subtract { blend } => {
+                negate(smooth_min(negate(density), self.shape.sample(p), blend))
+            }
+            BrushOperation::Flatten { normal, height, .. } => {
+                let normal = scale(normal, 1.0 / length(normal));
+                let plane = (dot(p, normal) - height, normal);
+                lerp(density, plane, self.weight(p))
+            }
+            BrushOperation::Smooth { .. } => {
+                let weight = self.weight(p);
+                if weight.0 > 0.0 {
+                    lerp(density, smoothed(), weight)
+                } else {
+                    density
+                }
+            }
+            BrushOperation::Paint { .. } => density,
+        }
+    }
+
+    /// Conservative range after this edit over `min..=max`, given the range
+    /// before it. `smoothed` is the range of a smooth edit's kernel samples.
+    fn apply_bounds(
+        &self,
+        min: [f32; 3],
+        max: [f32; 3],
+        density: Interval,
+        smoothed: impl FnOnce() -> Interval,
+    ) -> Interval {
+        if !self.overlaps(min, max) {
+            return density;
+        }
+        // Part of the box may lie outside the edit's reach and keep its old values
+        let hull =
+            |other: Interval| Interval::new(density.min.min(other.min), density.max.max(other.max));
+        match self.operation {
+            BrushOperation::Add { blend } => {
+                let shape = self.shape.bounds_min(min, max);
+                Interval::new(density.min.min(shape) - blend * 0.25, density.max)
+            }
+            BrushOperation::Subtract { blend } => {
+                let shape = self.shape.bounds_min(min, max);
+                Interval::new(density.min, density.max.max(-shape) + blend * 0.25)
+            }
+            BrushOperation::Flatten { normal, height, .. } => {
+                let normal = scale(normal, 1.0 / length(normal));
+                let mut plane = Interval::point(-height);
+                for axis in 0..3 {
+                    plane = plane + Interval::new(min[axis], max[axis]).scale(normal[axis]);
+                }
+                hull(plane)
+            }
+            BrushOperation::Smooth { .. } => hull(smoothed()),
+            BrushOperation::Paint { .. } => density,
+        }
+    }
+
+    /// Surface colour at `p` after this edit, given the colour before it
+    fn paint(&self, p: [f32; 3], color: u32) -> u32 {
+        let BrushOperation::Paint { color: paint, .. } = self.operation else {
+            return color;
+        };
+        if !self.contains(p) {
+            return color;
+        }
+        let weight = self.weight(p).0;
+        let (from, to) = (color.to_le_bytes(), paint.to_le_bytes());
+        u32::from_le_bytes(
+            [0, 1, 2, 3].map(|i| (from[i] as f32 + (to[i] as f32 - from[i] as f32) * weight) as u8),
+        )
+    }
+}
+
+// Blends from a to b by t, all with their gradients
+fn lerp(a: (f32, [f32; 3]), b: (f32, [f32; 3]), t: (f32, [f32; 3])) -> (f32, [f32; 3]) {
+    let value = a.0 + (b.0 - a.0) * t.0;
+    let gradient = add(
+        add(scale(a.1, 1.0 - t.0), scale(b.1, t.0)),
+        scale(t.1, b.0 - a.0),
+    );
+    (value, gradient)
+}
+
+/// Every edit made to the world in order, indexed by the chunks each one can
+/// change. Meshing a chunk only has to look at its own list.
+#[derive(Clone, Default)]
+pub struct EditLayer {
+    edits: Vec<Edit>,
+    chunks: FxHashMap<[i32; 3], Vec<u32>>,
+}
+
+impl EditLayer {
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Validates and records an edit, returning the chunks whose meshes it changes
+    pub fn push(&mut self, edit: Edit) -> Result<Vec<[i32; 3]>, EditError> {
+        edit.validate()?;
+        let id = self.edits.len() as u32;
+        for chunk in chunks_near(&edit, REACH + MAX_SMOOTH_RADIUS) {
+            self.chunks.entry(chunk).or_default().push(id);
+        }
+        let affected = chunks_near(&edit, REACH).collect();
+        self.edits.push(edit);
+        Ok(affected)
+    }
+
+    /// Removes the latest edit, returning it and the chunks whose meshes it changed
+    pub fn pop(&mut self) -> Option<(Edit, Vec<[i32; 3]>)> {
+        let edit = self.edits.pop()?;
+        for chunk in chunks_near(&edit, REACH + MAX_SMOOTH_RADIUS) {
+            if let Some(ids) = self.chunks.get_mut(&chunk) {
+                ids.pop();
+                if ids.is_empty() {
+                    self.chunks.remove(&chunk);
+                }
+            }
+        }
+        let affected = chunks_near(&edit, REACH).collect();
+        Some((edit, affected))
+    }
+
+    /// Drops every edit and returns the chunks that had any
+    pub fn clear(&mut self) -> Vec<[i32; 3]> {
+        let affected = self.edited_chunks();
+        self.edits.clear();
+        self.chunks.clear();
+        affected
+    }
+
+    /// Chunks that have any edit, sorted
+    pub fn edited_chunks(&self) -> Vec<[i32; 3]> {
+        let mut chunks: Vec<[i32; 3]> = self.chunks.keys().copied().collect();
+        chunks.sort_unstable();
+        chunks
+    }
+
+    /// Edits that can change chunk `(x, y, z)`, in the order they were made
+    pub fn chunk_edits(&self, x: i32, y: i32, z: i32) -> impl Iterator<Item = &Edit> {
+        self.chunks
+            .get(&[x, y, z])
+            .into_iter()
+            .flatten()
+            .map(|id| &self.edits[*id as usize])
+    }
+}
+
+// Chunks whose box grown by `margin` overlaps the edit's reach. Chunks
+// sampling within REACH of an edit are remeshed, edits are listed further
+// out for smooth edits whose kernel samples reach that far.
+fn chunks_near(edit: &Edit, margin: f32) -> impl Iterator<Item = [i32; 3]> {
+    let (first, last) = chunk_range(edit, margin);
+    (first[2]..=last[2]).flat_map(move |z| {
+        (first[1]..=last[1]).flat_map(move |y| (first[0]..=last[0]).map(move |x| [x, y, z]))
+    })
+}
+
+// First and last chunk of `chunks_near` on each axis
+fn chunk_range(edit: &Edit, margin: f32) -> ([i32; 3], [i32; 3]) {
+    let (lo, hi) = edit.reach();
+    let first = lo.map(|v| (((v - margin) / SIZE as f32).ceil() as i32).saturating_sub(1));
+    let last = hi.map(|v| ((v + margin) / SIZE as f32).floor() as i32);
+    (first, last)
+}
+
+/// A density with the edits of one chunk applied on top. Samples outside the
+/// chunk's reach may miss edits, so build one per chunk.
+pub struct Edited<'a, D: ?Sized> {
+    base: &'a D,
+    edits: Vec<&'a Edit>,
+}
+
+impl<'a, D: Density + ?Sized> Edited<'a, D> {
+    pub fn new(base: &'a D, layer: &'a EditLayer, x: i32, y: i32, z: i32) -> Self {
+        Edited {
+            base,
+            edits: layer.chunk_edits(x, y, z).collect(),
+        }
+    }
+
+    // Applies edit `index` at `p`. The kernel of a smooth edit samples the
+    // edits before it with smooth edits left out, which keeps the cost linear
+    // in the number of edits.
+    fn apply(&self, index: usize, p: [f32; 3], density: (f32, [f32; 3])) -> (f32, [f32; 3]) {
+        let edit = self.edits[index];
+        edit.apply(p, density, || {
+            let BrushOperation::Smooth { radius, .. } = edit.operation else {
+                unreachable!("only smooth edits sample their surroundings")
+            };
+            let weight = 1.0 / SMOOTH_KERNEL.len() as f32;
+            SMOOTH_KERNEL.iter().fold((0.0, [0.0; 3]), |sum, offset| {
+                let (value, gradient) = self.sample_before(index, add(p, scale(*offset, radius)));
+                (sum.0 + value * weight, add(sum.1, scale(gradient, weight)))
+            })
+        })
+    }
+
+    // Density before edit `index`, without smooth edits
+    fn sample_before(&self, index: usize, p: [f32; 3]) -> (f32, [f32; 3]) {
+        self.edits[..index]
+            .iter()
+            .filter(|edit| !matches!(edit.operation, BrushOperation::Smooth { .. }))
+            .fold(self.base.sample(p), |density, edit| {
+                edit.apply(p, density, || unreachable!("smooth edits are skipped"))
+            })
+    }
+
+    // Range over `min..=max` after the first `count` edits, smooth edits
+    // included only with `smooth` like in `sample_before`
+    fn bounds_before(&self, count: usize, min: [f32; 3], max: [f32; 3], smooth: bool) -> Interval {
+        let mut range = self.base.bounds(min, max);
+        for (index, edit) in self.edits[..count].iter().enumerate() {
+            if !smooth && matches!(edit.operation, BrushOperation::Smooth { .. }) {
+                continue;
+            }
+            range = edit.apply_bounds(min, max, range, || {
+                let BrushOperation::Smooth { radius, .. } = edit.operation else {
+                    unreachable!("only smooth edits sample their surroundings")
+                };
+                let (lo, hi) = (min.map(|v| v - radius), max.map(|v| v + radius));
+                self.bounds_before(index, lo, hi, false)
+            });
+        }
+        range
+    }
+}
+
+impl<D: Density + ?Sized> Density for Edited<'_, D> {
+    fn sample(&self, pos: [f32; 3]) -> (f32, [f32; 3]) {
+        (0..self.edits.len()).fold(self.base.sample(pos), |density, index| {
+            self.apply(index, pos, density)
+        })
+    }
+
+    fn bounds(&self, min: [f32; 3], max: [f32; 3]) -> Interval {
+        self.bounds_before(self.edits.len(), min, max, true)
+    }
+
+    fn sample_row(&self, xs: &[f32], y: f32, z: f32, field: &mut DensityField) {
+        // The base keeps its fast row path, edits are applied afterwards
+        let start = field.densities.len();
+        self.base.sample_row(xs, y, z, field);
+        let (Some(first), Some(last)) = (xs.first(), xs.last()) else {
+            return;
+        };
+        for (index, edit) in self.edits.iter().enumerate() {
+            if !edit.overlaps([*first, y, z], [*last, y, z]) {
+                continue;
+            }
+            for (i, x) in xs.iter().enumerate() {
+                let density = (field.densities[start + i], field.gradients[start + i]);
+                let (value, gradient) = self.apply(index, [*x, y, z], density);
+                field.densities[start + i] = value;
+                field.gradients[start + i] = gradient;
+            }
+        }
+    }
+
+    fn paint(&self, pos: [f32; 3], color: u32) -> u32 {
+        self.edits
+            .iter()
+            .fold(self.base.paint(pos, color), |color, edit| {
+                edit.paint(pos, color)
+            })
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use serde_json::json;
+
+use crate::mesh::{Chunk, IndexFormat, VertexFormat};
+use crate::packed::{unpack_vertex, PACKED_WORDS};
+
+const GLB_MAGIC: u32 = u32::from_le_bytes(*b"glTF");
+const GLB_JSON: u32 = u32::from_le_bytes(*b"JSON");
+const GLB_BIN: u32 = u32::from_le_bytes(*b"BIN\0");
+
+// glTF enums
+const FLOAT: u32 = 5126;
+const UNSIGNED_BYTE: u32 = 5121;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const TRIANGLES: u32 = 4;
+
+const SKIRT_FACES: [&str; 6] = ["-x", "+x", "-y", "+y", "-z", "+z"];
+
+/// A chunk's triangles the way external tools expect them: one float vertex
+/// list, counter-clockwise front faces and normals pointing out of the rock
+/// (the renderer culls the other way and its normals point into the rock).
+struct Surface {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    colors: Vec<u32>,
+    /// Named triangle lists with absolute vertex indices
+    groups: Vec<(String, Vec<u32>)>,
+}
+
+impl Surface {
+    /// With `per_meshlet` every meshlet (its split parts included) and every
+    /// skirt face is its own group, otherwise the chunk is one group
+    fn new(chunk: &Chunk, per_meshlet: bool) -> Surface {
+        let outward = |n: [f32; 3]| {
+            let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+            if length > 0.0 {
+                n.map(|c| -c / length)
+            } else {
+                [0.0, 1.0, 0.0]
+            }
+        };
+
+        let (positions, normals, colors) = match chunk.vertex_format() {
+            VertexFormat::Float => (
+                chunk
+                    .vertices()
+                    .chunks_exact(4)
+                    .map(|v| [v[0], v[1], v[2]])
+                    .collect(),
+                chunk
+                    .normals()
+                    .chunks_exact(4)
+                    .map(|n| outward([n[0], n[1], n[2]]))
+                    .collect(),
+                chunk.material_colors().to_vec(),
+            ),
+            VertexFormat::Packed => {
+                let origin = chunk.position_origin();
+                let mut positions = Vec::with_capacity(chunk.vertex_count());
+                let mut normals = Vec::with_capacity(chunk.vertex_count());
+                let mut colors = Vec::with_capacity(chunk.vertex_count());
+                for words in chunk.packed_vertices().chunks_exact(PACKED_WORDS) {
+                    let (position, normal, color) =
+                        unpack_vertex(origin, words.try_into().expect("exact chunks"));
+                    positions.push(position);
+                    normals.push(outward(normal));
+                    colors.push(color);
+                }
+                (positions, normals, colors)
+            }
+        };
+
+        // Split draws belong to the meshlet named in split_from
+        let commands = chunk.commands();
+        let base_draws = commands.len() - chunk.split_from().len();
+        let grid = chunk.density_len();
+        let mut groups: BTreeMap<usize, Vec<u32>> = BTreeMap::new();
+        for (draw, command) in commands.iter().enumerate() {
+            let owner = if draw < base_draws {
+                draw
+            } else {
+                chunk.split_from()[draw - base_draws] as usize
+            };
+            let group = groups
+                .entry(if per_meshlet { owner } else { 0 })
+                .or_default();
+
+            let range =
+                command.first_index as usize..(command.first_index + command.index_count) as usize;
+            let base = command.base_vertex as u32;
+            let local: Vec<u32> = match chunk.index_format() {
+                IndexFormat::Uint16 => chunk.indices()[range].iter().map(|i| *i as u32).collect(),
+                IndexFormat::Uint32 => chunk.indices_u32()[range].to_vec(),
+            };
+            for triangle in local.chunks_exact(3) {
+                group.extend([base + triangle[0], base + triangle[2], base + triangle[1]]);
+            }
+        }
+
+        let groups = groups
+            .into_iter()
+            .filter(|(_, indices)| !indices.is_empty())
+            .map(|(owner, indices)| {
+                let name = if !per_meshlet {
+                    "chunk".to_string()
+                } else if owner < grid {
+                    format!("meshlet_{}", owner)
+                } else {
+                    format!("skirt_{}", SKIRT_FACES[owner - grid])
+                };
+                (name, indices)
+            })
+            .collect();
+
+        Surface {
+            positions,
+            normals,
+            colors,
+            groups,
+        }
+    }
+}
+
+/// Binary glTF 2.0 of a chunk with positions, normals and material colours
+/// (`COLOR_0`). With `per_meshlet` each meshlet and skirt face is its own
+/// primitive, in the order of the chunk's draws, otherwise there is one.
+pub fn write_glb(chunk: &Chunk, per_meshlet: bool) -> Vec<u8> {
+    let surface = Surface::new(chunk, per_meshlet);
+
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "my-lib" },
+        "scene": 0,
+        "scenes": [{ "nodes": [] }],
+    });
+    let mut bin = Vec::new();
+    if !surface.groups.is_empty() {
+        let vertices = surface.positions.len();
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for p in &surface.positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+
+        let floats = |values: &[[f32; 3]]| -> Vec<u8> {
+            values
+                .iter()
+                .flatten()
+                .flat_map(|v| v.to_le_bytes())
+                .collect()
+        };
+        let mut views = Vec::new();
+        let mut push_view = |bytes: &[u8], target: u32| {
+            views.push(json!({
+                "buffer": 0,
+                "byteOffset": bin.len(),
+                "byteLength": bytes.len(),
+                "target": target,
+            }));
+            bin.extend_from_slice(bytes);
+            views.len() - 1
+        };
+        let positions = push_view(&floats(&surface.positions), ARRAY_BUFFER);
+        let normals = push_view(&floats(&surface.normals), ARRAY_BUFFER);
+        let color_bytes: Vec<u8> = surface
+            .colors
+            .iter()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        let colors = push_view(&color_bytes, ARRAY_BUFFER);
+        let index_bytes: Vec<u8> = surface
+            .groups
+            .iter()
+            .flat_map(|(_, indices)| indices.iter().flat_map(|i| i.to_le_bytes()))
+            .collect();
+        let indices = push_view(&index_bytes, ELEMENT_ARRAY_BUFFER);
+
+        let mut accessors = vec![
+            json!({
+                "bufferView": positions,
+                "componentType": FLOAT,
+                "count": vertices,
+                "type": "VEC3",
+                "min": min,
+                "max": max,
+            }),
+            json!({
+                "bufferView": normals,
+                "componentType": FLOAT,
+                "count": vertices,
+                "type": "VEC3",
+            }),
+            json!({
+                "bufferView": colors,
+                "componentType": UNSIGNED_BYTE,
+                "normalized": true,
+                "count": vertices,
+                "type": "VEC4",
+            }),
+        ];
+        let mut primitives = Vec::new();
+        let mut offset = 0;
+        for (name, group) in &surface.groups {
+            accessors.push(json!({
+                "bufferView": indices,
+                "byteOffset": offset * 4,
+                "componentType": UNSIGNED_INT,
+                "count": group.len(),
+                "type": "SCALAR",
+            }));
+            primitives.push(json!({
+                "attributes": { "POSITION": 0, "NORMAL": 1, "COLOR_0": 2 },
+                "indices": accessors.len() - 1,
+                "mode": TRIANGLES,
+                // glTF primitives have no name of their own
+                "extras": { "name": name },
+            }));
+            offset += group.len();
+        }
+        document["scenes"][0]["nodes"] = json!([0]);
+        document["nodes"] = json!([{ "name": "chunk", "mesh": 0 }]);
+        document["meshes"] = json!([{ "name": "chunk", "primitives": primitives }]);
+        document["accessors"] = json!(accessors);
+        document["bufferViews"] = json!(views);
+        document["buffers"] = json!([{ "byteLength": bin.len() }]);
+    }
+
+    let mut json = document.to_string().into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let mut total = 12 + 8 + json.len();
+    if !bin.is_empty() {
+        total += 8 + bin.len();
+    }
+    let mut glb = Vec::with_capacity(total);
+    for word in [GLB_MAGIC, 2, total as u32, json.len() as u32, GLB_JSON] {
+        glb.extend(word.to_le_bytes());
+    }
+    glb.extend(json);
+    if !bin.is_empty() {
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(GLB_BIN.to_le_bytes());
+        glb.extend(bin);
+    }
+    glb
+}
+
+/// Wavefront OBJ of a chunk. Material colours are written as `v x y z r g b`,
+/// which Blender and MeshLab read as vertex colours. With `per_meshlet` each
+/// meshlet and skirt face is a `g` group.
+pub fn write_obj(chunk: &Chunk, per_meshlet: bool) -> String {
+    let surface = Surface::new(chunk, per_meshlet);
+    let triangles: usize = surface.groups.iter().map(|(_, g)| g.len() / 3).sum();
+
+    let mut obj = String::new();
+    // Writing to a String cannot fail
+    let _ = writeln!(
+        obj,
+        "# my-lib chunk: {} vertices, {} triangles",
+        surface.positions.len(),
+        triangles
+    );
+    for (p, color) in surface.positions.iter().zip(&surface.colors) {
+        let [r, g, b, _] = color.to_le_bytes().map(|c| c as f32 / 255.0);
+        let _ = writeln!(
+            obj,
+            "v {} {} {} {:.4} {:.4} {:.4}",
+            p[0], p[1], p[2], r, g, b
+        );
+    }
+    for n in &surface.normals {
+        let _ = writeln!(obj, "vn {} {} {}", n[0], n[1], n[2]);
+    }
+    for (name, group) in &surface.groups {
+        let _ = writeln!(obj, "g {}", name);
+        for t in group.chunks_exact(3) {
+            let [a, b, c] = [t[0] + 1, t[1] + 1, t[2] + 1];
+            let _ = writeln!(obj, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c);
+        }
+    }
+    obj
+}
@@ -0,0 +1,516 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::graph::{DensityGraph, FractalMode, GraphError, Node, NodeId, MAX_GRAPH_NODES};
+use crate::noise::{NoiseKind, TerrainParams};
+
+/// Current version of the world definition format
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// Settings of the built-in heightfield; omitted fields keep the defaults for the seed
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TerrainDesc {
+    #[serde(default)]
+    seed: u32,
+    noise: Option<NoiseKind>,
+    warp_scale: Option<f32>,
+    warp_amount: Option<f32>,
+    octaves: Option<u32>,
+    frequency: Option<f32>,
+    amplitude: Option<f32>,
+    persistence: Option<f32>,
+    lacunarity: Option<f32>,
+    ridged_octaves: Option<u32>,
+    billow_octaves: Option<u32>,
+    base_height: Option<f32>,
+}
+
+/// On-disk form of one graph node, tagged by `"type"`
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum NodeDesc {
+    Constant {
+        value: f32,
+    },
+    Coordinate {
+        axis: Axis,
+    },
+    Noise {
+        kind: NoiseKind,
+        #[serde(default)]
+        seed: u32,
+        frequency: f32,
+    },
+    Fbm {
+        input: NodeId,
+        octaves: u32,
+        lacunarity: f32,
+        persistence: f32,
+    },
+    Ridged {
+        input: NodeId,
+        octaves: u32,
+        lacunarity: f32,
+        persistence: f32,
+    },
+    Billow {
+        input: NodeId,
+        octaves: u32,
+        lacunarity: f32,
+        persistence: f32,
+    },
+    Warp {
+        input: NodeId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        x: Option<NodeId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        y: Option<NodeId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        z: Option<NodeId>,
+        amount: f32,
+    },
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+    Box {
+        center: [f32; 3],
+        half_extents: [f32; 3],
+    },
+    Capsule {
+        a: [f32; 3],
+        b: [f32; 3],
+        radius: f32,
+    },
+    Plane {
+        normal: [f32; 3],
+        height: f32,
+    },
+    Add {
+        a: NodeId,
+        b: NodeId,
+    },
+    Subtract {
+        a: NodeId,
+        b: NodeId,
+    },
+    Multiply {
+        a: NodeId,
+        b: NodeId,
+    },
+    SmoothUnion {
+        a: NodeId,
+        b: NodeId,
+        blend: f32,
+    },
+    SmoothSubtraction {
+        a: NodeId,
+        b: NodeId,
+        blend: f32,
+    },
+    SmoothIntersection {
+        a: NodeId,
+        b: NodeId,
+        blend: f32,
+    },
+    Clamp {
+        input: NodeId,
+        min: f32,
+        max: f32,
+    },
+    Remap {
+        input: NodeId,
+        from: [f32; 2],
+        to: [f32; 2],
+    },
+    HeightCurve {
+        input: NodeId,
+        points: Vec<[f32; 2]>,
+    },
+    Terrain(TerrainDesc),
+}
+
+#[derive(Serialize)]
+struct Document {
+    version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root: Option<NodeId>,
+    nodes: Vec<NodeDesc>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatError {
+    /// The text is not valid JSON
+    Syntax(String),
+    /// The document was written by an unknown format version
+    UnsupportedVersion(u64),
+    /// The top-level object is malformed
+    Structure(String),
+    /// A node failed to parse or validate
+    Node {
+        index: usize,
+        node_type: Option<String>,
+        message: String,
+    },
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Syntax(message) => write!(f, "invalid JSON: {}", message),
+            FormatError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported format version {} (expected {})",
+                version, FORMAT_VERSION
+            ),
+            FormatError::Structure(message) => write!(f, "{}", message),
+            FormatError::Node {
+                index,
+                node_type: Some(node_type),
+                message,
+            } => write!(f, "nodes[{}] ({}): {}", index, node_type, message),
+            FormatError::Node {
+                index,
+                node_type: None,
+                message,
+            } => write!(f, "nodes[{}]: {}", index, message),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Parses and validates a world definition. Graphs over `MAX_GRAPH_NODES`
+/// nodes or `MAX_EVAL_COST` evaluations per sample are rejected, so a world
+/// file cannot make sampling arbitrarily slow.
+pub fn parse_graph(json: &str) -> Result<DensityGraph, FormatError> {
+    let document: Value =
+        serde_json::from_str(json).map_err(|e| FormatError::Syntax(e.to_string()))?;
+    let object = document
+        .as_object()
+        .ok_or_else(|| FormatError::Structure("document must be a JSON object".to_string()))?;
+
+    match object.get("version").and_then(Value::as_u64) {
+        Some(version) if version == FORMAT_VERSION as u64 => {}
+        Some(version) => return Err(FormatError::UnsupportedVersion(version)),
+        None => {
+            return Err(FormatError::Structure(
+                "missing numeric \"version\" field".to_string(),
+            ))
+        }
+    }
+
+    let nodes = object
+        .get("nodes")
+        .and_then(Value::as_array)
+        .ok_or_else(|| FormatError::Structure("missing \"nodes\" array".to_string()))?;
+    if nodes.len() > MAX_GRAPH_NODES {
+        return Err(FormatError::Structure(format!(
+            "\"nodes\" has {} entries, at most {} are allowed",
+            nodes.len(),
+            MAX_GRAPH_NODES
+        )));
+    }
+
+    let mut graph = DensityGraph::default();
+    for (index, value) in nodes.iter().enumerate() {
+        // Parse nodes one by one so errors can name the offending entry
+        let node_type = value.get("type").and_then(Value::as_str).map(String::from);
+        let node_error = |message: String| FormatError::Node {
+            index,
+            node_type: node_type.clone(),
+            message,
+        };
+
+        let desc: NodeDesc =
+            serde_json::from_value(value.clone()).map_err(|e| node_error(e.to_string()))?;
+        let node = desc.into_node().map_err(node_error)?;
+        graph.push(node).map_err(|e| match e {
+            GraphError::UnknownInput { input, .. } => {
+                node_error(format!("input {} must refer to an earlier node", input))
+            }
+            GraphError::InvalidParameter { message, .. } => node_error(message),
+        })?;
+    }
+
+    match object.get("root") {
+        None | Some(Value::Null) => {}
+        Some(root) => {
+            let root = root.as_u64().ok_or_else(|| {
+                FormatError::Structure("\"root\" must be a node index".to_string())
+            })?;
+            graph.set_root(root as NodeId).map_err(|_| {
+                FormatError::Structure(format!("\"root\" {} is not a node index", root))
+            })?;
+        }
+    }
+
+    if graph.root().is_none() {
+        return Err(FormatError::Structure(
+            "\"nodes\" must contain at least one node".to_string(),
+        ));
+    }
+
+    Ok(graph)
+}
+
+/// Writes a graph in the current format version
+pub fn write_graph(graph: &DensityGraph) -> String {
+    let document = Document {
+        version: FORMAT_VERSION,
+        root: graph.root(),
+        nodes: graph.nodes().iter().map(NodeDesc::from_node).collect(),
+    };
+    serde_json::to_string_pretty(&document).expect("graph documents always serialize")
+}
+
+/// Fingerprint of a world generator: FNV-1a of its serialized form, so it is
+/// the same on every platform and build and changes with any parameter
+pub fn generator_hash(graph: &DensityGraph) -> u64 {
+    write_graph(graph)
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+impl NodeDesc {
+    fn into_node(self) -> Result<Node, String> {
+        let node = match self {
+            NodeDesc::Constant { value } => Node::Constant(value),
+            NodeDesc::Coordinate { axis } => Node::Coordinate(axis as usize),
+            NodeDesc::Noise {
+                kind,
+                seed,
+                frequency,
+            } => Node::noise(kind, seed, frequency),
+            NodeDesc::Fbm {
+                input,
+                octaves,
+                lacunarity,
+                persistence,
+            } => fractal(input, FractalMode::Fbm, octaves, lacunarity, persistence),
+            NodeDesc::Ridged {
+                input,
+                octaves,
+                lacunarity,
+                persistence,
+            } => fractal(input, FractalMode::Ridged, octaves, lacunarity, persistence),
+            NodeDesc::Billow {
+                input,
+                octaves,
+                lacunarity,
+                persistence,
+            } => fractal(input, FractalMode::Billow, octaves, lacunarity, persistence),
+            NodeDesc::Warp {
+                input,
+                x,
+                y,
+                z,
+                amount,
+            } => Node::Warp {
+                input,
+                warp: [x, y, z],
+                amount,
+            },
+            NodeDesc::Sphere { center, radius } => Node::Sphere { center, radius },
+            NodeDesc::Box {
+                center,
+                half_extents,
+            } => Node::Box {
+                center,
+                half_extents,
+            },
+            NodeDesc::Capsule { a, b, radius } => Node::Capsule { a, b, radius },
+            NodeDesc::Plane { normal, height } => {
+                let length =
+                    (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+                if length == 0.0 {
+                    return Err("plane normal must not be zero".to_string());
+                }
+                Node::Plane {
+                    normal: normal.map(|n| n / length),
+                    height,
+                }
+            }
+            NodeDesc::Add { a, b } => Node::Add(a, b),
+            NodeDesc::Subtract { a, b } => Node::Subtract(a, b),
+            NodeDesc::Multiply { a, b } => Node::Multiply(a, b),
+            NodeDesc::SmoothUnion { a, b, blend } => Node::SmoothUnion(a, b, blend),
+            NodeDesc::SmoothSubtraction { a, b, blend } => Node::SmoothSubtraction(a, b, blend),
+            NodeDesc::SmoothIntersection { a, b, blend } => Node::SmoothIntersection(a, b, blend),
+            NodeDesc::Clamp { input, min, max } => Node::Clamp { input, min, max },
+            NodeDesc::Remap { input, from, to } => Node::Remap { input, from, to },
+            NodeDesc::HeightCurve { input, points } => Node::HeightCurve { input, points },
+            NodeDesc::Terrain(desc) => {
+                let mut params = TerrainParams::new(desc.seed);
+                params.noise = desc.noise.unwrap_or(params.noise);
+                params.warp_scale = desc.warp_scale.unwrap_or(params.warp_scale);
+                params.warp_amount = desc.warp_amount.unwrap_or(params.warp_amount);
+                params.octaves = desc.octaves.unwrap_or(params.octaves);
+                params.frequency = desc.frequency.unwrap_or(params.frequency);
+                params.amplitude = desc.amplitude.unwrap_or(params.amplitude);
+                params.persistence = desc.persistence.unwrap_or(params.persistence);
+                params.lacunarity = desc.lacunarity.unwrap_or(params.lacunarity);
+                params.ridged_octaves = desc.ridged_octaves.unwrap_or(params.ridged_octaves);
+                params.billow_octaves = desc.billow_octaves.unwrap_or(params.billow_octaves);
+                params.base_height = desc.base_height.unwrap_or(params.base_height);
+                Node::Terrain(params)
+            }
+        };
+        Ok(node)
+    }
+
+    fn from_node(node: &Node) -> NodeDesc {
+        match node {
+            Node::Constant(value) => NodeDesc::Constant { value: *value },
+            Node::Coordinate(axis) => NodeDesc::Coordinate {
+                axis: [Axis::X, Axis::Y, Axis::Z][*axis],
+            },
+            Node::Noise {
+                kind,
+                seed,
+                frequency,
+                ..
+            } => NodeDesc::Noise {
+                kind: *kind,
+                seed: *seed,
+                frequency: *frequency,
+            },
+            Node::Fractal {
+                input,
+                mode,
+                octaves,
+                lacunarity,
+                persistence,
+            } => {
+                let (input, octaves, lacunarity, persistence) =
+                    (*input, *octaves, *lacunarity, *persistence);
+                match mode {
+                    FractalMode::Fbm => NodeDesc::Fbm {
+                        input,
+                        octaves,
+                        lacunarity,
+                        persistence,
+                    },
+                    FractalMode::Ridged => NodeDesc::Ridged {
+                        input,
+                        octaves,
+                        lacunarity,
+                        persistence,
+                    },
+                    FractalMode::Billow => NodeDesc::Billow {
+                        input,
+                        octaves,
+                        lacunarity,
+                        persistence,
+                    },
+                }
+            }
+            Node::Warp {
+                input,
+                warp,
+                amount,
+            } => NodeDesc::Warp {
+                input: *input,
+                x: warp[0],
+                y: warp[1],
+                z: warp[2],
+                amount: *amount,
+            },
+            Node::Sphere { center, radius } => NodeDesc::Sphere {
+                center: *center,
+                radius: *radius,
+            },
+            Node::Box {
+                center,
+                half_extents,
+            } => NodeDesc::Box {
+                center: *center,
+                half_extents: *half_extents,
+            },
+            Node::Capsule { a, b, radius } => NodeDesc::Capsule {
+                a: *a,
+                b: *b,
+                radius: *radius,
+            },
+            Node::Plane { normal, height } => NodeDesc::Plane {
+                normal: *normal,
+                height: *height,
+            },
+            Node::Add(a, b) => NodeDesc::Add { a: *a, b: *b },
+            Node::Subtract(a, b) => NodeDesc::Subtract { a: *a, b: *b },
+            Node::Multiply(a, b) => NodeDesc::Multiply { a: *a, b: *b },
+            Node::SmoothUnion(a, b, blend) => NodeDesc::SmoothUnion {
+                a: *a,
+                b: *b,
+                blend: *blend,
+            },
+            Node::SmoothSubtraction(a, b, blend) => NodeDesc::SmoothSubtraction {
+                a: *a,
+                b: *b,
+                blend: *blend,
+            },
+            Node::SmoothIntersection(a, b, blend) => NodeDesc::SmoothIntersection {
+                a: *a,
+                b: *b,
+                blend: *blend,
+            },
+            Node::Clamp { input, min, max } => NodeDesc::Clamp {
+                input: *input,
+                min: *min,
+                max: *max,
+            },
+            Node::Remap { input, from, to } => NodeDesc::Remap {
+                input: *input,
+                from: *from,
+                to: *to,
+            },
+            Node::HeightCurve { input, points } => NodeDesc::HeightCurve {
+                input: *input,
+                points: points.clone(),
+            },
+            Node::Terrain(params) => NodeDesc::Terrain(TerrainDesc {
+                seed: params.seed(),
+                noise: Some(params.noise),
+                warp_scale: Some(params.warp_scale),
+                warp_amount: Some(params.warp_amount),
+                octaves: Some(params.octaves),
+                frequency: Some(params.frequency),
+                amplitude: Some(params.amplitude),
+                persistence: Some(params.persistence),
+                lacunarity: Some(params.lacunarity),
+                ridged_octaves: Some(params.ridged_octaves),
+                billow_octaves: Some(params.billow_octaves),
+                base_height: Some(params.base_height),
+            }),
+        }
+    }
+}
+
+fn fractal(
+    input: NodeId,
+    mode: FractalMode,
+    octaves: u32,
+    lacunarity: f32,
+    persistence: f32,
+) -> Node {
+    Node::Fractal {
+        input,
+        mode,
+        octaves,
+        lacunarity,
+        persistence,
+    }
+}
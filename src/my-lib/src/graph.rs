@@ -0,0 +1,916 @@
+use std::fmt;
+
+use crate::interval::Interval;
+use crate::noise::{
+    billow_bounds, noise3, ridged_bounds, Density, DensityField, NoiseKind, Permutation,
+    TerrainParams,
+};
+
+/// Index of a node inside a `DensityGraph`
+pub type NodeId = u32;
+
+/// Most nodes a graph may hold
+pub const MAX_GRAPH_NODES: usize = 1024;
+
+/// Most node evaluations one sample of a graph may take. A `Fractal` or
+/// `Warp` evaluates its input again for every octave or the warped position,
+/// so nesting them multiplies the cost.
+pub const MAX_EVAL_COST: u64 = 4096;
+
+/// Octave shaping applied by a `Fractal` node
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FractalMode {
+    /// Plain fBm: octaves are summed unchanged
+    Fbm,
+    /// Inverted, sharpened octaves (mountain ridges)
+    Ridged,
+    /// Absolute-valued octaves (rolling hills)
+    Billow,
+}
+
+/// One operation in the density graph. Inputs always point at earlier nodes,
+/// so the graph is acyclic by construction.
+#[derive(Clone)]
+pub enum Node {
+    Constant(f32),
+    /// World-space coordinate along one axis (0 = X, 1 = Y, 2 = Z)
+    Coordinate(usize),
+    Noise {
+        kind: NoiseKind,
+        seed: u32,
+        permutation: Permutation,
+        frequency: f32,
+    },
+    /// Sums octaves of `input`, each sampled at `lacunarity` times the previous frequency
+    Fractal {
+        input: NodeId,
+        mode: FractalMode,
+        octaves: u32,
+        lacunarity: f32,
+        persistence: f32,
+    },
+    /// Evaluates `input` at a position offset by `amount` times the warp nodes
+    Warp {
+        input: NodeId,
+        warp: [Option<NodeId>; 3],
+        amount: f32,
+    },
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+    Box {
+        center: [f32; 3],
+        half_extents: [f32; 3],
+    },
+    Capsule {
+        a: [f32; 3],
+        b: [f32; 3],
+        radius: f32,
+    },
+    /// Half-space below the plane `dot(p, normal) = height`
+    Plane {
+        normal: [f32; 3],
+        height: f32,
+    },
+    Add(NodeId, NodeId),
+    Subtract(NodeId, NodeId),
+    Multiply(NodeId, NodeId),
+    SmoothUnion(NodeId, NodeId, f32),
+    /// Removes the second shape from the first
+    SmoothSubtraction(NodeId, NodeId, f32),
+    SmoothIntersection(NodeId, NodeId, f32),
+    Clamp {
+        input: NodeId,
+        min: f32,
+        max: f32,
+    },
+    /// Linearly maps `from` onto `to` (not clamped)
+    Remap {
+        input: NodeId,
+        from: [f32; 2],
+        to: [f32; 2],
+    },
+    /// Piecewise linear curve through (input, output) points sorted by input
+    HeightCurve {
+        input: NodeId,
+        points: Vec<[f32; 2]>,
+    },
+    /// The built-in warped ridged/billow heightfield from `noise.rs`
+    Terrain(TerrainParams),
+}
+
+impl Node {
+    pub fn noise(kind: NoiseKind, seed: u32, frequency: f32) -> Node {
+        Node::Noise {
+            kind,
+            seed,
+            permutation: Permutation::from_seed(seed),
+            frequency,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphError {
+    /// `node` references `input`, which is not an earlier node
+    UnknownInput { node: usize, input: NodeId },
+    /// `node` has a parameter outside its valid range
+    InvalidParameter { node: usize, message: String },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::UnknownInput { node, input } => {
+                write!(
+                    f,
+                    "node {}: input {} does not refer to an earlier node",
+                    node, input
+                )
+            }
+            GraphError::InvalidParameter { node, message } => {
+                write!(f, "node {}: {}", node, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+/// Composable SDF node tree. Evaluation returns the density and its gradient,
+/// so meshes built from a graph keep exact normals.
+#[derive(Clone, Default)]
+pub struct DensityGraph {
+    nodes: Vec<Node>,
+    // Node evaluations needed to evaluate each node, which covers every node before it
+    costs: Vec<u64>,
+    root: Option<NodeId>,
+}
+
+impl DensityGraph {
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Root node, defaults to the last node added
+    pub fn root(&self) -> Option<NodeId> {
+        self.root
+            .or_else(|| self.nodes.len().checked_sub(1).map(|i| i as NodeId))
+    }
+
+    pub fn set_root(&mut self, root: NodeId) -> Result<(), GraphError> {
+        if root as usize >= self.nodes.len() {
+            return Err(GraphError::UnknownInput {
+                node: self.nodes.len(),
+                input: root,
+            });
+        }
+        self.root = Some(root);
+        Ok(())
+    }
+
+    /// Validates and appends a node, returning its id. Fails once the graph
+    /// would exceed `MAX_GRAPH_NODES` or `MAX_EVAL_COST`.
+    pub fn push(&mut self, node: Node) -> Result<NodeId, GraphError> {
+        let index = self.nodes.len();
+        let invalid = |message: String| GraphError::InvalidParameter {
+            node: index,
+            message,
+        };
+        if index >= MAX_GRAPH_NODES {
+            return Err(invalid(format!(
+                "graphs are limited to {} nodes",
+                MAX_GRAPH_NODES
+            )));
+        }
+        validate(&node, index)?;
+
+        let before = self.costs.last().copied().unwrap_or(0);
+        let again = |input: &NodeId| self.costs[*input as usize];
+        let own = match &node {
+            // The first octave reuses the input already evaluated at the sample
+            Node::Fractal { input, octaves, .. } => {
+                again(input).saturating_mul(*octaves as u64 - 1)
+            }
+            Node::Warp { input, .. } => again(input),
+            Node::Terrain(params) => {
+                // Warp noise and every octave of the three layers
+                (3 + params.octaves + params.ridged_octaves + params.billow_octaves) as u64
+            }
+            _ => 0,
+        };
+        let cost = before.saturating_add(own).saturating_add(1);
+        if cost > MAX_EVAL_COST {
+            return Err(invalid(format!(
+                "graph would take {} node evaluations per sample, at most {} are allowed",
+                cost, MAX_EVAL_COST
+            )));
+        }
+
+        self.nodes.push(node);
+        self.costs.push(cost);
+        Ok(index as NodeId)
+    }
+
+    pub fn noise(
+        &mut self,
+        kind: NoiseKind,
+        seed: u32,
+        frequency: f32,
+    ) -> Result<NodeId, GraphError> {
+        self.push(Node::noise(kind, seed, frequency))
+    }
+
+    /// Value and gradient of node `id` at `p`. Nodes up to `id` are evaluated
+    /// in index order into `values`, so a node shared by several parents runs
+    /// once. Only `Fractal` and `Warp` recurse, to sample their input elsewhere.
+    /// `values` is left as it was found.
+    fn eval(&self, id: NodeId, p: [f32; 3], values: &mut Vec<(f32, [f32; 3])>) -> (f32, [f32; 3]) {
+        let base = values.len();
+        for index in 0..=id as usize {
+            let value = self.eval_node(index, p, base, values);
+            values.push(value);
+        }
+        let result = values[base + id as usize];
+        values.truncate(base);
+        result
+    }
+
+    // Node `index` at `p`, with the earlier nodes' values from `values[base..]`
+    fn eval_node(
+        &self,
+        index: usize,
+        p: [f32; 3],
+        base: usize,
+        values: &mut Vec<(f32, [f32; 3])>,
+    ) -> (f32, [f32; 3]) {
+        let at = |values: &[(f32, [f32; 3])], id: &NodeId| values[base + *id as usize];
+        match &self.nodes[index] {
+            Node::Constant(value) => (*value, [0.0; 3]),
+            Node::Coordinate(axis) => {
+                let mut gradient = [0.0; 3];
+                gradient[*axis] = 1.0;
+                (p[*axis], gradient)
+            }
+            Node::Noise {
+                kind,
+                permutation,
+                frequency,
+                ..
+            } => {
+                let (value, gradient) = noise3(
+                    *kind,
+                    permutation,
+                    p[0] * frequency,
+                    p[1] * frequency,
+                    p[2] * frequency,
+                );
+                (value, scale(gradient, *frequency))
+            }
+            Node::Fractal {
+                input,
+                mode,
+                octaves,
+                lacunarity,
+                persistence,
+            } => {
+                let mut value = 0.0;
+                let mut gradient = [0.0; 3];
+                let mut amplitude = 1.0;
+                let mut frequency = 1.0;
+                for octave in 0..*octaves {
+                    // The first octave is the input at `p`, already evaluated
+                    let (n, g) = if octave == 0 {
+                        at(values, input)
+                    } else {
+                        self.eval(*input, scale(p, frequency), values)
+                    };
+                    let (shaped, slope) = match mode {
+                        FractalMode::Fbm => (n, 1.0),
+                        FractalMode::Ridged => {
+                            let r = 1.0 - 2.0 * n.abs();
+                            (r * r * r.signum(), -4.0 * r.abs() * n.signum())
+                        }
+                        FractalMode::Billow => (n.abs() * 2.0 - 1.0, 2.0 * n.signum()),
+                    };
+                    value += shaped * amplitude;
+                    gradient = add(gradient, scale(g, slope * amplitude * frequency));
+                    amplitude *= persistence;
+                    frequency *= lacunarity;
+                }
+                (value, gradient)
+            }
+            Node::Warp {
+                input,
+                warp,
+                amount,
+            } => {
+                // Row i of the Jacobian is d warped[i] / d p
+                let mut warped = p;
+                let mut jacobian = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+                for axis in 0..3 {
+                    if let Some(w) = &warp[axis] {
+                        let (offset, g) = at(values, w);
+                        warped[axis] += offset * amount;
+                        jacobian[axis] = add(jacobian[axis], scale(g, *amount));
+                    }
+                }
+                let (value, g) = self.eval(*input, warped, values);
+                let mut gradient = [0.0; 3];
+                for (j, out) in gradient.iter_mut().enumerate() {
+                    *out = g[0] * jacobian[0][j] + g[1] * jacobian[1][j] + g[2] * jacobian[2][j];
+                }
+                (value, gradient)
+            }
+            Node::Sphere { center, radius } => {
+                let d = sub(p, *center);
+                let length = length(d);
+                (length - radius, normalize_or_up(d, length))
+            }
+            Node::Box {
+                center,
+                half_extents,
+            } => sdf_box(sub(p, *center), *half_extents),
+            Node::Capsule { a, b, radius } => {
+                let pa = sub(p, *a);
+                let ba = sub(*b, *a);
+                let h = (dot(pa, ba) / dot(ba, ba).max(1e-12)).clamp(0.0, 1.0);
+                let d = sub(pa, scale(ba, h));
+                let length = length(d);
+                (length - radius, normalize_or_up(d, length))
+            }
+            Node::Plane { normal, height } => (dot(p, *normal) - height, *normal),
+            Node::Add(a, b) => {
+                let ((va, ga), (vb, gb)) = (at(values, a), at(values, b));
+                (va + vb, add(ga, gb))
+            }
+            Node::Subtract(a, b) => {
+                let ((va, ga), (vb, gb)) = (at(values, a), at(values, b));
+                (va - vb, sub(ga, gb))
+            }
+            Node::Multiply(a, b) => {
+                let ((va, ga), (vb, gb)) = (at(values, a), at(values, b));
+                (va * vb, add(scale(ga, vb), scale(gb, va)))
+            }
+            Node::SmoothUnion(a, b, k) => smooth_min(at(values, a), at(values, b), *k),
+            Node::SmoothSubtraction(a, b, k) => {
+                negate(smooth_min(negate(at(values, a)), at(values, b), *k))
+            }
+            Node::SmoothIntersection(a, b, k) => {
+                negate(smooth_min(negate(at(values, a)), negate(at(values, b)), *k))
+            }
+            Node::Clamp { input, min, max } => {
+                let (value, gradient) = at(values, input);
+                if value < *min {
+                    (*min, [0.0; 3])
+                } else if value > *max {
+                    (*max, [0.0; 3])
+                } else {
+                    (value, gradient)
+                }
+            }
+            Node::Remap { input, from, to } => {
+                let (value, gradient) = at(values, input);
+                let slope = (to[1] - to[0]) / (from[1] - from[0]);
+                (to[0] + (value - from[0]) * slope, scale(gradient, slope))
+            }
+            Node::HeightCurve { input, points } => {
+                let (value, gradient) = at(values, input);
+                let (mapped, slope) = evaluate_curve(points, value);
+                (mapped, scale(gradient, slope))
+            }
+            Node::Terrain(params) => params.sample(p),
+        }
+    }
+
+    /// Interval version of `eval`: a conservative range of node `id` over the
+    /// box `min..=max`, evaluated in index order the same way
+    fn eval_bounds(
+        &self,
+        id: NodeId,
+        min: [f32; 3],
+        max: [f32; 3],
+        ranges: &mut Vec<Interval>,
+    ) -> Interval {
+        let base = ranges.len();
+        for index in 0..=id as usize {
+            let range = self.bounds_node(index, min, max, base, ranges);
+            ranges.push(range);
+        }
+        let result = ranges[base + id as usize];
+        ranges.truncate(base);
+        result
+    }
+
+    // Range of node `index` over the box, with the earlier nodes' ranges from `ranges[base..]`
+    fn bounds_node(
+        &self,
+        index: usize,
+        min: [f32; 3],
+        max: [f32; 3],
+        base: usize,
+        ranges: &mut Vec<Interval>,
+    ) -> Interval {
+        let at = |ranges: &[Interval], id: &NodeId| ranges[base + *id as usize];
+        match &self.nodes[index] {
+            Node::Constant(value) => Interval::point(*value),
+            Node::Coordinate(axis) => Interval::new(min[*axis], max[*axis]),
+            Node::Noise { kind, .. } => Interval::around(0.0, kind.max_abs()),
+            Node::Fractal {
+                input,
+                mode,
+                octaves,
+                lacunarity,
+                persistence,
+            } => {
+                let mut value = Interval::point(0.0);
+                let mut amplitude = 1.0;
+                let mut frequency = 1.0;
+                for octave in 0..*octaves {
+                    let n = if octave == 0 {
+                        at(ranges, input)
+                    } else {
+                        let (lo, hi) = scale_box(min, max, frequency);
+                        self.eval_bounds(*input, lo, hi, ranges)
+                    };
+                    let shaped = match mode {
+                        FractalMode::Fbm => n,
+                        FractalMode::Ridged => ridged_bounds(n),
+                        FractalMode::Billow => billow_bounds(n),
+                    };
+                    value = value + shaped.scale(amplitude);
+                    amplitude *= persistence;
+                    frequency *= lacunarity;
+                }
+                value
+            }
+            Node::Warp {
+                input,
+                warp,
+                amount,
+            } => {
+                // Every point of the box can move by the warp's range along each axis
+                let (mut lo, mut hi) = (min, max);
+                for axis in 0..3 {
+                    if let Some(w) = &warp[axis] {
+                        let offset = at(ranges, w).scale(*amount);
+                        lo[axis] += offset.min;
+                        hi[axis] += offset.max;
+                    }
+                }
+                self.eval_bounds(*input, lo, hi, ranges)
+            }
+            Node::Sphere { center, radius } => {
+                let mut nearest = [0.0; 3];
+                let mut farthest = [0.0; 3];
+                for axis in 0..3 {
+                    let c = center[axis];
+                    nearest[axis] = c.clamp(min[axis], max[axis]) - c;
+                    farthest[axis] = (min[axis] - c).abs().max((max[axis] - c).abs());
+                }
+                Interval::new(length(nearest) - radius, length(farthest) - radius)
+            }
+            // Exact SDFs change by at most the distance moved. They have no
+            // inputs, so no earlier values are needed to sample them.
+            Node::Box { .. } | Node::Capsule { .. } => {
+                let center = scale(add(min, max), 0.5);
+                let radius = length(sub(max, min)) * 0.5;
+                Interval::around(self.eval_node(index, center, 0, &mut Vec::new()).0, radius)
+            }
+            Node::Plane { normal, height } => {
+                let mut value = Interval::point(-height);
+                for axis in 0..3 {
+                    value = value + Interval::new(min[axis], max[axis]).scale(normal[axis]);
+                }
+                value
+            }
+            Node::Add(a, b) => at(ranges, a) + at(ranges, b),
+            Node::Subtract(a, b) => at(ranges, a) - at(ranges, b),
+            Node::Multiply(a, b) => at(ranges, a) * at(ranges, b),
+            Node::SmoothUnion(a, b, k) => smooth_min_bounds(at(ranges, a), at(ranges, b), *k),
+            Node::SmoothSubtraction(a, b, k) => {
+                -smooth_min_bounds(-at(ranges, a), at(ranges, b), *k)
+            }
+            Node::SmoothIntersection(a, b, k) => {
+                -smooth_min_bounds(-at(ranges, a), -at(ranges, b), *k)
+            }
+            Node::Clamp {
+                input,
+                min: lo,
+                max: hi,
+            } => at(ranges, input).clamp(*lo, *hi),
+            Node::Remap { input, from, to } => {
+                let slope = (to[1] - to[0]) / (from[1] - from[0]);
+                (at(ranges, input) - Interval::point(from[0])).scale(slope) + Interval::point(to[0])
+            }
+            Node::HeightCurve { input, points } => curve_bounds(points, at(ranges, input)),
+            Node::Terrain(params) => params.bounds(min, max),
+        }
+    }
+}
+
+impl Density for DensityGraph {
+    fn sample(&self, pos: [f32; 3]) -> (f32, [f32; 3]) {
+        match self.root() {
+            Some(root) => self.eval(root, pos, &mut Vec::with_capacity(self.nodes.len())),
+            None => (1.0, [0.0, 1.0, 0.0]), // Empty graph is all air
+        }
+    }
+
+    fn bounds(&self, min: [f32; 3], max: [f32; 3]) -> Interval {
+        match self.root() {
+            Some(root) => {
+                self.eval_bounds(root, min, max, &mut Vec::with_capacity(self.nodes.len()))
+            }
+            None => Interval::point(1.0),
+        }
+    }
+
+    fn sample_row(&self, xs: &[f32], y: f32, z: f32, field: &mut DensityField) {
+        let Some(root) = self.root() else {
+            for x in xs {
+                let (value, gradient) = self.sample([*x, y, z]);
+                field.densities.push(value);
+                field.gradients.push(gradient);
+            }
+            return;
+        };
+        // A bare terrain root (the default world) keeps the terrain's fast row path
+        if let Node::Terrain(params) = &self.nodes[root as usize] {
+            return params.sample_row(xs, y, z, field);
+        }
+        // One scratch buffer for the whole row
+        let mut values = Vec::with_capacity(self.nodes.len());
+        for x in xs {
+            let (value, gradient) = self.eval(root, [*x, y, z], &mut values);
+            field.densities.push(value);
+            field.gradients.push(gradient);
+        }
+    }
+}
+
+fn validate(node: &Node, index: usize) -> Result<(), GraphError> {
+    let input = |id: &NodeId| -> Result<(), GraphError> {
+        if (*id as usize) < index {
+            Ok(())
+        } else {
+            Err(GraphError::UnknownInput {
+                node: index,
+                input: *id,
+            })
+        }
+    };
+    let invalid = |message: &str| GraphError::InvalidParameter {
+        node: index,
+        message: message.to_string(),
+    };
+    let finite = |points: &[[f32; 3]]| points.iter().flatten().all(|v| v.is_finite());
+    let positive = |v: f32| v > 0.0 && v.is_finite();
+    // Octave counts bound the work per sample, so they are capped
+    let fractal = |octaves: u32, lacunarity: f32, persistence: f32| {
+        if octaves == 0 || octaves > 16 {
+            return Err(invalid("octaves must be between 1 and 16"));
+        }
+        if !lacunarity.is_finite() || lacunarity <= 0.0 {
+            return Err(invalid("lacunarity must be positive"));
+        }
+        if !persistence.is_finite() {
+            return Err(invalid("persistence must be finite"));
+        }
+        Ok(())
+    };
+
+    match node {
+        Node::Constant(value) => {
+            if !value.is_finite() {
+                return Err(invalid("value must be finite"));
+            }
+        }
+        Node::Terrain(params) => {
+            fractal(params.octaves, params.lacunarity, params.persistence)?;
+            if params.ridged_octaves > 16 || params.billow_octaves > 16 {
+                return Err(invalid("ridged and billow octaves must be at most 16"));
+            }
+            if !params.frequency.is_finite() || params.frequency <= 0.0 {
+                return Err(invalid("frequency must be positive"));
+            }
+            if !params.warp_scale.is_finite() || params.warp_scale < 0.0 {
+                return Err(invalid("warp scale must not be negative"));
+            }
+            if !params.warp_amount.is_finite() {
+                return Err(invalid("warp amount must be finite"));
+            }
+            if !params.amplitude.is_finite() {
+                return Err(invalid("amplitude must be finite"));
+            }
+            if !params.base_height.is_finite() {
+                return Err(invalid("base height must be finite"));
+            }
+        }
+        Node::Coordinate(axis) => {
+            if *axis > 2 {
+                return Err(invalid("axis must be 0, 1 or 2"));
+            }
+        }
+        Node::Noise { frequency, .. } => {
+            if !frequency.is_finite() || *frequency <= 0.0 {
+                return Err(invalid("frequency must be positive"));
+            }
+        }
+        Node::Fractal {
+            input: i,
+            octaves,
+            lacunarity,
+            persistence,
+            ..
+        } => {
+            input(i)?;
+            fractal(*octaves, *lacunarity, *persistence)?;
+        }
+        Node::Warp {
+            input: i,
+            warp,
+            amount,
+        } => {
+            input(i)?;
+            for w in warp.iter().flatten() {
+                input(w)?;
+            }
+            if !amount.is_finite() {
+                return Err(invalid("warp amount must be finite"));
+            }
+        }
+        Node::Sphere { center, radius } => {
+            if !finite(&[*center]) {
+                return Err(invalid("center must be finite"));
+            }
+            if !positive(*radius) {
+                return Err(invalid("radius must be positive"));
+            }
+        }
+        Node::Capsule { a, b, radius } => {
+            if !finite(&[*a, *b]) {
+                return Err(invalid("end points must be finite"));
+            }
+            if !positive(*radius) {
+                return Err(invalid("radius must be positive"));
+            }
+        }
+        Node::Box {
+            center,
+            half_extents,
+        } => {
+            if !finite(&[*center]) {
+                return Err(invalid("center must be finite"));
+            }
+            if !half_extents.iter().all(|e| positive(*e)) {
+                return Err(invalid("half extents must be positive"));
+            }
+        }
+        Node::Plane { normal, height } => {
+            if !finite(&[*normal]) || (length(*normal) - 1.0).abs() > 1e-3 {
+                return Err(invalid("plane normal must be unit length"));
+            }
+            if !height.is_finite() {
+                return Err(invalid("plane height must be finite"));
+            }
+        }
+        Node::Add(a, b) | Node::Subtract(a, b) | Node::Multiply(a, b) => {
+            input(a)?;
+            input(b)?;
+        }
+        Node::SmoothUnion(a, b, k)
+        | Node::SmoothSubtraction(a, b, k)
+        | Node::SmoothIntersection(a, b, k) => {
+            input(a)?;
+            input(b)?;
+            if !(*k >= 0.0 && k.is_finite()) {
+                return Err(invalid("blend radius must not be negative"));
+            }
+        }
+        Node::Clamp { input: i, min, max } => {
+            input(i)?;
+            if !min.is_finite() || !max.is_finite() {
+                return Err(invalid("clamp bounds must be finite"));
+            }
+            if min > max {
+                return Err(invalid("min must not exceed max"));
+            }
+        }
+        Node::Remap { input: i, from, to } => {
+            input(i)?;
+            if !from.iter().chain(to).all(|v| v.is_finite()) {
+                return Err(invalid("remap ranges must be finite"));
+            }
+            if from[0] == from[1] {
+                return Err(invalid("remap source range is empty"));
+            }
+        }
+        Node::HeightCurve { input: i, points } => {
+            input(i)?;
+            if points.len() < 2 {
+                return Err(invalid("height curve needs at least two points"));
+            }
+            if !points.iter().flatten().all(|v| v.is_finite()) {
+                return Err(invalid("height curve points must be finite"));
+            }
+            if points.windows(2).any(|w| w[0][0] >= w[1][0]) {
+                return Err(invalid("height curve points must be sorted by input"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Polynomial smooth minimum (hard minimum when k is 0)
+pub(crate) fn smooth_min(a: (f32, [f32; 3]), b: (f32, [f32; 3]), k: f32) -> (f32, [f32; 3]) {
+    if k <= 0.0 {
+        return if a.0 < b.0 { a } else { b };
+    }
+    let h = (0.5 + 0.5 * (b.0 - a.0) / k).clamp(0.0, 1.0);
+    let value = b.0 + (a.0 - b.0) * h - k * h * (1.0 - h);
+    // The blend term's derivative cancels out, leaving the weighted input gradients
+    (value, add(scale(b.1, 1.0 - h), scale(a.1, h)))
+}
+
+/// Range of `smooth_min`, which stays within k / 4 below the hard minimum
+fn smooth_min_bounds(a: Interval, b: Interval, k: f32) -> Interval {
+    let hard = a.min(b);
+    if k <= 0.0 {
+        hard
+    } else {
+        Interval::new(hard.min - k * 0.25, hard.max)
+    }
+}
+
+pub(crate) fn negate(v: (f32, [f32; 3])) -> (f32, [f32; 3]) {
+    (-v.0, scale(v.1, -1.0))
+}
+
+pub(crate) fn sdf_box(p: [f32; 3], half_extents: [f32; 3]) -> (f32, [f32; 3]) {
+    let q = [
+        p[0].abs() - half_extents[0],
+        p[1].abs() - half_extents[1],
+        p[2].abs() - half_extents[2],
+    ];
+    let outside = [q[0].max(0.0), q[1].max(0.0), q[2].max(0.0)];
+    let outside_length = length(outside);
+    let sign = [p[0].signum(), p[1].signum(), p[2].signum()];
+
+    if outside_length > 0.0 {
+        let g = normalize_or_up(outside, outside_length);
+        (
+            outside_length,
+            [g[0] * sign[0], g[1] * sign[1], g[2] * sign[2]],
+        )
+    } else {
+        // Inside: distance to the closest face
+        let axis = if q[0] > q[1] && q[0] > q[2] {
+            0
+        } else if q[1] > q[2] {
+            1
+        } else {
+            2
+        };
+        let mut gradient = [0.0; 3];
+        gradient[axis] = sign[axis];
+        (q[axis], gradient)
+    }
+}
+
+/// Returns the curve value and its slope at `x`, holding the end values flat
+fn evaluate_curve(points: &[[f32; 2]], x: f32) -> (f32, f32) {
+    let first = points[0];
+    let last = points[points.len() - 1];
+    if x <= first[0] {
+        return (first[1], 0.0);
+    }
+    if x >= last[0] {
+        return (last[1], 0.0);
+    }
+    for w in points.windows(2) {
+        let (p0, p1) = (w[0], w[1]);
+        if x <= p1[0] {
+            let slope = (p1[1] - p0[1]) / (p1[0] - p0[0]);
+            return (p0[1] + (x - p0[0]) * slope, slope);
+        }
+    }
+    (last[1], 0.0)
+}
+
+/// Range of the piecewise linear curve over the inputs in `x`
+fn curve_bounds(points: &[[f32; 2]], x: Interval) -> Interval {
+    let ends = [
+        evaluate_curve(points, x.min).0,
+        evaluate_curve(points, x.max).0,
+    ];
+    let inner = points
+        .iter()
+        .filter(|p| p[0] > x.min && p[0] < x.max)
+        .map(|p| p[1]);
+    ends.into_iter().chain(inner).fold(
+        Interval::new(f32::INFINITY, f32::NEG_INFINITY),
+        |range, v| Interval::new(range.min.min(v), range.max.max(v)),
+    )
+}
+
+// Box scaled about the origin (the corners swap when the factor is negative)
+fn scale_box(min: [f32; 3], max: [f32; 3], s: f32) -> ([f32; 3], [f32; 3]) {
+    let (a, b) = (scale(min, s), scale(max, s));
+    (
+        [a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2])],
+        [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])],
+    )
+}
+
+pub(crate) fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub(crate) fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub(crate) fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn length(a: [f32; 3]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+pub(crate) fn normalize_or_up(a: [f32; 3], length: f32) -> [f32; 3] {
+    if length > 1e-6 {
+        scale(a, 1.0 / length)
+    } else {
+        [0.0, 1.0, 0.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each node feeds both inputs of the next, which took 2^depth evaluations per sample
+    // when shared nodes were evaluated once per path
+    #[test]
+    fn shared_inputs_evaluate_once() {
+        let mut graph = DensityGraph::default();
+        let mut node = graph.push(Node::Constant(1e-30)).unwrap();
+        for _ in 0..100 {
+            node = graph.push(Node::Add(node, node)).unwrap();
+        }
+        let expected = 1e-30 * 2f32.powi(100);
+        let (value, _) = graph.sample([0.0; 3]);
+        assert!((value - expected).abs() < expected * 1e-5);
+        let bounds = graph.bounds([0.0; 3], [1.0; 3]);
+        assert!((bounds.min - expected).abs() < expected * 1e-5);
+    }
+
+    #[test]
+    fn non_finite_parameters_are_rejected() {
+        let mut graph = DensityGraph::default();
+        let sphere = Node::Sphere {
+            center: [0.0; 3],
+            radius: f32::NAN,
+        };
+        assert!(graph.push(sphere).is_err());
+        assert!(graph.push(Node::Constant(f32::INFINITY)).is_err());
+        let json = r#"{"version": 1, "nodes": [
+            {"type": "sphere", "center": [0, 0, 0], "radius": 1e39}
+        ]}"#;
+        assert!(crate::format::parse_graph(json).is_err());
+    }
+
+    // Nested fractals multiply their octaves, the third level of 16 is too many
+    #[test]
+    fn eval_cost_is_capped() {
+        let mut graph = DensityGraph::default();
+        let mut node = graph.noise(NoiseKind::Value, 0, 0.01).unwrap();
+        let fractal = |input| Node::Fractal {
+            input,
+            mode: FractalMode::Fbm,
+            octaves: 16,
+            lacunarity: 2.0,
+            persistence: 0.5,
+        };
+        for _ in 0..2 {
+            node = graph.push(fractal(node)).unwrap();
+        }
+        assert!(matches!(
+            graph.push(fractal(node)),
+            Err(GraphError::InvalidParameter { node: 3, .. })
+        ));
+
+        let mut graph = DensityGraph::default();
+        for _ in 0..MAX_GRAPH_NODES {
+            graph.push(Node::Constant(0.0)).unwrap();
+        }
+        assert!(graph.push(Node::Constant(0.0)).is_err());
+    }
+}
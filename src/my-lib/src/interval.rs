@@ -0,0 +1,118 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// How a region relates to the isosurface, decided from a density interval
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Region {
+    Empty,   // Air everywhere, density >= 0
+    Full,    // Solid everywhere, density < 0
+    Surface, // May contain the isosurface
+}
+
+/// Conservative range of a density over some region
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Interval {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Interval {
+    pub const UNBOUNDED: Interval = Interval {
+        min: f32::NEG_INFINITY,
+        max: f32::INFINITY,
+    };
+
+    pub fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+    pub fn point(value: f32) -> Self {
+        Self::new(value, value)
+    }
+
+    /// Range of `center ± radius`, for 1-Lipschitz functions like exact SDFs
+    pub fn around(center: f32, radius: f32) -> Self {
+        Self::new(center - radius, center + radius)
+    }
+
+    pub fn scale(self, s: f32) -> Self {
+        if s >= 0.0 {
+            Self::new(self.min * s, self.max * s)
+        } else {
+            Self::new(self.max * s, self.min * s)
+        }
+    }
+
+    pub fn min(self, other: Self) -> Self {
+        Self::new(self.min.min(other.min), self.max.min(other.max))
+    }
+
+    pub fn clamp(self, min: f32, max: f32) -> Self {
+        Self::new(self.min.clamp(min, max), self.max.clamp(min, max))
+    }
+
+    pub fn abs(self) -> Self {
+        if self.min >= 0.0 {
+            self
+        } else if self.max <= 0.0 {
+            -self
+        } else {
+            Self::new(0.0, self.max.max(-self.min))
+        }
+    }
+
+    /// Range of a monotonically increasing function over the interval
+    pub fn map_increasing(self, f: impl Fn(f32) -> f32) -> Self {
+        Self::new(f(self.min), f(self.max))
+    }
+
+    pub fn region(self) -> Region {
+        if self.min >= 0.0 {
+            Region::Empty
+        } else if self.max < 0.0 {
+            Region::Full
+        } else {
+            Region::Surface
+        }
+    }
+}
+
+impl Add for Interval {
+    type Output = Interval;
+
+    fn add(self, other: Interval) -> Interval {
+        Interval::new(self.min + other.min, self.max + other.max)
+    }
+}
+
+impl Sub for Interval {
+    type Output = Interval;
+
+    fn sub(self, other: Interval) -> Interval {
+        Interval::new(self.min - other.max, self.max - other.min)
+    }
+}
+
+impl Mul for Interval {
+    type Output = Interval;
+
+    fn mul(self, other: Interval) -> Interval {
+        let products = [
+            self.min * other.min,
+            self.min * other.max,
+            self.max * other.min,
+            self.max * other.max,
+        ];
+        Interval::new(
+            products.iter().copied().fold(f32::INFINITY, f32::min),
+            products.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+        )
+    }
+}
+
+impl Neg for Interval {
+    type Output = Interval;
+
+    fn neg(self) -> Interval {
+        Interval::new(-self.max, -self.min)
+    }
+}
@@ -0,0 +1,506 @@
+use std::fmt;
+
+use crate::brush::{BrushOperation, BrushShape};
+use crate::edit::{Edit, EditError, EditLayer};
+use crate::format::{parse_graph, write_graph};
+
+/// Current version of the journal layout
+pub const JOURNAL_VERSION: u32 = 1;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"OCEJ");
+
+// Words before the first entry
+const HEADER_WORDS: usize = 6;
+
+/// One brush application and the chunks it changed
+#[derive(Clone)]
+pub struct JournalEntry {
+    pub edit: Edit,
+    pub chunks: Vec<[i32; 3]>,
+}
+
+/// Undoable history of the world's edits. Entries past `applied` were
+/// undone and come back with `redo` until a new edit replaces them.
+#[derive(Clone, Default)]
+pub struct Journal {
+    layer: EditLayer,
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// The edits currently applied, for meshing
+    pub fn layer(&self) -> &EditLayer {
+        &self.layer
+    }
+
+    /// Every recorded entry, undone ones included
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Number of entries currently applied
+    pub fn applied(&self) -> usize {
+        self.layer.edits().len()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.applied() > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.applied() < self.entries.len()
+    }
+
+    /// Applies an edit and drops the undone entries, returning the chunks to remesh
+    pub fn push(&mut self, edit: Edit) -> Result<Vec<[i32; 3]>, EditError> {
+        let chunks = self.layer.push(edit.clone())?;
+        self.entries.truncate(self.applied() - 1);
+        self.entries.push(JournalEntry {
+            edit,
+            chunks: chunks.clone(),
+        });
+        Ok(chunks)
+    }
+
+    /// Reverts the latest applied edit, returning the chunks to remesh
+    pub fn undo(&mut self) -> Option<Vec<[i32; 3]>> {
+        self.layer.pop().map(|(_, chunks)| chunks)
+    }
+
+    /// Reapplies the latest undone edit, returning the chunks to remesh
+    pub fn redo(&mut self) -> Option<Vec<[i32; 3]>> {
+        let entry = self.entries.get(self.applied())?;
+        Some(
+            self.layer
+                .push(entry.edit.clone())
+                .expect("journal entries were validated when recorded"),
+        )
+    }
+
+    /// Forgets every edit and the history, returning the chunks that had any
+    pub fn clear(&mut self) -> Vec<[i32; 3]> {
+        self.entries.clear();
+        self.layer.clear()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JournalError {
+    /// The data does not start with the journal magic
+    NotAJournal,
+    /// The journal was written by an unknown version
+    UnsupportedVersion(u32),
+    /// The journal was recorded on a different world generator, replaying
+    /// it would not reproduce the same terrain
+    GeneratorMismatch { expected: u64, found: u64 },
+    /// The journal ends before its header or an entry does
+    Truncated,
+    /// An entry cannot be decoded or is not a valid edit
+    Malformed(String),
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::NotAJournal => write!(f, "not an edit journal"),
+            JournalError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported edit journal version {} (expected {})",
+                version, JOURNAL_VERSION
+            ),
+            JournalError::GeneratorMismatch { expected, found } => write!(
+                f,
+                "edit journal was recorded on generator {:016x}, the active one is {:016x}",
+                found, expected
+            ),
+            JournalError::Truncated => write!(f, "edit journal is truncated"),
+            JournalError::Malformed(message) => write!(f, "malformed edit journal: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for JournalError {}
+
+/// Serializes a journal, undone entries included, as little-endian u32 words.
+/// `generator` is `generator_hash` of the world it was recorded on.
+///
+/// Header: magic "OCEJ", version, generator hash (low, high), entry count,
+/// applied count. Each entry is a shape tag and its parameters followed by
+/// an operation tag and its parameters, floats stored as their bits. An SDF
+/// shape stores its graph as the JSON world format, zero-padded to a word.
+pub fn write_journal(journal: &Journal, generator: u64) -> Vec<u8> {
+    let mut words = vec![
+        MAGIC,
+        JOURNAL_VERSION,
+        generator as u32,
+        (generator >> 32) as u32,
+        journal.entries.len() as u32,
+        journal.applied() as u32,
+    ];
+    let floats = |words: &mut Vec<u32>, values: &[f32]| {
+        words.extend(values.iter().map(|v| v.to_bits()));
+    };
+    for entry in &journal.entries {
+        match &entry.edit.shape {
+            BrushShape::Sphere { center, radius } => {
+                words.push(0);
+                floats(&mut words, &[center[0], center[1], center[2], *radius]);
+            }
+            BrushShape::Box {
+                center,
+                half_extents,
+            } => {
+                words.push(1);
+                floats(&mut words, center);
+                floats(&mut words, half_extents);
+            }
+            BrushShape::Cylinder { a, b, radius } => {
+                words.push(2);
+                floats(&mut words, a);
+                floats(&mut words, b);
+                floats(&mut words, &[*radius]);
+            }
+            BrushShape::Capsule { a, b, radius } => {
+                words.push(3);
+                floats(&mut words, a);
+                floats(&mut words, b);
+                floats(&mut words, &[*radius]);
+            }
+            BrushShape::Cone { base, tip, radius } => {
+                words.push(4);
+                floats(&mut words, base);
+                floats(&mut words, tip);
+                floats(&mut words, &[*radius]);
+            }
+            BrushShape::Sdf { graph, min, max } => {
+                words.push(5);
+                floats(&mut words, min);
+                floats(&mut words, max);
+                let json = write_graph(graph).into_bytes();
+                words.push(json.len() as u32);
+                words.extend(json.chunks(4).map(|bytes| {
+                    let mut word = [0; 4];
+                    word[..bytes.len()].copy_from_slice(bytes);
+                    u32::from_le_bytes(word)
+                }));
+            }
+        }
+        match entry.edit.operation {
+            BrushOperation::Add { blend } => {
+                words.push(0);
+                floats(&mut words, &[blend]);
+            }
+            BrushOperation::Subtract { blend } => {
+                words.push(1);
+                floats(&mut words, &[blend]);
+            }
+            BrushOperation::Flatten {
+                normal,
+                height,
+                blend,
+            } => {
+                words.push(2);
+                floats(&mut words, &normal);
+                floats(&mut words, &[height, blend]);
+            }
+            BrushOperation::Smooth { radius, blend } => {
+                words.push(3);
+                floats(&mut words, &[radius, blend]);
+            }
+            BrushOperation::Paint { color, blend } => {
+                words.extend([4, color]);
+                floats(&mut words, &[blend]);
+            }
+        }
+    }
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+/// Rebuilds a journal by replaying its entries in order, so the world comes
+/// back exactly as it was. `generator` is `generator_hash` of the active
+/// world, which must be the one the journal was recorded on.
+pub fn read_journal(bytes: &[u8], generator: u64) -> Result<Journal, JournalError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.word()? != MAGIC {
+        return Err(JournalError::NotAJournal);
+    }
+    let version = reader.word()?;
+    if version != JOURNAL_VERSION {
+        return Err(JournalError::UnsupportedVersion(version));
+    }
+    let found = reader.word()? as u64 | (reader.word()? as u64) << 32;
+    if found != generator {
+        return Err(JournalError::GeneratorMismatch {
+            expected: generator,
+            found,
+        });
+    }
+    let count = reader.word()? as usize;
+    let applied = reader.word()? as usize;
+    if applied > count {
+        return Err(JournalError::Malformed(format!(
+            "{} of {} entries applied",
+            applied, count
+        )));
+    }
+    // Every entry takes at least three words, so the count cannot be bogus
+    if count > (bytes.len() / 4).saturating_sub(HEADER_WORDS) / 3 {
+        return Err(JournalError::Truncated);
+    }
+
+    let mut journal = Journal::default();
+    for index in 0..count {
+        let edit = Edit::new(reader.shape()?, reader.operation()?);
+        journal
+            .push(edit)
+            .map_err(|e| JournalError::Malformed(format!("entry {}: {}", index, e)))?;
+    }
+    if reader.offset != bytes.len() {
+        return Err(JournalError::Malformed(
+            "data after the last entry".to_string(),
+        ));
+    }
+    for _ in applied..count {
+        journal.undo();
+    }
+    Ok(journal)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn word(&mut self) -> Result<u32, JournalError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + 4)
+            .ok_or(JournalError::Truncated)?;
+        self.offset += 4;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn float(&mut self) -> Result<f32, JournalError> {
+        self.word().map(f32::from_bits)
+    }
+
+    fn vector(&mut self) -> Result<[f32; 3], JournalError> {
+        Ok([self.float()?, self.float()?, self.float()?])
+    }
+
+    fn shape(&mut self) -> Result<BrushShape, JournalError> {
+        Ok(match self.word()? {
+            0 => BrushShape::Sphere {
+                center: self.vector()?,
+                radius: self.float()?,
+            },
+            1 => BrushShape::Box {
+                center: self.vector()?,
+                half_extents: self.vector()?,
+            },
+            2 => BrushShape::Cylinder {
+                a: self.vector()?,
+                b: self.vector()?,
+                radius: self.float()?,
+            },
+            3 => BrushShape::Capsule {
+                a: self.vector()?,
+                b: self.vector()?,
+                radius: self.float()?,
+            },
+            4 => BrushShape::Cone {
+                base: self.vector()?,
+                tip: self.vector()?,
+                radius: self.float()?,
+            },
+            5 => {
+                let (min, max) = (self.vector()?, self.vector()?);
+                let length = self.word()? as usize;
+                let json = self
+                    .bytes
+                    .get(self.offset..self.offset.saturating_add(length))
+                    .ok_or(JournalError::Truncated)?;
+                self.offset += length.next_multiple_of(4);
+                if self.offset > self.bytes.len() {
+                    return Err(JournalError::Truncated);
+                }
+                let json = std::str::from_utf8(json)
+                    .map_err(|_| JournalError::Malformed("SDF graph is not UTF-8".to_string()))?;
+                let graph = parse_graph(json)
+                    .map_err(|e| JournalError::Malformed(format!("SDF graph: {}", e)))?;
+                BrushShape::Sdf { graph, min, max }
+            }
+            other => return Err(JournalError::Malformed(format!("shape {}", other))),
+        })
+    }
+
+    fn operation(&mut self) -> Result<BrushOperation, JournalError> {
+        Ok(match self.word()? {
+            0 => BrushOperation::Add {
+                blend: self.float()?,
+            },
+            1 => BrushOperation::Subtract {
+                blend: self.float()?,
+            },
+            2 => BrushOperation::Flatten {
+                normal: self.vector()?,
+                height: self.float()?,
+                blend: self.float()?,
+            },
+            3 => BrushOperation::Smooth {
+                radius: self.float()?,
+                blend: self.float()?,
+            },
+            4 => BrushOperation::Paint {
+                color: self.word()?,
+                blend: self.float()?,
+            },
+            other => return Err(JournalError::Malformed(format!("operation {}", other))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{DensityGraph, Node};
+
+    const GENERATOR: u64 = 0x0123_4567_89ab_cdef;
+
+    // Every shape and operation, the last two undone
+    fn journal() -> Journal {
+        let mut graph = DensityGraph::default();
+        graph
+            .push(Node::Sphere {
+                center: [40.0, 0.0, 0.0],
+                radius: 12.0,
+            })
+            .unwrap();
+        let edits = [
+            Edit::new(
+                BrushShape::Sphere {
+                    center: [0.0, 10.0, 0.0],
+                    radius: 8.0,
+                },
+                BrushOperation::Add { blend: 2.0 },
+            ),
+            Edit::new(
+                BrushShape::Box {
+                    center: [300.0, 0.0, -20.0],
+                    half_extents: [4.0, 6.0, 8.0],
+                },
+                BrushOperation::Flatten {
+                    normal: [0.0, 1.0, 0.0],
+                    height: 1.5,
+                    blend: 0.0,
+                },
+            ),
+            Edit::new(
+                BrushShape::Cylinder {
+                    a: [0.0; 3],
+                    b: [0.0, 20.0, 0.0],
+                    radius: 5.0,
+                },
+                BrushOperation::Smooth {
+                    radius: 3.0,
+                    blend: 1.0,
+                },
+            ),
+            Edit::new(
+                BrushShape::Capsule {
+                    a: [-10.0, 0.0, 0.0],
+                    b: [10.0, 0.0, 0.0],
+                    radius: 3.0,
+                },
+                BrushOperation::Paint {
+                    color: 0xff00ff,
+                    blend: 4.0,
+                },
+            ),
+            Edit::new(
+                BrushShape::Cone {
+                    base: [0.0; 3],
+                    tip: [0.0, 30.0, 0.0],
+                    radius: 10.0,
+                },
+                BrushOperation::Subtract { blend: 0.0 },
+            ),
+            Edit::new(
+                BrushShape::Sdf {
+                    graph,
+                    min: [28.0, -12.0, -12.0],
+                    max: [52.0, 12.0, 12.0],
+                },
+                BrushOperation::Add { blend: 1.0 },
+            ),
+        ];
+        let mut journal = Journal::default();
+        for edit in edits {
+            journal.push(edit).unwrap();
+        }
+        journal.undo().unwrap();
+        journal.undo().unwrap();
+        journal
+    }
+
+    #[test]
+    fn round_trip_keeps_undone_entries() {
+        let mut journal = journal();
+        let bytes = write_journal(&journal, GENERATOR);
+        let mut read = read_journal(&bytes, GENERATOR).unwrap();
+        assert_eq!(write_journal(&read, GENERATOR), bytes);
+        assert_eq!(read.entries().len(), 6);
+        assert_eq!(read.applied(), 4);
+        assert_eq!(
+            read.layer().edited_chunks(),
+            journal.layer().edited_chunks()
+        );
+        for (read, entry) in read.entries().iter().zip(journal.entries()) {
+            assert_eq!(read.chunks, entry.chunks);
+        }
+
+        // The undone entries redo as they would have before saving
+        while journal.can_redo() {
+            assert_eq!(read.redo(), journal.redo());
+        }
+        assert!(!read.can_redo());
+        assert_eq!(
+            write_journal(&read, GENERATOR),
+            write_journal(&journal, GENERATOR)
+        );
+    }
+
+    #[test]
+    fn damaged_journals_are_rejected() {
+        let bytes = write_journal(&journal(), GENERATOR);
+        for length in [0, 3, HEADER_WORDS * 4, bytes.len() - 4] {
+            assert_eq!(
+                read_journal(&bytes[..length], GENERATOR).err(),
+                Some(JournalError::Truncated)
+            );
+        }
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] ^= 1;
+        assert_eq!(
+            read_journal(&wrong_magic, GENERATOR).err(),
+            Some(JournalError::NotAJournal)
+        );
+
+        assert_eq!(
+            read_journal(&bytes, !GENERATOR).err(),
+            Some(JournalError::GeneratorMismatch {
+                expected: !GENERATOR,
+                found: GENERATOR,
+            })
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.extend([0; 4]);
+        assert!(matches!(
+            read_journal(&trailing, GENERATOR),
+            Err(JournalError::Malformed(_))
+        ));
+    }
+}
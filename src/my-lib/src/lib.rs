@@ -1,136 +1,467 @@
-extern crate wasm_bindgen;
+//! Voxel terrain: density generation, meshing and mesh serialization.
+//!
+//! The Rust API works on any target. The `wasm` feature (on by default)
+//! adds the wasm-bindgen layer the browser client calls, native tools build
+//! with `default-features = false`.
 
-use js_sys::{Float32Array, Uint16Array, Uint32Array};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::wasm_bindgen;
-use wasm_bindgen::JsValue;
 
+use crate::interval::Region;
+
+#[cfg(feature = "wasm")]
+mod arena;
+mod blob;
+mod bounds;
+mod brush;
+mod cache;
+mod dual;
+mod edit;
+mod export;
+mod format;
+mod graph;
+mod interval;
+mod journal;
 mod mesh;
 mod noise;
+mod octree;
+mod packed;
+mod parallel;
+mod perlin;
+mod simd;
+mod simplex;
+mod skirt;
+mod transition;
+#[cfg(feature = "wasm")]
+mod wasm;
 
-#[wasm_bindgen]
-pub fn get_memory() -> JsValue {
-    wasm_bindgen::memory()
-}
+pub use blob::{read_blob, write_blob, BlobError, BLOB_VERSION};
+pub use bounds::BOUNDS_STRIDE;
+pub use brush::{BrushOperation, BrushShape, MAX_BLEND, MAX_BRUSH_EXTENT, MAX_SMOOTH_RADIUS};
+pub use cache::{DirectoryStore, FieldStore, VoxelCache, VoxelKey, VOXEL_CACHE_VERSION};
+pub use edit::{Edit, EditError, EditLayer, Edited};
+pub use export::{write_glb, write_obj};
+pub use format::{generator_hash, parse_graph, write_graph, FormatError, FORMAT_VERSION};
+pub use graph::{
+    DensityGraph, FractalMode, GraphError, Node, NodeId, MAX_EVAL_COST, MAX_GRAPH_NODES,
+};
+pub use interval::Interval;
+pub use journal::{
+    read_journal, write_journal, Journal, JournalEntry, JournalError, JOURNAL_VERSION,
+};
+pub use mesh::{
+    Chunk, Command, IndexFormat, MeshOptions, MeshPatch, Mesher, PatchedDraw, VertexFormat,
+};
+pub use noise::{
+    noise_for_chunk, noise_for_region, only_noise_for_chunk, Density, DensityField, NoiseKind,
+    TerrainParams, SIZE,
+};
+pub use skirt::Skirt;
 
-#[wasm_bindgen]
+/// Element counts of a chunk's buffers (the `MeshResult` arrays in JS) and
+/// how to decode the vertices.
+/// Packed positions decode as `position_origin + q * position_scale`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy)]
 pub struct MeshMetadata {
+    pub vertex_format: VertexFormat,
+    pub vertex_count: usize,
     pub vertices_length: usize,
     pub normals_length: usize,
     pub colors_length: usize,
     pub material_colors_length: usize,
+    pub packed_vertices_length: usize,
+    pub position_origin_x: f32,
+    pub position_origin_y: f32,
+    pub position_origin_z: f32,
+    pub position_scale: f32,
     pub commands_length: usize,
+    pub split_from_length: usize,
+    pub bounds_length: usize,
     pub densities_length: usize,
     pub vertex_counts_length: usize,
-    pub indices_length: usize,
+    pub index_format: IndexFormat,
+    pub indices_length: usize, // Whichever of indices and indices_u32 is filled
 }
 
-#[wasm_bindgen]
+/// Byte sizes of a chunk's buffers, for sizing GPU buffers
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy)]
 pub struct BufferSizes {
+    pub vertex_stride_bytes: usize,
     pub vertices_bytes: usize,
     pub normals_bytes: usize,
     pub colors_bytes: usize,
     pub material_colors_bytes: usize,
+    pub packed_vertices_bytes: usize,
     pub commands_bytes: usize,
+    pub split_from_bytes: usize,
+    pub bounds_bytes: usize,
     pub densities_bytes: usize,
     pub vertex_counts_bytes: usize,
     pub indices_bytes: usize,
 }
 
-// Generate mesh and return typed arrays directly (wasm-bindgen handles efficient transfer)
-#[wasm_bindgen]
-pub struct MeshResult {
-    vertices: Float32Array,
-    normals: Float32Array,
-    colors: Uint32Array,
-    material_colors: Uint32Array,
-    commands: Uint32Array,
-    densities: Uint32Array,
-    vertex_counts: Uint32Array,
-    indices: Uint16Array,
+fn lod_resolution(lod: u32) -> (u32, f32) {
+    let scale = 2_u32.pow(lod) as f32;
+    let resolution = (256.0 / scale) as u32;
+    (resolution, scale)
 }
 
-#[wasm_bindgen]
-impl MeshResult {
-    #[wasm_bindgen(getter)]
-    pub fn vertices(&self) -> Float32Array {
-        self.vertices.clone()
-    }
-    #[wasm_bindgen(getter)]
-    pub fn normals(&self) -> Float32Array {
-        self.normals.clone()
+/// The default world: the built-in terrain as the only node
+pub fn default_generator() -> DensityGraph {
+    let mut graph = DensityGraph::default();
+    graph
+        .push(Node::Terrain(TerrainParams::default()))
+        .expect("terrain node has no inputs");
+    graph
+}
+
+/// Samples and meshes chunk `(x, y, z)` at `lod` (256 >> lod cells per axis)
+pub fn mesh_chunk<D: Density + ?Sized>(
+    density: &D,
+    x: i32,
+    y: i32,
+    z: i32,
+    lod: u32,
+    options: MeshOptions,
+) -> Chunk {
+    let (resolution, scale) = lod_resolution(lod);
+
+    // Chunks entirely above or below the surface skip sampling and meshing,
+    // only the empty per-meshlet draws are returned
+    let min = [x * SIZE, y * SIZE, z * SIZE].map(|v| v as f32);
+    let max = min.map(|v| v + SIZE as f32);
+    if density.bounds(min, max).region() != Region::Surface {
+        return Chunk::empty([x, y, z], resolution, options);
     }
-    #[wasm_bindgen(getter)]
-    pub fn colors(&self) -> Uint32Array {
-        self.colors.clone()
+
+    mesh::generate_mesh(density, x, y, z, resolution, scale, options, None)
+}
+
+/// Same as `mesh_chunk`, but the chunk's voxels come from `cache` when it
+/// has them and stay there for the next call, e.g. at another LOD.
+/// `generator` is `generator_hash` of the world `density` samples, chunks
+/// with edits must not go through the cache. The dual meshers sample their
+/// own bricks and always go to `mesh_chunk`.
+#[allow(clippy::too_many_arguments)]
+pub fn mesh_chunk_cached<D: Density + ?Sized>(
+    cache: &mut VoxelCache,
+    density: &D,
+    generator: u64,
+    x: i32,
+    y: i32,
+    z: i32,
+    lod: u32,
+    options: MeshOptions,
+) -> Chunk {
+    let (resolution, scale) = lod_resolution(lod);
+    let min = [x * SIZE, y * SIZE, z * SIZE].map(|v| v as f32);
+    let max = min.map(|v| v + SIZE as f32);
+    if options.mesher != Mesher::MarchingCubes
+        || density.bounds(min, max).region() != Region::Surface
+    {
+        return mesh_chunk(density, x, y, z, lod, options);
     }
-    #[wasm_bindgen(getter)]
-    pub fn material_colors(&self) -> Uint32Array {
-        self.material_colors.clone()
+
+    let key = VoxelKey {
+        x,
+        y,
+        z,
+        lod,
+        generator,
+    };
+    let octree = cache.octree(density, key);
+    mesh::generate_mesh(density, x, y, z, resolution, scale, options, Some(octree))
+}
+
+/// Remeshes the part of a chunk from `mesh_chunk` that samples the box
+/// `min..=max` after the density changed there, e.g. by an edit (see
+/// `Edit::reach`), and writes it into the chunk's buffers. The other
+/// arguments must match the `mesh_chunk` call. Returns None when the chunk
+/// has to be meshed again as a whole.
+#[allow(clippy::too_many_arguments)]
+pub fn remesh_chunk<D: Density + ?Sized>(
+    chunk: &mut Chunk,
+    density: &D,
+    x: i32,
+    y: i32,
+    z: i32,
+    lod: u32,
+    options: MeshOptions,
+    min: [f32; 3],
+    max: [f32; 3],
+) -> Option<MeshPatch> {
+    let (resolution, scale) = lod_resolution(lod);
+    mesh::remesh_region(
+        chunk, density, x, y, z, resolution, scale, options, min, max,
+    )
+}
+
+pub fn metadata(chunk: &Chunk) -> MeshMetadata {
+    let origin = chunk.position_origin();
+    MeshMetadata {
+        vertex_format: chunk.vertex_format(),
+        vertex_count: chunk.vertex_count(),
+        vertices_length: chunk.vertices_len(),
+        normals_length: chunk.normals_len(),
+        colors_length: chunk.colors_len(),
+        material_colors_length: chunk.material_colors_len(),
+        packed_vertices_length: chunk.packed_vertices_len(),
+        position_origin_x: origin[0],
+        position_origin_y: origin[1],
+        position_origin_z: origin[2],
+        position_scale: 1.0 / packed::POSITION_STEPS,
+        commands_length: chunk.commands_len() * 5, // 5 u32s per command (DrawIndexedIndirect)
+        split_from_length: chunk.split_from_len(),
+        bounds_length: chunk.bounds_len(),
+        densities_length: chunk.density_len(),
+        vertex_counts_length: chunk.vertex_counts_len(),
+        index_format: chunk.index_format(),
+        indices_length: match chunk.index_format() {
+            IndexFormat::Uint16 => chunk.indices_len(),
+            IndexFormat::Uint32 => chunk.indices_u32_len(),
+        },
     }
-    #[wasm_bindgen(getter)]
-    pub fn commands(&self) -> Uint32Array {
-        self.commands.clone()
+}
+
+pub fn buffer_sizes(metadata: &MeshMetadata) -> BufferSizes {
+    BufferSizes {
+        vertex_stride_bytes: match metadata.vertex_format {
+            // Position and normal vec4<f32>, material and lit colour
+            VertexFormat::Float => 40,
+            VertexFormat::Packed => packed::PACKED_WORDS * 4,
+        },
+        vertices_bytes: metadata.vertices_length * 4,
+        normals_bytes: metadata.normals_length * 4,
+        colors_bytes: metadata.colors_length * 4,
+        material_colors_bytes: metadata.material_colors_length * 4,
+        packed_vertices_bytes: metadata.packed_vertices_length * 4,
+        commands_bytes: metadata.commands_length * 4,
+        split_from_bytes: metadata.split_from_length * 4,
+        bounds_bytes: metadata.bounds_length * 4,
+        densities_bytes: metadata.densities_length * 4,
+        vertex_counts_bytes: metadata.vertex_counts_length * 4,
+        indices_bytes: match metadata.index_format {
+            IndexFormat::Uint16 => metadata.indices_length * 2,
+            IndexFormat::Uint32 => metadata.indices_length * 4,
+        },
     }
-    #[wasm_bindgen(getter)]
-    pub fn densities(&self) -> Uint32Array {
-        self.densities.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Chunks coarser than one brick have no cells, they mesh to nothing
+    #[test]
+    fn lods_coarser_than_a_brick_mesh_empty() {
+        let generator = default_generator();
+        let hash = generator_hash(&generator);
+        let mut cache = VoxelCache::new(usize::MAX);
+        for lod in 5..=8 {
+            for mesher in [
+                Mesher::MarchingCubes,
+                Mesher::SurfaceNets,
+                Mesher::DualContouring,
+            ] {
+                let options = MeshOptions {
+                    mesher,
+                    skirt: Some(Skirt::Inward),
+                    ..MeshOptions::default()
+                };
+                let mut chunk = mesh_chunk(&generator, 0, 0, 0, lod, options);
+                let cached = mesh_chunk_cached(&mut cache, &generator, hash, 0, 0, 0, lod, options);
+                assert_eq!(write_blob(&chunk), write_blob(&cached));
+                if lod > 5 {
+                    assert_eq!(chunk.vertex_count(), 0);
+                }
+                let (min, max) = ([0.0; 3], [SIZE as f32; 3]);
+                let patch = remesh_chunk(&mut chunk, &generator, 0, 0, 0, lod, options, min, max);
+                assert!(patch.is_some());
+            }
+        }
     }
-    #[wasm_bindgen(getter)]
-    pub fn vertex_counts(&self) -> Uint32Array {
-        self.vertex_counts.clone()
+
+    // Vertex data of every triangle a draw renders, in draw order
+    fn draw_triangles(chunk: &Chunk, draw: usize) -> Vec<[u32; 9]> {
+        let [count, _, first, base, _] = chunk.commands[draw].to_words();
+        let indices = &chunk.indices_u32[first as usize..(first + count) as usize];
+        let vertex = |index: u32| {
+            let v = (index + base) as usize;
+            let position = &chunk.vertices[v * 4..v * 4 + 3];
+            let normal = &chunk.normals[v * 4..v * 4 + 3];
+            position
+                .iter()
+                .chain(normal)
+                .map(|f| f.to_bits())
+                .chain([chunk.colors[v], chunk.material_colors[v]])
+                .collect::<Vec<_>>()
+        };
+        indices
+            .chunks(3)
+            .map(|triangle| {
+                let words: Vec<u32> = triangle.iter().flat_map(|i| vertex(*i)).collect();
+                std::array::from_fn(|i| words[i])
+            })
+            .collect()
     }
-    #[wasm_bindgen(getter)]
-    pub fn indices(&self) -> Uint16Array {
-        self.indices.clone()
+
+    // Patching the meshlets an edit touches gives the draws meshing the edited chunk
+    // again would
+    #[test]
+    fn remesh_patch_matches_full_remesh() {
+        // A tilted plane keeps the full meshes cheap, the terrain samples most of the chunk
+        let mut generator = DensityGraph::default();
+        generator
+            .push(Node::Plane {
+                normal: [0.36, 0.8, 0.48],
+                height: 100.0,
+            })
+            .unwrap();
+        for lod in 0..=2u32 {
+            for mesher in [Mesher::MarchingCubes, Mesher::SurfaceNets] {
+                let options = MeshOptions {
+                    mesher,
+                    skirt: Some(Skirt::Inward),
+                    neighbor_lods: [lod.saturating_sub(1), 255, 255, 255, 255, lod + 1],
+                    index_format: IndexFormat::Uint32,
+                    vertex_format: VertexFormat::Float,
+                };
+                let mut chunk = mesh_chunk(&generator, 0, 0, 0, lod, options);
+                // Carve into the surface near the -X face, where the seam is
+                let v = (0..chunk.vertex_count())
+                    .min_by_key(|v| {
+                        chunk.vertices[v * 4] as i32
+                            + (chunk.vertices[v * 4 + 2] as i32 - 128).abs()
+                    })
+                    .unwrap();
+                let center = [0, 1, 2].map(|axis| chunk.vertices[v * 4 + axis]);
+                let edit = Edit::new(
+                    BrushShape::Sphere {
+                        center,
+                        radius: 24.0,
+                    },
+                    BrushOperation::Subtract { blend: 2.0 },
+                );
+                let (min, max) = edit.reach();
+                let mut layer = EditLayer::default();
+                layer.push(edit).unwrap();
+                let edited = Edited::new(&generator, &layer, 0, 0, 0);
+
+                let patch =
+                    remesh_chunk(&mut chunk, &edited, 0, 0, 0, lod, options, min, max).unwrap();
+                assert!(!patch.draws.is_empty());
+                let full = mesh_chunk(&edited, 0, 0, 0, lod, options);
+                assert_eq!(chunk.densities, full.densities);
+                assert_eq!(chunk.vertex_counts, full.vertex_counts);
+                assert_eq!(chunk.bounds, full.bounds);
+                assert_eq!(chunk.split_from, full.split_from);
+                assert_eq!(chunk.commands.len(), full.commands.len());
+                for draw in 0..full.commands.len() {
+                    assert_eq!(draw_triangles(&chunk, draw), draw_triangles(&full, draw));
+                }
+            }
+        }
     }
-}
 
-#[wasm_bindgen]
-pub fn generate_mesh(x: i32, y: i32, z: i32, lod: u32, neighbor_lods: Vec<u32>) -> MeshResult {
-    let scale = 2_u32.pow(lod) as f32;
-    let resolution = (256.0 / scale) as u32;
+    // A coarse chunk between two finer ones: on both faces they share, every edge of
+    // the chunks' own triangles and the transition fill belongs to exactly two triangles
+    #[test]
+    fn transition_seams_are_closed() {
+        use std::collections::HashMap;
 
-    // neighbor_lods order: -X, +X, -Y, +Y, -Z, +Z (use 255 for no neighbor)
-    let neighbor_lods_array: [u32; 6] = if neighbor_lods.len() >= 6 {
-        [
-            neighbor_lods[0],
-            neighbor_lods[1],
-            neighbor_lods[2],
-            neighbor_lods[3],
-            neighbor_lods[4],
-            neighbor_lods[5],
-        ]
-    } else {
-        [255, 255, 255, 255, 255, 255] // Default: no neighbors
-    };
+        let mut generator = DensityGraph::default();
+        generator
+            .push(Node::Capsule {
+                a: [-60.0, 128.0, 120.0],
+                b: [316.0, 136.0, 128.0],
+                radius: 70.0,
+            })
+            .unwrap();
+        let options = |neighbor_lods| MeshOptions {
+            neighbor_lods,
+            index_format: IndexFormat::Uint32,
+            ..MeshOptions::default()
+        };
+        let chunks = [
+            mesh_chunk(
+                &generator,
+                -1,
+                0,
+                0,
+                1,
+                options([255, 2, 255, 255, 255, 255]),
+            ),
+            mesh_chunk(&generator, 0, 0, 0, 2, options([1, 1, 255, 255, 255, 255])),
+            mesh_chunk(
+                &generator,
+                1,
+                0,
+                0,
+                1,
+                options([2, 255, 255, 255, 255, 255]),
+            ),
+        ];
+
+        // Chunks compute shared vertices separately, so they are welded within a tolerance
+        let mut welded: Vec<[f32; 3]> = Vec::new();
+        let mut cells: HashMap<[i32; 3], Vec<usize>> = HashMap::new();
+        let mut weld = |p: [f32; 3]| {
+            let cell = p.map(|v| (v * 64.0).floor() as i32);
+            for dz in -1..=1 {
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let near = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                        for &id in cells.get(&near).into_iter().flatten() {
+                            if (0..3).all(|axis| (welded[id][axis] - p[axis]).abs() < 1e-3) {
+                                return id;
+                            }
+                        }
+                    }
+                }
+            }
+            welded.push(p);
+            cells.entry(cell).or_default().push(welded.len() - 1);
+            welded.len() - 1
+        };
+
+        let mut edges: HashMap<(usize, usize), u32> = HashMap::new();
+        let mut triangles = 0;
+        for chunk in &chunks {
+            for command in &chunk.commands {
+                let [count, _, first, base, _] = command.to_words();
+                for triangle in
+                    chunk.indices_u32[first as usize..(first + count) as usize].chunks(3)
+                {
+                    let ids: [usize; 3] = std::array::from_fn(|corner| {
+                        let v = (triangle[corner] + base) as usize;
+                        weld([0, 1, 2].map(|axis| chunk.vertices[v * 4 + axis]))
+                    });
+                    if ids[0] == ids[1] || ids[1] == ids[2] || ids[2] == ids[0] {
+                        continue;
+                    }
+                    for (a, b) in [(ids[0], ids[1]), (ids[1], ids[2]), (ids[2], ids[0])] {
+                        *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+                    }
+                    triangles += 1;
+                }
+            }
+        }
+        assert!(triangles > 0);
 
-    let chunk = mesh::generate_mesh(x, y, z, resolution, scale, neighbor_lods_array);
-
-    unsafe {
-        // Create JS-owned copies of the data (not views into WASM memory)
-        let vertices_slice = std::slice::from_raw_parts(chunk.vertices(), chunk.vertices_len());
-        let normals_slice = std::slice::from_raw_parts(chunk.normals(), chunk.normals_len());
-        let colors_slice = std::slice::from_raw_parts(chunk.colors(), chunk.colors_len());
-        let material_colors_slice =
-            std::slice::from_raw_parts(chunk.material_colors(), chunk.material_colors_len());
-        let commands_slice = std::slice::from_raw_parts(
-            chunk.commands() as *const u32,
-            chunk.commands_len() * 5, // 5 u32s per command (DrawIndexedIndirect)
-        );
-        let densities_slice = std::slice::from_raw_parts(chunk.densities(), chunk.density_len());
-        let vertex_counts_slice =
-            std::slice::from_raw_parts(chunk.vertex_counts(), chunk.vertex_counts_len());
-        let indices_slice = std::slice::from_raw_parts(chunk.indices(), chunk.indices_len());
-
-        MeshResult {
-            vertices: Float32Array::from(vertices_slice),
-            normals: Float32Array::from(normals_slice),
-            colors: Uint32Array::from(colors_slice),
-            material_colors: Uint32Array::from(material_colors_slice),
-            commands: Uint32Array::from(commands_slice),
-            densities: Uint32Array::from(densities_slice),
-            vertex_counts: Uint32Array::from(vertex_counts_slice),
-            indices: Uint16Array::from(indices_slice),
+        let mut seam_edges = 0;
+        for (&(a, b), &count) in &edges {
+            let on_face = |plane: f32| {
+                [a, b]
+                    .iter()
+                    .all(|id| (welded[*id][0] - plane).abs() < 1e-3)
+            };
+            if on_face(0.0) || on_face(SIZE as f32) {
+                assert_eq!(count, 2, "edge {:?} {:?}", welded[a], welded[b]);
+                seam_edges += 1;
+            }
         }
+        assert!(seam_edges > 0);
     }
 }
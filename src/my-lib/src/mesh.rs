@@ -1,5 +1,14 @@
-use crate::noise::{only_noise_for_chunk, SIZE};
+use crate::bounds::{MeshletBounds, BOUNDS_STRIDE};
+use crate::dual::dual_brick;
+use crate::noise::{Density, SIZE};
+use crate::octree::{sample_brick, Brick, BrickRef, VoxelOctree, BRICK_SIZE};
+use crate::packed::{pack_vertex, position_origin, PACKED_WORDS};
+use crate::parallel::map_init;
+use crate::skirt::{Skirt, Skirts};
+use crate::transition::Seams;
 use rustc_hash::FxHashMap;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
 
 const EDGE_TABLE_DATA: [u32; 256] = [
     0x0, 0x109, 0x203, 0x30a, 0x406, 0x50f, 0x605, 0x70c, 0x80c, 0x905, 0xa0f, 0xb06, 0xc0a, 0xd03,
@@ -179,57 +188,14 @@ const TRIANGLE_TABLE_DATA: [i32; 4096] = [
     -1, -1,
 ];
 
-// Voxel data structure containing density, color, and pre-computed gradient
-//#[repr(C, packed)]
-#[derive(Clone, Copy)]
-struct VoxelData {
-    density: f32,
-    color: u32,         // Packed RGBA color
-    gradient: [f32; 3], // Pre-computed normal
-}
-
-fn get_voxel_data(voxels: &[VoxelData], pos: [u32; 3], grid_size: u32) -> VoxelData {
-    // Use (grid_size + 1)³ grid size (with border voxels)
-    let size = grid_size + 1;
-    let index = (pos[2] * size * size + pos[1] * size + pos[0]) as usize;
-    voxels[index]
-}
+// Most vertices a single draw can address with u16 indices
+const MAX_U16_VERTICES: usize = u16::MAX as usize + 1;
 
-// Combined getters to reduce indexing overhead
-fn get_voxel_all_safe(voxels: &[VoxelData], pos: [i32; 3], grid_size: u32) -> (f32, u32, [f32; 3]) {
-    let size = (grid_size + 1) as i32;
-    if pos[0] < 0 || pos[1] < 0 || pos[2] < 0 || pos[0] >= size || pos[1] >= size || pos[2] >= size
-    {
-        return (1.0, 0x808080FF, [0.0, 1.0, 0.0]); // Outside bounds defaults
-    }
-    let data = get_voxel_data(
-        voxels,
-        [pos[0] as u32, pos[1] as u32, pos[2] as u32],
-        grid_size,
-    );
-    (data.density, data.color, data.gradient)
-}
-
-fn get_voxel_density(voxels: &[VoxelData], pos: [u32; 3], grid_size: u32) -> f32 {
-    get_voxel_data(voxels, pos, grid_size).density
-}
-
-fn get_voxel_density_safe(voxels: &[VoxelData], pos: [i32; 3], grid_size: u32) -> f32 {
-    // Allow access to (grid_size + 1)³ grid (0 to grid_size) for border voxels
-    let size = (grid_size + 1) as i32;
-    if pos[0] < 0 || pos[1] < 0 || pos[2] < 0 || pos[0] >= size || pos[1] >= size || pos[2] >= size
-    {
-        return 0.0; // Outside bounds = air
-    }
-    get_voxel_density(
-        voxels,
-        [pos[0] as u32, pos[1] as u32, pos[2] as u32],
-        grid_size,
-    )
-}
+// COMPRESSION must be constant 8 to maintain meshlet structure (one octree brick per meshlet)
+const COMPRESSION: u32 = BRICK_SIZE;
 
 // Cube vertex positions (8 corners of a unit cube)
-const CUBE_VERTICES: [[f32; 3]; 8] = [
+pub(crate) const CUBE_VERTICES: [[f32; 3]; 8] = [
     [0.0, 0.0, 0.0], // 0
     [1.0, 0.0, 0.0], // 1
     [1.0, 1.0, 0.0], // 2
@@ -241,7 +207,7 @@ const CUBE_VERTICES: [[f32; 3]; 8] = [
 ];
 
 // Edge connections for interpolation
-const EDGE_VERTICES: [[usize; 2]; 12] = [
+pub(crate) const EDGE_VERTICES: [[usize; 2]; 12] = [
     [0, 1],
     [1, 2],
     [2, 3],
@@ -256,7 +222,7 @@ const EDGE_VERTICES: [[usize; 2]; 12] = [
     [3, 7],
 ];
 
-fn interpolate_vertex(p1: [f32; 3], p2: [f32; 3], val1: f32, val2: f32) -> [f32; 3] {
+pub(crate) fn interpolate_vertex(p1: [f32; 3], p2: [f32; 3], val1: f32, val2: f32) -> [f32; 3] {
     // Optimized: isolevel is always 0.0, so mu = (0.0 - val1) / (val2 - val1) = -val1 / (val2 - val1)
     let delta = val2 - val1;
     if delta.abs() < 0.00001 {
@@ -278,7 +244,7 @@ fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
 }
 
 /// Calculate color based on height and slope for realistic terrain materials
-fn calculate_terrain_color(world_pos: [f32; 3], normal: [f32; 3]) -> u32 {
+pub(crate) fn calculate_terrain_color(world_pos: [f32; 3], normal: [f32; 3]) -> u32 {
     // Calculate slope from normal's Y component (1.0 = flat, 0.0 = vertical)
     let flatness = normal[1].abs();
     let slope = (1.0 - flatness).clamp(0.0, 1.0);
@@ -311,10 +277,6 @@ fn calculate_terrain_color(world_pos: [f32; 3], normal: [f32; 3]) -> u32 {
     color.1 = sand.1 * sand_w + grass.1 * grass_w + rock.1 * rock_w + snow.1 * snow_w;
     color.2 = sand.2 * sand_w + grass.2 * grass_w + rock.2 * rock_w + snow.2 * snow_w;
 
-    // Add subtle spatial variation (using continuous fractional calculation to avoid banding)
-    let noise_seed = ((world_pos[0] * 0.3 + world_pos[2] * 0.7) * 0.05882).fract(); // 0.05882 ≈ 1/17
-    let variation = 0.92 + noise_seed * 0.16; // 0.92 to 1.08
-
     let r = (color.0).clamp(0.0, 255.0) as u32;
     let g = (color.1).clamp(0.0, 255.0) as u32;
     let b = (color.2).clamp(0.0, 255.0) as u32;
@@ -323,7 +285,7 @@ fn calculate_terrain_color(world_pos: [f32; 3], normal: [f32; 3]) -> u32 {
     0xFF000000 | (b << 16) | (g << 8) | r
 }
 
-fn interpolate_normal(n1: [f32; 3], n2: [f32; 3], val1: f32, val2: f32) -> [f32; 3] {
+pub(crate) fn interpolate_normal(n1: [f32; 3], n2: [f32; 3], val1: f32, val2: f32) -> [f32; 3] {
     // Optimized: isolevel is always 0.0, inlined normalization, removed redundant checks
     let delta = val2 - val1;
     if delta.abs() < 0.00001 {
@@ -359,95 +321,10 @@ fn interpolate_normal(n1: [f32; 3], n2: [f32; 3], val1: f32, val2: f32) -> [f32;
     }
 }
 
-fn calculate_gradient(voxels: &[VoxelData], pos: [i32; 3], grid_size: u32) -> [f32; 3] {
-    let max_pos = grid_size as i32;
-
-    // Use one-sided differences at boundaries, central differences in interior
-    // One-sided differences are scaled by 2x to match central difference magnitude
-
-    // X gradient
-    let dx = if pos[0] == 0 {
-        // Forward difference at min boundary (scaled 2x)
-        2.0 * (get_voxel_density(voxels, [1, pos[1] as u32, pos[2] as u32], grid_size)
-            - get_voxel_density(voxels, [0, pos[1] as u32, pos[2] as u32], grid_size))
-    } else if pos[0] == max_pos {
-        // Backward difference at max boundary (scaled 2x)
-        2.0 * (get_voxel_density(
-            voxels,
-            [max_pos as u32, pos[1] as u32, pos[2] as u32],
-            grid_size,
-        ) - get_voxel_density(
-            voxels,
-            [(max_pos - 1) as u32, pos[1] as u32, pos[2] as u32],
-            grid_size,
-        ))
-    } else {
-        // Central difference in interior
-        get_voxel_density(
-            voxels,
-            [(pos[0] + 1) as u32, pos[1] as u32, pos[2] as u32],
-            grid_size,
-        ) - get_voxel_density(
-            voxels,
-            [(pos[0] - 1) as u32, pos[1] as u32, pos[2] as u32],
-            grid_size,
-        )
-    };
-
-    // Y gradient
-    let dy = if pos[1] == 0 {
-        2.0 * (get_voxel_density(voxels, [pos[0] as u32, 1, pos[2] as u32], grid_size)
-            - get_voxel_density(voxels, [pos[0] as u32, 0, pos[2] as u32], grid_size))
-    } else if pos[1] == max_pos {
-        2.0 * (get_voxel_density(
-            voxels,
-            [pos[0] as u32, max_pos as u32, pos[2] as u32],
-            grid_size,
-        ) - get_voxel_density(
-            voxels,
-            [pos[0] as u32, (max_pos - 1) as u32, pos[2] as u32],
-            grid_size,
-        ))
-    } else {
-        get_voxel_density(
-            voxels,
-            [pos[0] as u32, (pos[1] + 1) as u32, pos[2] as u32],
-            grid_size,
-        ) - get_voxel_density(
-            voxels,
-            [pos[0] as u32, (pos[1] - 1) as u32, pos[2] as u32],
-            grid_size,
-        )
-    };
-
-    // Z gradient
-    let dz = if pos[2] == 0 {
-        2.0 * (get_voxel_density(voxels, [pos[0] as u32, pos[1] as u32, 1], grid_size)
-            - get_voxel_density(voxels, [pos[0] as u32, pos[1] as u32, 0], grid_size))
-    } else if pos[2] == max_pos {
-        2.0 * (get_voxel_density(
-            voxels,
-            [pos[0] as u32, pos[1] as u32, max_pos as u32],
-            grid_size,
-        ) - get_voxel_density(
-            voxels,
-            [pos[0] as u32, pos[1] as u32, (max_pos - 1) as u32],
-            grid_size,
-        ))
-    } else {
-        get_voxel_density(
-            voxels,
-            [pos[0] as u32, pos[1] as u32, (pos[2] + 1) as u32],
-            grid_size,
-        ) - get_voxel_density(
-            voxels,
-            [pos[0] as u32, pos[1] as u32, (pos[2] - 1) as u32],
-            grid_size,
-        )
-    };
-
-    let gradient = [dx, dy, dz];
-    let length = (dx * dx + dy * dy + dz * dz).sqrt();
+// Normals point against the density gradient (same convention as the old finite-difference pass)
+pub(crate) fn gradient_to_normal(gradient: [f32; 3]) -> [f32; 3] {
+    let length =
+        (gradient[0] * gradient[0] + gradient[1] * gradient[1] + gradient[2] * gradient[2]).sqrt();
     if length > 0.0001 {
         [
             -gradient[0] / length,
@@ -461,12 +338,45 @@ fn calculate_gradient(voxels: &[VoxelData], pos: [i32; 3], grid_size: u32) -> [f
 
 // WebGPU DrawIndexedIndirect command format
 #[repr(C)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Command {
-    index_count: u32,    // Number of indices to draw
-    instance_count: u32, // Number of instances to draw
-    first_index: u32,    // First index in the index buffer
-    base_vertex: i32,    // Value added to vertex index before indexing into vertex buffer
-    first_instance: u32, // First instance ID
+    pub index_count: u32,    // Number of indices to draw
+    pub instance_count: u32, // Number of instances to draw
+    pub first_index: u32,    // First index in the index buffer
+    pub base_vertex: i32,    // Value added to vertex index before indexing into vertex buffer
+    pub first_instance: u32, // First instance ID
+}
+
+impl Command {
+    fn empty(first_index: u32, base_vertex: i32) -> Command {
+        Command {
+            index_count: 0,
+            instance_count: 1,
+            first_index,
+            base_vertex,
+            first_instance: 0,
+        }
+    }
+
+    pub(crate) fn to_words(&self) -> [u32; 5] {
+        [
+            self.index_count,
+            self.instance_count,
+            self.first_index,
+            self.base_vertex as u32,
+            self.first_instance,
+        ]
+    }
+
+    pub(crate) fn from_words(words: [u32; 5]) -> Command {
+        Command {
+            index_count: words[0],
+            instance_count: words[1],
+            first_index: words[2],
+            base_vertex: words[3] as i32,
+            first_instance: words[4],
+        }
+    }
 }
 
 // Vertex key for deduplication - combines position, normal, and color
@@ -496,527 +406,1043 @@ impl VertexKey {
     }
 }
 
+/// Mesh buffers of one chunk. `commands` and `vertex_counts` hold one entry
+/// per meshlet in grid order, followed by six skirt entries (-X, +X, -Y, +Y,
+/// -Z, +Z) when skirts are enabled. `densities` only covers the meshlet grid.
+///
+/// With u16 indices a draw addresses at most 65536 vertices, so larger
+/// meshlets are split: the first part keeps the meshlet's slot and the rest
+/// are appended as extra draws, `split_from[i]` naming the draw that extra
+/// draw `i` continues. Only one of `indices` and `indices_u32` is filled.
+///
+/// `bounds` has `BOUNDS_STRIDE` floats of culling volumes per draw, in the
+/// same order as `commands` (see `MeshletBounds` for the layout).
+///
+/// The float vertex format fills `vertices`, `normals`, `material_colors` and
+/// `colors`, the packed format only `packed_vertices` (see `pack_vertex`),
+/// with positions quantized relative to `position_origin`.
 pub struct Chunk {
-    densities: Vec<u32>,
-    vertex_counts: Vec<u32>,
-    commands: Vec<Command>,
-    vertices: Vec<f32>,
-    normals: Vec<f32>,
-    material_colors: Vec<u32>,
-    colors: Vec<u32>,
-    indices: Vec<u16>,
+    pub(crate) densities: Vec<u32>,
+    pub(crate) vertex_counts: Vec<u32>,
+    pub(crate) commands: Vec<Command>,
+    pub(crate) split_from: Vec<u32>,
+    pub(crate) bounds: Vec<f32>,
+    pub(crate) vertex_format: VertexFormat,
+    pub(crate) position_origin: [f32; 3],
+    pub(crate) vertices: Vec<f32>,
+    pub(crate) normals: Vec<f32>,
+    pub(crate) material_colors: Vec<u32>,
+    pub(crate) colors: Vec<u32>,
+    pub(crate) packed_vertices: Vec<u32>,
+    pub(crate) index_format: IndexFormat,
+    pub(crate) indices: Vec<u16>,
+    pub(crate) indices_u32: Vec<u32>,
 }
 
 impl Chunk {
-    pub fn densities(&self) -> *const u32 {
-        self.densities.as_ptr()
+    /// Chunk without any geometry: every meshlet (and skirt) gets a zero-length draw
+    pub fn empty(chunk: [i32; 3], resolution: u32, options: MeshOptions) -> Chunk {
+        let s_size = (resolution / COMPRESSION) as usize;
+        let meshlets = s_size * s_size * s_size;
+        let draws = if options.skirt.is_some() {
+            meshlets + 6
+        } else {
+            meshlets
+        };
+        Chunk {
+            densities: vec![0; meshlets],
+            vertex_counts: vec![0; draws],
+            commands: (0..draws).map(|_| Command::empty(0, 0)).collect(),
+            split_from: Vec::new(),
+            bounds: (0..draws)
+                .flat_map(|_| MeshletBounds::empty().to_floats())
+                .collect(),
+            vertex_format: options.vertex_format,
+            position_origin: position_origin(chunk),
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            material_colors: Vec::new(),
+            colors: Vec::new(),
+            packed_vertices: Vec::new(),
+            index_format: options.index_format,
+            indices: Vec::new(),
+            indices_u32: Vec::new(),
+        }
+    }
+
+    pub fn densities(&self) -> &[u32] {
+        &self.densities
     }
 
     pub fn density_len(&self) -> usize {
         self.densities.len()
     }
 
-    pub fn vertex_counts(&self) -> *const u32 {
-        self.vertex_counts.as_ptr()
+    pub fn vertex_counts(&self) -> &[u32] {
+        &self.vertex_counts
     }
 
     pub fn vertex_counts_len(&self) -> usize {
         self.vertex_counts.len()
     }
 
-    pub fn vertices(&self) -> *const f32 {
-        self.vertices.as_ptr()
+    pub fn vertices(&self) -> &[f32] {
+        &self.vertices
     }
 
     pub fn vertices_len(&self) -> usize {
         self.vertices.len()
     }
 
-    pub fn normals(&self) -> *const f32 {
-        self.normals.as_ptr()
+    pub fn normals(&self) -> &[f32] {
+        &self.normals
     }
 
     pub fn normals_len(&self) -> usize {
         self.normals.len()
     }
 
-    pub fn material_colors(&self) -> *const u32 {
-        self.material_colors.as_ptr()
+    pub fn material_colors(&self) -> &[u32] {
+        &self.material_colors
     }
 
     pub fn material_colors_len(&self) -> usize {
         self.material_colors.len()
     }
 
-    pub fn colors(&self) -> *const u32 {
-        self.colors.as_ptr()
+    pub fn colors(&self) -> &[u32] {
+        &self.colors
     }
 
     pub fn colors_len(&self) -> usize {
         self.colors.len()
     }
 
-    pub fn commands(&self) -> *const Command {
-        self.commands.as_ptr()
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
     }
 
     pub fn commands_len(&self) -> usize {
         self.commands.len()
     }
 
-    pub fn indices(&self) -> *const u16 {
-        self.indices.as_ptr()
+    pub fn split_from(&self) -> &[u32] {
+        &self.split_from
+    }
+
+    pub fn split_from_len(&self) -> usize {
+        self.split_from.len()
+    }
+
+    pub fn bounds(&self) -> &[f32] {
+        &self.bounds
+    }
+
+    pub fn bounds_len(&self) -> usize {
+        self.bounds.len()
+    }
+
+    pub fn vertex_format(&self) -> VertexFormat {
+        self.vertex_format
+    }
+
+    pub fn position_origin(&self) -> [f32; 3] {
+        self.position_origin
+    }
+
+    pub fn packed_vertices(&self) -> &[u32] {
+        &self.packed_vertices
+    }
+
+    pub fn packed_vertices_len(&self) -> usize {
+        self.packed_vertices.len()
+    }
+
+    /// Vertices in whichever format the chunk was built with
+    pub fn vertex_count(&self) -> usize {
+        match self.vertex_format {
+            VertexFormat::Float => self.vertices.len() / 4,
+            VertexFormat::Packed => self.packed_vertices.len() / PACKED_WORDS,
+        }
+    }
+
+    pub fn index_format(&self) -> IndexFormat {
+        self.index_format
+    }
+
+    pub fn indices(&self) -> &[u16] {
+        &self.indices
     }
 
     pub fn indices_len(&self) -> usize {
         self.indices.len()
     }
+
+    pub fn indices_u32(&self) -> &[u32] {
+        &self.indices_u32
+    }
+
+    pub fn indices_u32_len(&self) -> usize {
+        self.indices_u32.len()
+    }
+}
+
+/// Lattice edge a vertex was interpolated on, as its two sample points in
+/// world units. Identifies the same crossing across cells, meshlets and chunks.
+/// Dual mesher vertices use their cell's lowest sample for both points.
+pub(crate) type EdgeKey = ([i32; 3], [i32; 3]);
+
+/// One marching cubes output vertex (position in world space)
+#[derive(Clone, Copy)]
+pub(crate) struct Vertex {
+    pub edge: EdgeKey,
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub color: u32,
+}
+
+/// Edges of a triangle lying on the plane `axis = plane`, in the triangle's winding
+pub(crate) fn face_edges(
+    triangle: &[Vertex; 3],
+    axis: usize,
+    plane: i32,
+) -> impl Iterator<Item = (Vertex, Vertex)> + '_ {
+    let on_plane = move |v: &Vertex| v.edge.0[axis] == plane && v.edge.1[axis] == plane;
+    (0..3)
+        .map(move |i| (triangle[i], triangle[(i + 1) % 3]))
+        .filter(move |(u, v)| on_plane(u) && on_plane(v))
+}
+
+/// Triangulates one cell and appends its triangles. `voxel` is the cell's
+/// lowest sample in chunk voxels. Returns whether the surface crosses the cell.
+pub(crate) fn march_cell(
+    values: &[f32; 8],
+    normals: &[[f32; 3]; 8],
+    voxel: [u32; 3],
+    scale: f32,
+    chunk: [i32; 3],
+    triangles: &mut Vec<[Vertex; 3]>,
+) -> bool {
+    // Calculate cube configuration index
+    let mut cube_index = 0u32;
+    for (i, value) in values.iter().enumerate() {
+        if *value < 0.0 {
+            cube_index |= 1u32 << i;
+        }
+    }
+
+    // Skip empty cubes
+    if cube_index == 0 || cube_index == 255 {
+        return false;
+    }
+
+    // Get edge configuration
+    let edges = EDGE_TABLE_DATA[cube_index as usize];
+    if edges == 0 {
+        return true;
+    }
+
+    let chunk_world_pos = [chunk[0] * SIZE, chunk[1] * SIZE, chunk[2] * SIZE];
+    let step = scale as i32;
+    let sample = |corner: usize| -> [i32; 3] {
+        let offset = CUBE_VERTICES[corner];
+        [
+            (voxel[0] as i32 + offset[0] as i32) * step + chunk_world_pos[0],
+            (voxel[1] as i32 + offset[1] as i32) * step + chunk_world_pos[1],
+            (voxel[2] as i32 + offset[2] as i32) * step + chunk_world_pos[2],
+        ]
+    };
+
+    // Convert voxel grid position to chunk-local world position (0-256 range)
+    let local_pos = [
+        voxel[0] as f32 * scale,
+        voxel[1] as f32 * scale,
+        voxel[2] as f32 * scale,
+    ];
+
+    // Calculate interpolated vertices, normals, and colors on edges
+    let mut vertex_list = [Vertex {
+        edge: ([0; 3], [0; 3]),
+        position: [0.0; 3],
+        normal: [0.0; 3],
+        color: 0,
+    }; 12];
+
+    // Check each edge bit and interpolate if necessary
+    for (i, vertex) in vertex_list.iter_mut().enumerate() {
+        let edge_bit = 1u32 << i;
+        if (edges & edge_bit) != 0 {
+            let v1 = EDGE_VERTICES[i][0];
+            let v2 = EDGE_VERTICES[i][1];
+
+            let p1 = [
+                local_pos[0] + CUBE_VERTICES[v1][0] * scale,
+                local_pos[1] + CUBE_VERTICES[v1][1] * scale,
+                local_pos[2] + CUBE_VERTICES[v1][2] * scale,
+            ];
+            let p2 = [
+                local_pos[0] + CUBE_VERTICES[v2][0] * scale,
+                local_pos[1] + CUBE_VERTICES[v2][1] * scale,
+                local_pos[2] + CUBE_VERTICES[v2][2] * scale,
+            ];
+
+            let local_vertex = interpolate_vertex(p1, p2, values[v1], values[v2]);
+
+            // Apply chunk world position offset to vertices
+            let position = [
+                local_vertex[0] + chunk_world_pos[0] as f32,
+                local_vertex[1] + chunk_world_pos[1] as f32,
+                local_vertex[2] + chunk_world_pos[2] as f32,
+            ];
+
+            // Use pre-computed gradients from cube corners
+            let normal = interpolate_normal(normals[v1], normals[v2], values[v1], values[v2]);
+
+            let (a, b) = (sample(v1), sample(v2));
+            *vertex = Vertex {
+                edge: (a.min(b), a.max(b)),
+                position,
+                normal,
+                // Calculate color based on world position and surface normal
+                color: calculate_terrain_color(position, normal),
+            };
+        }
+    }
+
+    // Generate triangles using lookup table
+    let base_triangle_index = (cube_index * 16) as usize;
+    for triangle in TRIANGLE_TABLE_DATA[base_triangle_index..base_triangle_index + 16].chunks(3) {
+        if triangle[0] < 0 || triangle.len() < 3 {
+            break;
+        }
+        if triangle[1] >= 0 && triangle[2] >= 0 {
+            triangles.push([
+                vertex_list[triangle[0] as usize],
+                vertex_list[triangle[1] as usize],
+                vertex_list[triangle[2] as usize],
+            ]);
+        }
+    }
+
+    true
+}
+
+/// Geometry of one meshlet before it is appended to the chunk buffers
+#[derive(Default)]
+pub(crate) struct Meshlet {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    colors: Vec<u32>,
+    indices: Vec<u32>,
+    vertex_map: FxHashMap<VertexKey, u32>,
+    density: u32, // Number of cells the surface passes through
+}
+
+impl Meshlet {
+    fn new(triangles: &[[Vertex; 3]], density: u32) -> Meshlet {
+        let corners = triangles.len() * 3;
+        let mut meshlet = Meshlet {
+            positions: Vec::with_capacity(corners),
+            normals: Vec::with_capacity(corners),
+            colors: Vec::with_capacity(corners),
+            indices: Vec::with_capacity(corners),
+            vertex_map: FxHashMap::with_capacity_and_hasher(corners, Default::default()),
+            density,
+        };
+        for triangle in triangles {
+            meshlet.add_triangle(triangle);
+        }
+        meshlet
+    }
+
+    // Add or reuse a vertex
+    fn add_vertex(&mut self, vertex: &Vertex) -> u32 {
+        let key = VertexKey::new(vertex.position, vertex.normal, vertex.color);
+        if let Some(&idx) = self.vertex_map.get(&key) {
+            idx
+        } else {
+            let idx = self.positions.len() as u32;
+            self.positions.push(vertex.position);
+            self.normals.push(vertex.normal);
+            self.colors.push(vertex.color);
+            self.vertex_map.insert(key, idx);
+            idx
+        }
+    }
+
+    pub(crate) fn add_triangle(&mut self, triangle: &[Vertex; 3]) {
+        for vertex in triangle {
+            let idx = self.add_vertex(vertex);
+            self.indices.push(idx);
+        }
+    }
+
+    // Lets the density recolour the vertices, e.g. for painted edits
+    fn paint<D: Density + ?Sized>(&mut self, density: &D) {
+        for (position, color) in self.positions.iter().zip(&mut self.colors) {
+            *color = density.paint(*position, *color);
+        }
+    }
+
+    // Splits the triangles into parts of at most `max_vertices` vertices each,
+    // duplicating the vertices parts share. The first part keeps the density.
+    fn split(self, max_vertices: usize) -> Vec<Meshlet> {
+        let mut parts = Vec::new();
+        let mut part = Meshlet {
+            density: self.density,
+            ..Meshlet::default()
+        };
+        let mut remap: FxHashMap<u32, u32> = FxHashMap::default();
+        for triangle in self.indices.chunks_exact(3) {
+            let new = triangle.iter().filter(|i| !remap.contains_key(i)).count();
+            if part.positions.len() + new > max_vertices {
+                parts.push(std::mem::take(&mut part));
+                remap.clear();
+            }
+            for &i in triangle {
+                let local = *remap.entry(i).or_insert_with(|| {
+                    part.positions.push(self.positions[i as usize]);
+                    part.normals.push(self.normals[i as usize]);
+                    part.colors.push(self.colors[i as usize]);
+                    part.positions.len() as u32 - 1
+                });
+                part.indices.push(local);
+            }
+        }
+        parts.push(part);
+        parts
+    }
+}
+
+/// Surface extraction algorithm
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mesher {
+    /// Classic marching cubes, the default
+    MarchingCubes = 0,
+    /// One vertex per surface cell at the mean of its edge crossings, about half the triangles
+    SurfaceNets = 1,
+    /// One vertex per surface cell from a QEF on the crossing gradients, keeps sharp edges
+    DualContouring = 2,
+}
+
+/// Index buffer element type
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IndexFormat {
+    /// Half the index memory, draws over 65536 vertices are split
+    Uint16 = 0,
+    /// No vertex limit per draw
+    Uint32 = 1,
+}
+
+/// Vertex buffer layout
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VertexFormat {
+    /// `vec4<f32>` position and normal plus material and lit colour, 40 bytes per vertex
+    Float = 0,
+    /// Quantized chunk-relative position, octahedral normal and one colour, 16 bytes per vertex
+    Packed = 1,
+}
+
+/// Per-chunk mesher settings
+#[derive(Clone, Copy)]
+pub struct MeshOptions {
+    pub mesher: Mesher,
+    /// LOD of each face neighbour, order: -X, +X, -Y, +Y, -Z, +Z (255 means no neighbor).
+    /// Transition geometry rebuilds the neighbour's marching cubes contour, so
+    /// dual meshers only stitch same-LOD neighbours and rely on skirts otherwise.
+    pub neighbor_lods: [u32; 6],
+    /// Emit skirts as six extra draws after the meshlet grid
+    pub skirt: Option<Skirt>,
+    pub index_format: IndexFormat,
+    pub vertex_format: VertexFormat,
+}
+
+impl Default for MeshOptions {
+    /// Marching cubes without neighbours or skirts, u16 indices and float vertices
+    fn default() -> MeshOptions {
+        MeshOptions {
+            mesher: Mesher::MarchingCubes,
+            neighbor_lods: [255; 6],
+            skirt: None,
+            index_format: IndexFormat::Uint16,
+            vertex_format: VertexFormat::Float,
+        }
+    }
 }
 
-pub fn generate_mesh(
+/// Meshes a chunk, `cached` is its octree when one was kept from an earlier
+/// sampling (marching cubes only, the dual meshers always sample)
+#[allow(clippy::too_many_arguments)]
+pub fn generate_mesh<D: Density + ?Sized>(
+    density_source: &D,
     x: i32,
     y: i32,
     z: i32,
     resolution: u32,
     scale: f32,
-    neighbor_lods: [u32; 6],
+    options: MeshOptions,
+    cached: Option<&VoxelOctree>,
 ) -> Chunk {
-    // neighbor_lods order: -X, +X, -Y, +Y, -Z, +Z (255 means no neighbor)
-    // COMPRESSION must be constant 8 to maintain meshlet structure
-    const COMPRESSION: u32 = 8;
     let s_size = resolution / COMPRESSION;
+    let chunk = [x, y, z];
+
+    // Only bricks crossing the surface keep voxel data, uniform air and rock collapse.
+    // Dual meshers sample their own bricks, they also need the next brick's first cells.
+    let sampled;
+    let octree = match (options.mesher, cached) {
+        (Mesher::MarchingCubes, Some(octree)) => Some(octree),
+        (Mesher::MarchingCubes, None) => {
+            sampled = VoxelOctree::from_density(density_source, x, y, z, resolution, scale);
+            Some(&sampled)
+        }
+        (Mesher::SurfaceNets | Mesher::DualContouring, _) => None,
+    };
 
-    let density_data = only_noise_for_chunk(x, y, z, resolution, scale);
-
-    // Convert density data to VoxelData with colors and pre-compute gradients
-    let voxel_size = resolution + 1;
-    let total_voxels = (voxel_size * voxel_size * voxel_size) as usize;
-    let mut voxels = Vec::with_capacity(total_voxels);
-
-    // First pass: Create voxels with density and color (no gradients yet)
-    for (idx, density) in density_data.iter().enumerate() {
-        // Rainbow colors based on y-axis position (height)
-        // Color format: 0xAABBGGRR (little endian RGBA)
-        let color = if *density < 0.0 {
-            // Calculate y coordinate from flat index (x + y * width + z * width * height)
-            let y = (idx / voxel_size as usize) % voxel_size as usize;
-
-            // Create rainbow based on y position (height from 0.0 to 1.0)
-            let t = (y as f32 / voxel_size as f32) * 8.0 + 16.0;
-            let r = ((t.sin() * 127.5 + 127.5).clamp(0.0, 255.0) as u32) & 0xFF;
-            let g = (((t + 2.0).sin() * 127.5 + 127.5).clamp(0.0, 255.0) as u32) & 0xFF;
-            let b = (((t + 4.0).sin() * 127.5 + 127.5).clamp(0.0, 255.0) as u32) & 0xFF;
-            0xFF000000 | (b << 16) | (g << 8) | r
+    // Faces with a finer neighbour get transition geometry (the neighbour leaves its side alone)
+    let neighbor_lods = match options.mesher {
+        Mesher::MarchingCubes => options.neighbor_lods,
+        Mesher::SurfaceNets | Mesher::DualContouring => [255; 6],
+    };
+    let mut seams = Seams::new(chunk, scale, neighbor_lods);
+    let mut skirts = options.skirt.map(|skirt| Skirts::new(skirt, chunk, scale));
+
+    // Meshlets are independent, they can be built in any order and on any thread
+    let ids: Vec<[u32; 3]> = (0..s_size)
+        .flat_map(|gz| (0..s_size).flat_map(move |gy| (0..s_size).map(move |gx| [gx, gy, gz])))
+        .collect();
+    let built = map_init(&ids, Vec::new, |triangles, id| {
+        let boundary = id.iter().any(|g| *g == 0 || *g == s_size - 1);
+
+        triangles.clear();
+        let density = mesh_brick(
+            density_source,
+            octree,
+            chunk,
+            *id,
+            scale,
+            options.mesher,
+            triangles,
+        );
+        let meshlet = Meshlet::new(triangles, density);
+        // Only boundary meshlets can touch a chunk face
+        let face_triangles = if boundary {
+            triangles.clone()
         } else {
-            0xFF808080 // Gray for outside: A=FF, B=80, G=80, R=80
+            Vec::new()
         };
-        voxels.push(VoxelData {
-            density: *density,
-            color,
-            gradient: [0.0, 1.0, 0.0], // Placeholder, will be computed in second pass
-        });
-    }
-
-    // Second pass: Pre-compute gradients for all voxels (in-place, no intermediate Vec)
-    for z in 0..voxel_size {
-        for y in 0..voxel_size {
-            for x in 0..voxel_size {
-                let pos = [x as i32, y as i32, z as i32];
-                let gradient = calculate_gradient(&voxels, pos, resolution);
-                let idx = (z * voxel_size * voxel_size + y * voxel_size + x) as usize;
-                voxels[idx].gradient = gradient;
+        (meshlet, face_triangles)
+    });
+
+    // Seams and skirts see the boundary triangles in grid order, as if meshed serially
+    let mut meshlets = Vec::with_capacity(built.len());
+    for (meshlet, face_triangles) in built {
+        for triangle in &face_triangles {
+            seams.record(triangle);
+            if let Some(skirts) = &mut skirts {
+                skirts.record(triangle);
             }
         }
+        meshlets.push(meshlet);
     }
 
-    let chunk_world_pos = [x * SIZE, y * SIZE, z * SIZE];
-
-    let mut all_vertices = Vec::new();
-    let mut all_normals = Vec::new();
-    let mut all_colors = Vec::new(); // u32 packed for lit colors (initialized same as material_colors)
-    let mut all_material_colors = Vec::new(); // u32 packed for material colors
-    let mut all_indices: Vec<u16> = Vec::new();
-    let mut commands = Vec::new();
-    let mut densities = Vec::new();
-    let mut vertex_counts = Vec::new();
-
-    // Process each meshlet (workgroup)
-    for gz in 0..s_size {
-        for gy in 0..s_size {
-            for gx in 0..s_size {
-                let actual_id = [gx, gy, gz];
-
-                // Pre-allocate with estimated capacity
-                // Max vertices per meshlet: COMPRESSION³ cubes × ~15 triangles × 3 vertices / ~3 (sharing factor)
-                let ESTIMATED_VERTS_PER_MESHLET: usize =
-                    (COMPRESSION * COMPRESSION * COMPRESSION * 15) as usize;
-                let ESTIMATED_INDICES_PER_MESHLET: usize =
-                    (COMPRESSION * COMPRESSION * COMPRESSION * 15 * 3) as usize;
-
-                let mut local_positions = Vec::with_capacity(ESTIMATED_VERTS_PER_MESHLET);
-                let mut local_normals = Vec::with_capacity(ESTIMATED_VERTS_PER_MESHLET);
-                let mut local_colors = Vec::with_capacity(ESTIMATED_VERTS_PER_MESHLET);
-                let mut local_indices: Vec<u16> = Vec::with_capacity(ESTIMATED_INDICES_PER_MESHLET);
-                let mut vertex_map: FxHashMap<VertexKey, u32> = FxHashMap::with_capacity_and_hasher(
-                    ESTIMATED_VERTS_PER_MESHLET,
-                    Default::default(),
-                );
-                let mut density = 0;
-
-                // Triple nested loop over COMPRESSION³ cells
-                for z in 0..COMPRESSION {
-                    for y in 0..COMPRESSION {
-                        for x in 0..COMPRESSION {
-                            let voxel_pos = [
-                                (x + actual_id[0] * COMPRESSION) as i32,
-                                (y + actual_id[1] * COMPRESSION) as i32,
-                                (z + actual_id[2] * COMPRESSION) as i32,
-                            ];
-
-                            // Convert voxel grid position to chunk-local world position (0-256 range)
-                            let local_pos = [
-                                voxel_pos[0] as f32 * scale,
-                                voxel_pos[1] as f32 * scale,
-                                voxel_pos[2] as f32 * scale,
-                            ];
-
-                            // Get the 8 corner values, colors, and gradients of the cube (combined fetch)
-                            let mut cube_values = [0.0f32; 8];
-                            let mut cube_colors = [0u32; 8];
-                            let mut cube_gradients = [[0.0f32; 3]; 8];
-
-                            for i in 0..8 {
-                                let corner_offset = CUBE_VERTICES[i];
-                                let pos = [
-                                    voxel_pos[0] + corner_offset[0] as i32,
-                                    voxel_pos[1] + corner_offset[1] as i32,
-                                    voxel_pos[2] + corner_offset[2] as i32,
-                                ];
-                                let (density, color, gradient) =
-                                    get_voxel_all_safe(&voxels, pos, resolution);
-                                cube_values[i] = density;
-                                cube_colors[i] = color;
-                                cube_gradients[i] = gradient;
-                            }
-
-                            // Calculate cube configuration index
-                            let mut cube_index = 0u32;
-                            for i in 0..8 {
-                                if cube_values[i] < 0.0 {
-                                    cube_index |= 1u32 << i;
-                                }
-                            }
-
-                            // Skip empty cubes
-                            if cube_index == 0 || cube_index == 255 {
-                                continue;
-                            }
-
-                            density += 1;
-
-                            // Get edge configuration
-                            let edges = EDGE_TABLE_DATA[cube_index as usize];
-                            if edges == 0 {
-                                continue;
-                            }
-
-                            // Calculate interpolated vertices, normals, and colors on edges
-                            let mut vertex_list = [[0.0f32; 3]; 12];
-                            let mut normal_list = [[0.0f32; 3]; 12];
-                            let mut color_list = [0u32; 12];
-
-                            // Check each edge bit and interpolate if necessary
-                            for i in 0..12 {
-                                let edge_bit = 1u32 << i;
-                                if (edges & edge_bit) != 0 {
-                                    let v1 = EDGE_VERTICES[i][0];
-                                    let v2 = EDGE_VERTICES[i][1];
-
-                                    // let p1 = [
-                                    //     (world_pos[0] as f32 + CUBE_VERTICES[v1][0]) * scale,
-                                    //     (world_pos[1] as f32 + CUBE_VERTICES[v1][1]) * scale,
-                                    //     (world_pos[2] as f32 + CUBE_VERTICES[v1][2]) * scale,
-                                    // ];
-                                    // let p2 = [
-                                    //     (world_pos[0] as f32 + CUBE_VERTICES[v2][0]) * scale,
-                                    //     (world_pos[1] as f32 + CUBE_VERTICES[v2][1]) * scale,
-                                    //     (world_pos[2] as f32 + CUBE_VERTICES[v2][2]) * scale,
-                                    // ];
-                                    let p1 = [
-                                        local_pos[0] + CUBE_VERTICES[v1][0] * scale,
-                                        local_pos[1] + CUBE_VERTICES[v1][1] * scale,
-                                        local_pos[2] + CUBE_VERTICES[v1][2] * scale,
-                                    ];
-                                    let p2 = [
-                                        local_pos[0] + CUBE_VERTICES[v2][0] * scale,
-                                        local_pos[1] + CUBE_VERTICES[v2][1] * scale,
-                                        local_pos[2] + CUBE_VERTICES[v2][2] * scale,
-                                    ];
-
-                                    vertex_list[i] = interpolate_vertex(
-                                        p1,
-                                        p2,
-                                        cube_values[v1],
-                                        cube_values[v2],
-                                    );
-
-                                    // Use pre-computed gradients from cube corners
-                                    let n1 = cube_gradients[v1];
-                                    let n2 = cube_gradients[v2];
-                                    normal_list[i] = interpolate_normal(
-                                        n1,
-                                        n2,
-                                        cube_values[v1],
-                                        cube_values[v2],
-                                    );
-
-                                    // Calculate color based on world position and surface normal
-                                    let world_vertex_pos = [
-                                        vertex_list[i][0] + chunk_world_pos[0] as f32,
-                                        vertex_list[i][1] + chunk_world_pos[1] as f32,
-                                        vertex_list[i][2] + chunk_world_pos[2] as f32,
-                                    ];
-                                    color_list[i] =
-                                        calculate_terrain_color(world_vertex_pos, normal_list[i]);
-                                }
-                            }
-
-                            // Generate triangles using lookup table
-                            let base_triangle_index = (cube_index * 16) as usize;
-
-                            // Helper closure to add or reuse vertex (moved outside triangle loop)
-                            let mut add_vertex =
-                                |pos: [f32; 3], norm: [f32; 3], color: u32| -> u32 {
-                                    let key = VertexKey::new(pos, norm, color);
-                                    if let Some(&idx) = vertex_map.get(&key) {
-                                        idx
-                                    } else {
-                                        let idx = local_positions.len() as u32;
-                                        local_positions.push(pos);
-                                        local_normals.push(norm);
-                                        local_colors.push(color);
-                                        vertex_map.insert(key, idx);
-                                        idx
-                                    }
-                                };
-
-                            let mut tri_idx = 0;
-                            while tri_idx < 16 {
-                                let edge1 = TRIANGLE_TABLE_DATA[base_triangle_index + tri_idx];
-                                if edge1 == -1 {
-                                    break;
-                                }
-
-                                let edge2 = TRIANGLE_TABLE_DATA[base_triangle_index + tri_idx + 1];
-                                let edge3 = TRIANGLE_TABLE_DATA[base_triangle_index + tri_idx + 2];
-
-                                if edge1 >= 0 && edge2 >= 0 && edge3 >= 0 {
-                                    let edge1_idx = edge1 as usize;
-                                    let edge2_idx = edge2 as usize;
-                                    let edge3_idx = edge3 as usize;
-
-                                    // Apply chunk world position offset to vertices
-                                    let v1_world = [
-                                        vertex_list[edge1_idx][0] + chunk_world_pos[0] as f32,
-                                        vertex_list[edge1_idx][1] + chunk_world_pos[1] as f32,
-                                        vertex_list[edge1_idx][2] + chunk_world_pos[2] as f32,
-                                    ];
-                                    let v2_world = [
-                                        vertex_list[edge2_idx][0] + chunk_world_pos[0] as f32,
-                                        vertex_list[edge2_idx][1] + chunk_world_pos[1] as f32,
-                                        vertex_list[edge2_idx][2] + chunk_world_pos[2] as f32,
-                                    ];
-                                    let v3_world = [
-                                        vertex_list[edge3_idx][0] + chunk_world_pos[0] as f32,
-                                        vertex_list[edge3_idx][1] + chunk_world_pos[1] as f32,
-                                        vertex_list[edge3_idx][2] + chunk_world_pos[2] as f32,
-                                    ];
-
-                                    // Add vertices (reusing if duplicate)
-                                    let idx1 = add_vertex(
-                                        v1_world,
-                                        normal_list[edge1_idx],
-                                        color_list[edge1_idx],
-                                    );
-                                    let idx2 = add_vertex(
-                                        v2_world,
-                                        normal_list[edge2_idx],
-                                        color_list[edge2_idx],
-                                    );
-                                    let idx3 = add_vertex(
-                                        v3_world,
-                                        normal_list[edge3_idx],
-                                        color_list[edge3_idx],
-                                    );
-
-                                    // Add triangle indices
-                                    local_indices.push(idx1 as u16);
-                                    local_indices.push(idx2 as u16);
-                                    local_indices.push(idx3 as u16);
-                                }
-
-                                tri_idx += 3;
-                            }
-                        }
-                    }
-                }
+    if !seams.is_empty() {
+        seams.fill(density_source, chunk, scale, &mut meshlets, COMPRESSION);
+    }
 
-                let index_count = local_indices.len() as u32;
-                let vertex_count = local_positions.len() as u32;
-                let first_index = all_indices.len() as u32;
-                let vertex_offset = (all_vertices.len() / 4) as i32; // Divide by 4 since vertices are vec4<f32>
+    let mut skirts = skirts.map(Skirts::build).unwrap_or_default();
+    for meshlet in meshlets.iter_mut().chain(&mut skirts) {
+        meshlet.paint(density_source);
+    }
+    assemble(meshlets, skirts, chunk, options)
+}
 
-                // Append local data to global arrays
-                all_vertices.extend(local_positions.iter().flat_map(|v| [v[0], v[1], v[2], 1.0]));
-                // Normals need padding to 16 bytes (vec3<f32> in storage buffer has 16-byte stride)
-                all_normals.extend(local_normals.iter().flat_map(|n| [n[0], n[1], n[2], 0.0]));
+/// A draw `remesh_region` replaced. Its vertices are now
+/// `command.base_vertex..+vertex_count` and its indices
+/// `command.first_index..+command.index_count` of the chunk buffers.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PatchedDraw {
+    /// Index into the chunk's commands, vertex counts and bounds
+    pub draw: u32,
+    pub vertex_count: u32,
+    pub command: Command,
+}
 
-                // Store material colors as u32 packed
-                all_material_colors.extend(local_colors.iter().copied());
+/// What `remesh_region` changed in a chunk, so GPU copies of its buffers can
+/// be updated without uploading them again. Besides the geometry ranges, the
+/// bounds of every patched draw and the densities of patched meshlets changed.
+pub struct MeshPatch {
+    /// Patched meshlets in grid order, then patched skirt faces
+    pub draws: Vec<PatchedDraw>,
+    /// Vertex and index buffer lengths after the patch, only larger than
+    /// before when a draw outgrew its old ranges
+    pub vertex_count: usize,
+    pub index_count: usize,
+}
 
-                // Initialize lit colors same as material colors (lighting will update these on GPU)
-                all_colors.extend(local_colors.iter().copied());
+/// Remeshes the meshlets of a chunk from `generate_mesh` that sample the box
+/// `min..=max` and writes them into the chunk. `density_source` and the
+/// remaining arguments must be those the chunk was meshed with, apart from
+/// the density changes inside the box.
+///
+/// A meshlet is remeshed when any of its samples lies within one voxel of
+/// the box, so samples whose gradients see a change on the box's border are
+/// redone too. Touching a chunk face with skirts also rebuilds that skirt
+/// face, and touching a face with transition seams remeshes every meshlet
+/// along it, as the seam's triangles belong to the meshlets they start in.
+///
+/// A patched draw keeps its buffer ranges when its new geometry fits and
+/// moves past the end of the buffers otherwise, leaving a gap. Meshing the
+/// chunk again compacts it. Returns None when the chunk cannot be patched
+/// (it was meshed with other options, or a patched draw is or would become
+/// split across several u16 draws); mesh the whole chunk then.
+#[allow(clippy::too_many_arguments)]
+pub fn remesh_region<D: Density + ?Sized>(
+    mesh: &mut Chunk,
+    density_source: &D,
+    x: i32,
+    y: i32,
+    z: i32,
+    resolution: u32,
+    scale: f32,
+    options: MeshOptions,
+    min: [f32; 3],
+    max: [f32; 3],
+) -> Option<MeshPatch> {
+    let s_size = resolution / COMPRESSION;
+    let grid = (s_size * s_size * s_size) as usize;
+    let chunk = [x, y, z];
+    let skirt_draws = if options.skirt.is_some() { 6 } else { 0 };
+    if mesh.densities.len() != grid
+        || mesh.commands.len() != grid + skirt_draws + mesh.split_from.len()
+        || mesh.vertex_format != options.vertex_format
+        || mesh.index_format != options.index_format
+    {
+        return None;
+    }
+    // Chunks coarser than one meshlet (LOD 6 and up) have no cells to remesh
+    if s_size == 0 {
+        return Some(MeshPatch {
+            draws: Vec::new(),
+            vertex_count: mesh.vertex_count(),
+            index_count: mesh.index_len(),
+        });
+    }
+    let index = |id: [u32; 3]| ((id[2] * s_size + id[1]) * s_size + id[0]) as usize;
+    // Meshlets along face `face`
+    let layer = |face: usize| {
+        let axis = face / 2;
+        let plane = if face % 2 == 1 { s_size - 1 } else { 0 };
+        (0..s_size).flat_map(move |v| {
+            (0..s_size).map(move |u| {
+                let mut id = [0; 3];
+                id[axis] = plane;
+                id[(axis + 1) % 3] = u;
+                id[(axis + 2) % 3] = v;
+                id
+            })
+        })
+    };
 
-                // Append indices
-                all_indices.extend(local_indices.iter().copied());
+    // Marching cubes bricks sample their own box, dual meshers also the
+    // first cells of the next brick
+    let extent = COMPRESSION as f32 * scale;
+    let far = match options.mesher {
+        Mesher::MarchingCubes => scale,
+        Mesher::SurfaceNets | Mesher::DualContouring => 2.0 * scale,
+    };
+    let range = [0, 1, 2].map(|axis| {
+        let origin = (chunk[axis] * SIZE) as f32;
+        let first = ((min[axis] - origin - far) / extent).ceil() as i32 - 1;
+        let last = ((max[axis] - origin + scale) / extent).floor() as i32;
+        first.max(0)..=last.min(s_size as i32 - 1)
+    });
+    let mut patched = vec![false; grid];
+    for gz in range[2].clone() {
+        for gy in range[1].clone() {
+            for gx in range[0].clone() {
+                patched[index([gx, gy, gz].map(|g| g as u32))] = true;
+            }
+        }
+    }
 
-                vertex_counts.push(vertex_count);
-                densities.push(density);
+    let neighbor_lods = match options.mesher {
+        Mesher::MarchingCubes => options.neighbor_lods,
+        Mesher::SurfaceNets | Mesher::DualContouring => [255; 6],
+    };
+    let mut seams = Seams::new(chunk, scale, neighbor_lods);
+    let seam_faces = seams.faces();
+    // A remeshed meshlet on a seam face remeshes the face, whose meshlets may
+    // lie on further seam faces
+    let mut refill = [false; 6];
+    loop {
+        let touched: [bool; 6] = std::array::from_fn(|face| {
+            seam_faces[face] && layer(face).any(|id| patched[index(id)])
+        });
+        if touched == refill {
+            break;
+        }
+        refill = touched;
+        for face in (0..6).filter(|face| refill[*face]) {
+            for id in layer(face) {
+                patched[index(id)] = true;
+            }
+        }
+    }
+    seams.retain(refill);
+    // Skirt faces only need the triangles of the meshlets along them
+    let reskirt: [bool; 6] = std::array::from_fn(|face| {
+        options.skirt.is_some() && layer(face).any(|id| patched[index(id)])
+    });
+    let mut marched = patched.clone();
+    for face in (0..6).filter(|face| reskirt[*face]) {
+        for id in layer(face) {
+            marched[index(id)] = true;
+        }
+    }
 
-                commands.push(Command {
-                    index_count,
-                    instance_count: 1,
-                    first_index,
-                    base_vertex: vertex_offset,
-                    first_instance: 0,
-                });
+    let ids: Vec<[u32; 3]> = (0..s_size)
+        .flat_map(|gz| (0..s_size).flat_map(move |gy| (0..s_size).map(move |gx| [gx, gy, gz])))
+        .filter(|id| marched[index(*id)])
+        .collect();
+    let built = map_init(&ids, Vec::new, |triangles, id| {
+        let boundary = id.iter().any(|g| *g == 0 || *g == s_size - 1);
+
+        triangles.clear();
+        let density = mesh_brick(
+            density_source,
+            None,
+            chunk,
+            *id,
+            scale,
+            options.mesher,
+            triangles,
+        );
+        let meshlet = Meshlet::new(triangles, density);
+        let face_triangles = if boundary {
+            triangles.clone()
+        } else {
+            Vec::new()
+        };
+        (meshlet, face_triangles)
+    });
+
+    // Same recording order as generate_mesh, so seams and skirts come out identical
+    let mut skirts = options.skirt.map(|skirt| Skirts::new(skirt, chunk, scale));
+    let mut meshlets: Vec<Meshlet> = (0..grid).map(|_| Meshlet::default()).collect();
+    for (id, (meshlet, face_triangles)) in ids.iter().zip(built) {
+        for triangle in &face_triangles {
+            seams.record(triangle);
+            if let Some(skirts) = &mut skirts {
+                skirts.record(triangle);
             }
         }
+        meshlets[index(*id)] = meshlet;
+    }
+    if !seams.is_empty() {
+        seams.fill(density_source, chunk, scale, &mut meshlets, COMPRESSION);
     }
 
-    // Adjust boundary vertices to align with coarser LOD neighbors
-    // This reduces gaps at LOD transitions
-    // DISABLED FOR NOW - causing issues
-    // adjust_boundary_vertices_for_lod(
-    //     &mut all_vertices,
-    //     resolution,
-    //     scale,
-    //     neighbor_lods,
-    // );
-
-    Chunk {
-        commands,
-        vertex_counts,
-        densities,
-        vertices: all_vertices,
-        normals: all_normals,
-        material_colors: all_material_colors,
-        colors: all_colors,
-        indices: all_indices,
+    let skirts = skirts.map(Skirts::build).unwrap_or_default();
+    let mut replaced: Vec<(usize, Meshlet)> = meshlets
+        .into_iter()
+        .enumerate()
+        .filter(|(draw, _)| patched[*draw])
+        .chain(
+            skirts
+                .into_iter()
+                .enumerate()
+                .filter(|(face, _)| reskirt[*face])
+                .map(|(face, meshlet)| (grid + face, meshlet)),
+        )
+        .collect();
+
+    // Split draws cannot be patched, their extra draws come after all others
+    let split = replaced.iter().any(|(draw, meshlet)| {
+        mesh.split_from.contains(&(*draw as u32))
+            || (options.index_format == IndexFormat::Uint16
+                && meshlet.positions.len() > MAX_U16_VERTICES)
+    });
+    if split {
+        return None;
     }
+
+    let draws = replaced
+        .iter_mut()
+        .map(|(draw, meshlet)| {
+            meshlet.paint(density_source);
+            mesh.replace(*draw, meshlet)
+        })
+        .collect();
+    Some(MeshPatch {
+        draws,
+        vertex_count: mesh.vertex_count(),
+        index_count: mesh.index_len(),
+    })
 }
 
-// Adjust vertices at chunk boundaries to align with coarser neighbor grids
-// This prevents gaps when adjacent chunks have different LOD levels
-fn adjust_boundary_vertices_for_lod(
-    vertices: &mut Vec<f32>,
-    resolution: u32,
+// Meshes brick `id` of the chunk, returns how many cells the surface passes
+// through. Marching cubes reads the brick from the chunk's octree, or samples
+// it on its own without one.
+fn mesh_brick<D: Density + ?Sized>(
+    density_source: &D,
+    octree: Option<&VoxelOctree>,
+    chunk: [i32; 3],
+    id: [u32; 3],
     scale: f32,
-    neighbor_lods: [u32; 6],
-) {
-    let chunk_size = resolution as f32 * scale;
-    let boundary_threshold = scale * 0.5; // Half a voxel's worth of tolerance
-
-    // Process each vertex (vertices are stored as vec4<f32>: [x, y, z, 1.0])
-    for i in (0..vertices.len()).step_by(4) {
-        let mut x = vertices[i];
-        let mut y = vertices[i + 1];
-        let mut z = vertices[i + 2];
-
-        // Check each face and snap to coarser neighbor grid if needed
-        // Face order: -X(0), +X(1), -Y(2), +Y(3), -Z(4), +Z(5)
-
-        // Only snap if we're at a boundary AND the neighbor has a coarser LOD
-        // 255 means no neighbor exists
-        let current_lod = (scale.log2()) as u32;
-
-        // -X face (x near 0)
-        if x < boundary_threshold {
-            if neighbor_lods[0] != 255 && neighbor_lods[0] < 10 {
-                // Sanity check: valid LOD is 0-2
-                let neighbor_lod = neighbor_lods[0];
-                if neighbor_lod > current_lod {
-                    // Neighbor is coarser, snap to their grid
-                    let neighbor_scale = 2_u32.pow(neighbor_lod) as f32;
-                    y = (y / neighbor_scale).round() * neighbor_scale;
-                    z = (z / neighbor_scale).round() * neighbor_scale;
-                }
-            }
+    mesher: Mesher,
+    triangles: &mut Vec<[Vertex; 3]>,
+) -> u32 {
+    match (mesher, octree) {
+        (Mesher::MarchingCubes, Some(octree)) => match octree.brick(id) {
+            BrickRef::Surface(brick) => march_brick(brick, id, scale, chunk, triangles),
+            // Uniform bricks have no sign changes and produce no triangles
+            BrickRef::Air | BrickRef::Solid => 0,
+        },
+        (Mesher::MarchingCubes, None) => match sample_brick(density_source, chunk, id, scale) {
+            Some(brick) => march_brick(&brick, id, scale, chunk, triangles),
+            None => 0,
+        },
+        (Mesher::SurfaceNets | Mesher::DualContouring, _) => {
+            dual_brick(density_source, chunk, id, scale, mesher, triangles)
         }
+    }
+}
 
-        // +X face (x near chunk_size)
-        if x > chunk_size - boundary_threshold {
-            if neighbor_lods[1] != 255 && neighbor_lods[1] < 10 {
-                let neighbor_lod = neighbor_lods[1];
-                if neighbor_lod > current_lod {
-                    let neighbor_scale = 2_u32.pow(neighbor_lod) as f32;
-                    y = (y / neighbor_scale).round() * neighbor_scale;
-                    z = (z / neighbor_scale).round() * neighbor_scale;
+// Marches the cells of one surface brick, returns how many the surface passes through
+fn march_brick(
+    brick: &Brick,
+    brick_pos: [u32; 3],
+    scale: f32,
+    chunk: [i32; 3],
+    triangles: &mut Vec<[Vertex; 3]>,
+) -> u32 {
+    let mut density = 0;
+
+    // Triple nested loop over COMPRESSION³ cells
+    for z in 0..COMPRESSION {
+        for y in 0..COMPRESSION {
+            for x in 0..COMPRESSION {
+                // Get the 8 corner values and normals of the cube from the brick
+                let mut cube_values = [0.0f32; 8];
+                let mut cube_normals = [[0.0f32; 3]; 8];
+
+                for (i, corner_offset) in CUBE_VERTICES.iter().enumerate() {
+                    let pos = [
+                        x + corner_offset[0] as u32,
+                        y + corner_offset[1] as u32,
+                        z + corner_offset[2] as u32,
+                    ];
+                    cube_values[i] = brick.density(pos);
+                    cube_normals[i] = brick.normal(pos);
                 }
-            }
-        }
 
-        // -Y face (y near 0)
-        if y < boundary_threshold {
-            if neighbor_lods[2] != 255 && neighbor_lods[2] < 10 {
-                let neighbor_lod = neighbor_lods[2];
-                if neighbor_lod > current_lod {
-                    let neighbor_scale = 2_u32.pow(neighbor_lod) as f32;
-                    x = (x / neighbor_scale).round() * neighbor_scale;
-                    z = (z / neighbor_scale).round() * neighbor_scale;
+                let voxel = [
+                    x + brick_pos[0] * COMPRESSION,
+                    y + brick_pos[1] * COMPRESSION,
+                    z + brick_pos[2] * COMPRESSION,
+                ];
+                if march_cell(&cube_values, &cube_normals, voxel, scale, chunk, triangles) {
+                    density += 1;
                 }
             }
         }
+    }
 
-        // +Y face (y near chunk_size)
-        if y > chunk_size - boundary_threshold {
-            if neighbor_lods[3] != 255 && neighbor_lods[3] < 10 {
-                let neighbor_lod = neighbor_lods[3];
-                if neighbor_lod > current_lod {
-                    let neighbor_scale = 2_u32.pow(neighbor_lod) as f32;
-                    x = (x / neighbor_scale).round() * neighbor_scale;
-                    z = (z / neighbor_scale).round() * neighbor_scale;
-                }
+    density
+}
+
+// Append every meshlet and then every skirt to the chunk buffers, one draw command
+// each, then the extra draws of meshlets too large for u16 indices
+fn assemble(
+    meshlets: Vec<Meshlet>,
+    skirts: Vec<Meshlet>,
+    chunk: [i32; 3],
+    options: MeshOptions,
+) -> Chunk {
+    let index_format = options.index_format;
+    let mut chunk = Chunk {
+        // Skirts are not part of the meshlet grid, so they get no density entry
+        densities: meshlets.iter().map(|meshlet| meshlet.density).collect(),
+        vertex_counts: Vec::with_capacity(meshlets.len() + skirts.len()),
+        commands: Vec::with_capacity(meshlets.len() + skirts.len()),
+        split_from: Vec::new(),
+        bounds: Vec::with_capacity((meshlets.len() + skirts.len()) * BOUNDS_STRIDE),
+        vertex_format: options.vertex_format,
+        position_origin: position_origin(chunk),
+        vertices: Vec::new(),
+        normals: Vec::new(),
+        material_colors: Vec::new(), // u32 packed for material colors
+        colors: Vec::new(), // u32 packed for lit colors (initialized same as material_colors)
+        packed_vertices: Vec::new(),
+        index_format,
+        indices: Vec::new(),
+        indices_u32: Vec::new(),
+    };
+
+    let mut splits = Vec::new();
+    for (draw, meshlet) in meshlets.into_iter().chain(skirts).enumerate() {
+        if index_format == IndexFormat::Uint16 && meshlet.positions.len() > MAX_U16_VERTICES {
+            let mut parts = meshlet.split(MAX_U16_VERTICES).into_iter();
+            if let Some(first) = parts.next() {
+                chunk.append(&first);
             }
+            splits.extend(parts.map(|part| (draw as u32, part)));
+        } else {
+            chunk.append(&meshlet);
         }
+    }
+    for (draw, part) in splits {
+        chunk.split_from.push(draw);
+        chunk.append(&part);
+    }
 
-        // -Z face (z near 0)
-        if z < boundary_threshold {
-            if neighbor_lods[4] != 255 && neighbor_lods[4] < 10 {
-                let neighbor_lod = neighbor_lods[4];
-                if neighbor_lod > current_lod {
-                    let neighbor_scale = 2_u32.pow(neighbor_lod) as f32;
-                    x = (x / neighbor_scale).round() * neighbor_scale;
-                    y = (y / neighbor_scale).round() * neighbor_scale;
-                }
-            }
+    chunk
+}
+
+impl Chunk {
+    // Appends a meshlet's geometry and its draw command
+    fn append(&mut self, meshlet: &Meshlet) {
+        let first_index = self.index_len();
+        let vertex_offset = self.vertex_count();
+        self.write(meshlet, vertex_offset, first_index);
+
+        self.vertex_counts.push(meshlet.positions.len() as u32);
+        self.bounds.extend(
+            MeshletBounds::from_triangles(&meshlet.positions, &meshlet.indices).to_floats(),
+        );
+
+        self.commands.push(Command {
+            index_count: meshlet.indices.len() as u32,
+            instance_count: 1,
+            first_index: first_index as u32,
+            base_vertex: vertex_offset as i32,
+            first_instance: 0,
+        });
+    }
+
+    // Writes a meshlet over draw `draw`, into the draw's old ranges when it
+    // fits and after the end of the buffers otherwise
+    fn replace(&mut self, draw: usize, meshlet: &Meshlet) -> PatchedDraw {
+        let old = self.commands[draw].clone();
+        let vertex_count = meshlet.positions.len() as u32;
+        let index_count = meshlet.indices.len() as u32;
+        let first_vertex = if vertex_count <= self.vertex_counts[draw] {
+            old.base_vertex as usize
+        } else {
+            self.vertex_count()
+        };
+        let first_index = if index_count <= old.index_count {
+            old.first_index as usize
+        } else {
+            self.index_len()
+        };
+        self.write(meshlet, first_vertex, first_index);
+
+        if let Some(density) = self.densities.get_mut(draw) {
+            *density = meshlet.density;
+        }
+        self.vertex_counts[draw] = vertex_count;
+        self.bounds[draw * BOUNDS_STRIDE..(draw + 1) * BOUNDS_STRIDE].copy_from_slice(
+            &MeshletBounds::from_triangles(&meshlet.positions, &meshlet.indices).to_floats(),
+        );
+        let command = Command {
+            index_count,
+            first_index: first_index as u32,
+            base_vertex: first_vertex as i32,
+            ..old
+        };
+        self.commands[draw] = command.clone();
+        PatchedDraw {
+            draw: draw as u32,
+            vertex_count,
+            command,
         }
+    }
 
-        // +Z face (z near chunk_size)
-        if z > chunk_size - boundary_threshold {
-            if neighbor_lods[5] != 255 && neighbor_lods[5] < 10 {
-                let neighbor_lod = neighbor_lods[5];
-                if neighbor_lod > current_lod {
-                    let neighbor_scale = 2_u32.pow(neighbor_lod) as f32;
-                    x = (x / neighbor_scale).round() * neighbor_scale;
-                    y = (y / neighbor_scale).round() * neighbor_scale;
-                }
+    fn index_len(&self) -> usize {
+        match self.index_format {
+            IndexFormat::Uint16 => self.indices.len(),
+            IndexFormat::Uint32 => self.indices_u32.len(),
+        }
+    }
+
+    // Writes a meshlet's vertices and indices from the given elements on,
+    // over whatever is there and past the end of the buffers
+    fn write(&mut self, meshlet: &Meshlet, first_vertex: usize, first_index: usize) {
+        match self.vertex_format {
+            VertexFormat::Float => {
+                // Append local data to global arrays
+                put(
+                    &mut self.vertices,
+                    first_vertex * 4,
+                    meshlet
+                        .positions
+                        .iter()
+                        .flat_map(|v| [v[0], v[1], v[2], 1.0]),
+                );
+                // Normals need padding to 16 bytes (vec3<f32> in storage buffer has 16-byte stride)
+                put(
+                    &mut self.normals,
+                    first_vertex * 4,
+                    meshlet.normals.iter().flat_map(|n| [n[0], n[1], n[2], 0.0]),
+                );
+
+                // Store material colors as u32 packed
+                put(
+                    &mut self.material_colors,
+                    first_vertex,
+                    meshlet.colors.iter().copied(),
+                );
+
+                // Initialize lit colors same as material colors (lighting will update these on GPU)
+                put(
+                    &mut self.colors,
+                    first_vertex,
+                    meshlet.colors.iter().copied(),
+                );
+            }
+            VertexFormat::Packed => {
+                let origin = self.position_origin;
+                put(
+                    &mut self.packed_vertices,
+                    first_vertex * PACKED_WORDS,
+                    meshlet
+                        .positions
+                        .iter()
+                        .zip(&meshlet.normals)
+                        .zip(&meshlet.colors)
+                        .flat_map(|((p, n), c)| pack_vertex(origin, *p, *n, *c)),
+                );
             }
         }
 
-        // Write back adjusted coordinates
-        vertices[i] = x;
-        vertices[i + 1] = y;
-        vertices[i + 2] = z;
+        // Write indices, split meshlets never exceed the u16 range
+        match self.index_format {
+            IndexFormat::Uint16 => put(
+                &mut self.indices,
+                first_index,
+                meshlet.indices.iter().map(|i| *i as u16),
+            ),
+            IndexFormat::Uint32 => put(
+                &mut self.indices_u32,
+                first_index,
+                meshlet.indices.iter().copied(),
+            ),
+        }
+    }
+}
+
+// Overwrites `buffer` from `at` on, growing it where the values run past its end
+fn put<T>(buffer: &mut Vec<T>, at: usize, values: impl IntoIterator<Item = T>) {
+    let mut values = values.into_iter();
+    for slot in buffer.iter_mut().skip(at) {
+        match values.next() {
+            Some(value) => *slot = value,
+            None => return,
+        }
     }
+    buffer.extend(values);
 }
@@ -1,5 +1,15 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
+use crate::interval::Interval;
+use crate::parallel::MaybeSync;
+use crate::perlin::{perlin, shader_noise};
+use crate::simd::terrain_row;
+use crate::simplex::opensimplex2;
+
 // Fixed world space size for one chunk
 pub const SIZE: i32 = 256;
 
@@ -20,20 +30,167 @@ const NOISE_TABLE: [u8; 256] = [
     128, 195, 78, 66, 215, 61, 156, 180,
 ];
 
-fn noise_lookup(x: i32, y: i32, z: i32) -> f32 {
+/// Seeded permutation table used to hash lattice coordinates.
+///
+/// Seed 0 keeps the hand-crafted `NOISE_TABLE` so existing worlds stay unchanged.
+#[derive(Clone, Copy)]
+pub struct Permutation([u8; 256]);
+
+impl Permutation {
+    pub fn from_seed(seed: u32) -> Self {
+        if seed == 0 {
+            return Permutation(NOISE_TABLE);
+        }
+
+        let mut table = [0u8; 256];
+        for (i, value) in table.iter_mut().enumerate() {
+            *value = i as u8;
+        }
+
+        // Fisher-Yates shuffle driven by splitmix64 so every platform gets the same table
+        let mut state = seed as u64;
+        for i in (1..256).rev() {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^= z >> 31;
+            let j = (z % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+
+        Permutation(table)
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, i: usize) -> usize {
+        self.0[i & 255] as usize
+    }
+}
+
+/// Noise function used for every octave and the domain warp
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseKind {
+    /// Trilinear value noise over the permutation table (original terrain)
+    Value = 0,
+    /// Improved Perlin gradient noise
+    Perlin = 1,
+    /// OpenSimplex2 gradient noise, fewer axis-aligned artifacts than Perlin
+    #[serde(rename = "opensimplex2")]
+    OpenSimplex2 = 2,
+    /// Port of `noise3` from perlin.wgsl, matches the GPU shader (ignores the seed)
+    Shader = 3,
+}
+
+impl NoiseKind {
+    /// Bound on the absolute noise value, used to classify regions without sampling.
+    /// Gradient noises overshoot 1.0 slightly (Perlin peaks around 1.02 here), so they get extra headroom.
+    pub(crate) fn max_abs(self) -> f32 {
+        match self {
+            NoiseKind::Value | NoiseKind::Shader => 1.0 + 1e-4,
+            NoiseKind::Perlin | NoiseKind::OpenSimplex2 => 1.1,
+        }
+    }
+}
+
+/// Terrain generator settings. Everything needed to reproduce a world lives here.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy)]
+pub struct TerrainParams {
+    seed: u32,
+    permutation: Permutation,
+    pub noise: NoiseKind,
+    pub warp_scale: f32,
+    pub warp_amount: f32,
+    pub octaves: u32,
+    pub frequency: f32,
+    pub amplitude: f32,
+    pub persistence: f32,
+    pub lacunarity: f32,
+    pub ridged_octaves: u32,
+    pub billow_octaves: u32,
+    pub base_height: f32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl TerrainParams {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(seed: u32) -> TerrainParams {
+        TerrainParams {
+            seed,
+            permutation: Permutation::from_seed(seed),
+            noise: NoiseKind::Value,
+            warp_scale: 0.002,
+            warp_amount: 30.0,
+            octaves: 5,
+            frequency: 0.003,
+            amplitude: 80.0,
+            persistence: 0.3,
+            lacunarity: 4.0,
+            ridged_octaves: 2,
+            billow_octaves: 1,
+            base_height: 64.0,
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl TerrainParams {
+    pub(crate) fn permutation(&self) -> &Permutation {
+        &self.permutation
+    }
+}
+
+impl Default for TerrainParams {
+    fn default() -> Self {
+        TerrainParams::new(0)
+    }
+}
+
+pub(crate) fn noise_lookup(perm: &Permutation, x: i32, y: i32, z: i32) -> f32 {
     let xi = (x & 255) as usize;
     let yi = (y & 255) as usize;
     let zi = (z & 255) as usize;
 
-    let h1 = NOISE_TABLE[xi] as usize;
-    let h2 = NOISE_TABLE[(h1 + yi) & 255] as usize;
-    let h3 = NOISE_TABLE[(h2 + zi) & 255];
+    let h1 = perm.get(xi);
+    let h2 = perm.get(h1 + yi);
+    let h3 = perm.get(h2 + zi);
 
     // Normalize to -1.0 to 1.0
     (h3 as f32 / 127.5) - 1.0
 }
 
-fn sample_noise(x: f32, y: f32, z: f32) -> f32 {
+/// Derivative of a trilinear blend of corner values (ordered 000, 100, 010, 110,
+/// 001, 101, 011, 111) with interpolants `s` whose derivatives are `ds`.
+/// Generic so the SIMD terrain shares the exact operation order.
+#[inline(always)]
+pub(crate) fn trilinear_derivative<T>(n: [T; 8], s: [T; 3], ds: [T; 3]) -> [T; 3]
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Output = T> + Neg<Output = T>,
+{
+    let k1 = n[1] - n[0];
+    let k2 = n[2] - n[0];
+    let k3 = n[4] - n[0];
+    let k4 = n[0] - n[1] - n[2] + n[3];
+    let k5 = n[0] - n[2] - n[4] + n[6];
+    let k6 = n[0] - n[1] - n[4] + n[5];
+    let k7 = -n[0] + n[1] + n[2] - n[3] + n[4] - n[5] - n[6] + n[7];
+
+    let [u, v, w] = s;
+    [
+        ds[0] * (k1 + k4 * v + k6 * w + k7 * v * w),
+        ds[1] * (k2 + k5 * w + k4 * u + k7 * w * u),
+        ds[2] * (k3 + k6 * u + k5 * v + k7 * u * v),
+    ]
+}
+
+fn value_noise(perm: &Permutation, x: f32, y: f32, z: f32) -> (f32, [f32; 3]) {
     let x0 = x.floor() as i32;
     let y0 = y.floor() as i32;
     let z0 = z.floor() as i32;
@@ -48,14 +205,14 @@ fn sample_noise(x: f32, y: f32, z: f32) -> f32 {
     let sz = fz * fz * (3.0 - 2.0 * fz);
 
     // Sample 8 corners of the cube
-    let n000 = noise_lookup(x0, y0, z0);
-    let n100 = noise_lookup(x0 + 1, y0, z0);
-    let n010 = noise_lookup(x0, y0 + 1, z0);
-    let n110 = noise_lookup(x0 + 1, y0 + 1, z0);
-    let n001 = noise_lookup(x0, y0, z0 + 1);
-    let n101 = noise_lookup(x0 + 1, y0, z0 + 1);
-    let n011 = noise_lookup(x0, y0 + 1, z0 + 1);
-    let n111 = noise_lookup(x0 + 1, y0 + 1, z0 + 1);
+    let n000 = noise_lookup(perm, x0, y0, z0);
+    let n100 = noise_lookup(perm, x0 + 1, y0, z0);
+    let n010 = noise_lookup(perm, x0, y0 + 1, z0);
+    let n110 = noise_lookup(perm, x0 + 1, y0 + 1, z0);
+    let n001 = noise_lookup(perm, x0, y0, z0 + 1);
+    let n101 = noise_lookup(perm, x0 + 1, y0, z0 + 1);
+    let n011 = noise_lookup(perm, x0, y0 + 1, z0 + 1);
+    let n111 = noise_lookup(perm, x0 + 1, y0 + 1, z0 + 1);
 
     // Trilinear interpolation
     let nx00 = n000 * (1.0 - sx) + n100 * sx;
@@ -66,19 +223,66 @@ fn sample_noise(x: f32, y: f32, z: f32) -> f32 {
     let nxy0 = nx00 * (1.0 - sy) + nx10 * sy;
     let nxy1 = nx01 * (1.0 - sy) + nx11 * sy;
 
-    nxy0 * (1.0 - sz) + nxy1 * sz
+    let value = nxy0 * (1.0 - sz) + nxy1 * sz;
+
+    // Smoothstep derivative: 6t - 6t²
+    let ds = [
+        6.0 * fx * (1.0 - fx),
+        6.0 * fy * (1.0 - fy),
+        6.0 * fz * (1.0 - fz),
+    ];
+    let gradient = trilinear_derivative(
+        [n000, n100, n010, n110, n001, n101, n011, n111],
+        [sx, sy, sz],
+        ds,
+    );
+
+    (value, gradient)
+}
+
+/// Noise value and its analytic gradient at a point
+pub(crate) fn noise3(
+    kind: NoiseKind,
+    perm: &Permutation,
+    x: f32,
+    y: f32,
+    z: f32,
+) -> (f32, [f32; 3]) {
+    match kind {
+        NoiseKind::Value => value_noise(perm, x, y, z),
+        NoiseKind::Perlin => perlin(perm, x, y, z),
+        NoiseKind::OpenSimplex2 => opensimplex2(perm, x, y, z),
+        NoiseKind::Shader => shader_noise(x, y, z),
+    }
+}
+
+/// Range of the ridged shaping (1 - 2|n|, then cubed) for noise values in `n`
+pub(crate) fn ridged_bounds(n: Interval) -> Interval {
+    (Interval::point(1.0) - n.abs().scale(2.0)).map_increasing(|r| r * r * r.signum())
+}
+
+/// Range of the billow shaping (2|n| - 1) for noise values in `n`
+pub(crate) fn billow_bounds(n: Interval) -> Interval {
+    n.abs().scale(2.0) + Interval::point(-1.0)
+}
+
+fn sample_noise(params: &TerrainParams, x: f32, y: f32, z: f32) -> (f32, [f32; 3]) {
+    noise3(params.noise, &params.permutation, x, y, z)
 }
 
-fn generate_sin_noise(pos: [f32; 3]) -> f32 {
+/// Terrain SDF and its analytic gradient in world units
+fn generate_sin_noise(params: &TerrainParams, pos: [f32; 3]) -> (f32, [f32; 3]) {
     // Domain warping for organic distortion
-    let warp_scale = 0.002;
-    let warp_amount = 30.0;
-    let warp_x = sample_noise(
+    let warp_scale = params.warp_scale;
+    let warp_amount = params.warp_amount;
+    let (warp_x, warp_x_grad) = sample_noise(
+        params,
         pos[0] * warp_scale,
         pos[1] * warp_scale,
         pos[2] * warp_scale,
     );
-    let warp_z = sample_noise(
+    let (warp_z, warp_z_grad) = sample_noise(
+        params,
         (pos[0] + 73.2) * warp_scale,
         (pos[1] + 39.7) * warp_scale,
         (pos[2] + 127.1) * warp_scale,
@@ -91,42 +295,101 @@ fn generate_sin_noise(pos: [f32; 3]) -> f32 {
         pos[2] + warp_z * warp_amount,
     ];
 
-    // Fractal noise with 5 octaves - using much larger amplitudes for dramatic terrain
-    let mut height = 0.0;
-    let mut amplitude = 80.0;
-    let mut frequency = 0.003;
-    let persistence = 0.3;
-    let lacunarity = 4.0;
+    // Jacobian of the warp (row i = d warped_pos[i] / d pos)
+    let warp_factor = warp_amount * warp_scale;
+    let warp_jacobian = [
+        [
+            1.0 + warp_x_grad[0] * warp_factor,
+            warp_x_grad[1] * warp_factor,
+            warp_x_grad[2] * warp_factor,
+        ],
+        [0.0, 1.0, 0.0],
+        [
+            warp_z_grad[0] * warp_factor,
+            warp_z_grad[1] * warp_factor,
+            1.0 + warp_z_grad[2] * warp_factor,
+        ],
+    ];
 
-    for i in 0..5 {
-        let mut noise_val = sample_noise(
+    // Fractal noise - using large amplitudes for dramatic terrain
+    let mut height = 0.0;
+    let mut height_grad = [0.0f32; 3]; // d height / d warped_pos
+    let mut amplitude = params.amplitude;
+    let mut frequency = params.frequency;
+    let billow_end = params.ridged_octaves + params.billow_octaves;
+
+    for i in 0..params.octaves {
+        let (mut noise_val, noise_grad) = sample_noise(
+            params,
             warped_pos[0] * frequency,
             warped_pos[1] * frequency,
             warped_pos[2] * frequency,
         );
+        let mut slope = 1.0; // d shaped / d noise_val
 
-        // Use ridged noise for first 2 octaves (creates mountain ridges)
-        if i < 2 {
+        // Use ridged noise for the first octaves (creates mountain ridges)
+        if i < params.ridged_octaves {
             // Ridged noise: inverts and sharpens to create ridges
+            let sign = noise_val.signum();
             noise_val = 1.0 - 2.0 * noise_val.abs(); // Maps to -1 to 1 range with sharp ridges
+            slope = -4.0 * noise_val.abs() * sign;
             noise_val = noise_val * noise_val * noise_val.signum(); // Sharpen the ridges (cubic)
         }
-        // Use billowy noise for octave 2 (creates rolling hills)
-        else if i == 2 {
+        // Use billowy noise for the next octaves (creates rolling hills)
+        else if i < billow_end {
+            slope = 2.0 * noise_val.signum();
             noise_val = noise_val.abs() * 2.0 - 1.0; // Billowy (puffy)
         }
-        // Standard noise for fine detail (octaves 3, 4)
+        // Standard noise for fine detail
 
         height += noise_val * amplitude;
-        amplitude *= persistence;
-        frequency *= lacunarity;
+        let factor = slope * amplitude * frequency;
+        height_grad[0] += noise_grad[0] * factor;
+        height_grad[1] += noise_grad[1] * factor;
+        height_grad[2] += noise_grad[2] * factor;
+
+        amplitude *= params.persistence;
+        frequency *= params.lacunarity;
     }
 
-    // Base terrain height - centered around y=32
-    let surface_height = 64.0 + height;
+    // Base terrain height
+    let surface_height = params.base_height + height;
+
+    // Chain rule through the warp: d height / d pos = J^T * d height / d warped_pos
+    let mut gradient = [0.0f32; 3];
+    for (j, g) in gradient.iter_mut().enumerate() {
+        *g = -(height_grad[0] * warp_jacobian[0][j]
+            + height_grad[1] * warp_jacobian[1][j]
+            + height_grad[2] * warp_jacobian[2][j]);
+    }
+    gradient[1] += 1.0;
 
     // SDF: distance from current Y to surface
-    pos[1] - surface_height // negative below, positive above
+    (pos[1] - surface_height, gradient) // negative below, positive above
+}
+
+/// Density range of the terrain over a box. The fractal height is bounded
+/// independently of x and z (so the warp does not matter), which is enough to
+/// tell chunks far above or below the surface apart.
+fn terrain_bounds(params: &TerrainParams, min: [f32; 3], max: [f32; 3]) -> Interval {
+    let noise = Interval::around(0.0, params.noise.max_abs());
+    let billow_end = params.ridged_octaves + params.billow_octaves;
+
+    let mut height = Interval::point(params.base_height);
+    let mut amplitude = params.amplitude;
+    for i in 0..params.octaves {
+        let shaped = if i < params.ridged_octaves {
+            ridged_bounds(noise)
+        } else if i < billow_end {
+            billow_bounds(noise)
+        } else {
+            noise
+        };
+        height = height + shaped.scale(amplitude);
+        amplitude *= params.persistence;
+    }
+
+    Interval::new(min[1], max[1]) - height
 }
 
 fn generate_sin_color(pos: [f32; 3]) -> u32 {
@@ -143,62 +406,148 @@ fn generate_sin_color(pos: [f32; 3]) -> u32 {
 /// Returns Float32Array containing interleaved density and color data
 /// Format: [density0, color0_as_f32, density1, color1_as_f32, ...]
 /// Each pair represents one voxel (8 bytes total: f32 + u32 reinterpreted as f32)
-#[wasm_bindgen]
-pub fn noise_for_chunk(x: i32, y: i32, z: i32, resolution: u32) -> Box<[f32]> {
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn noise_for_chunk(
+    params: &TerrainParams,
+    x: i32,
+    y: i32,
+    z: i32,
+    resolution: u32,
+) -> Box<[f32]> {
     let voxel_size = resolution + 1; // 257 for gridSize 256
     let chunk_offset = [x * SIZE, y * SIZE, z * SIZE];
+    let field = only_noise_for_chunk(params, x, y, z, resolution, 1.0);
+
+    let mut result = Vec::with_capacity(field.densities.len() * 2); // density + color per voxel
+
+    // Voxel data is in the same order as WGSL: z * size² + y * size + x
+    for (idx, density) in field.densities.iter().enumerate() {
+        let idx = idx as u32;
+        let world_pos = [
+            (idx % voxel_size) as f32 + chunk_offset[0] as f32,
+            (idx / voxel_size % voxel_size) as f32 + chunk_offset[1] as f32,
+            (idx / (voxel_size * voxel_size)) as f32 + chunk_offset[2] as f32,
+        ];
+        let color = generate_sin_color(world_pos);
+
+        // Store density as f32
+        result.push(*density);
+        // Store color (u32) reinterpreted as f32 for buffer transfer
+        result.push(f32::from_bits(color));
+    }
+
+    result.into_boxed_slice()
+}
 
-    let total_voxels = (voxel_size * voxel_size * voxel_size) as usize;
-    let mut result = Vec::with_capacity(total_voxels * 2); // density + color per voxel
+/// Anything that can be sampled as a terrain SDF (negative inside, positive
+/// outside) together with its gradient in world units
+pub trait Density: MaybeSync {
+    fn sample(&self, pos: [f32; 3]) -> (f32, [f32; 3]);
 
-    // Generate voxel data in the same order as WGSL: z * size² + y * size + x
-    for vz in 0..voxel_size {
-        for vy in 0..voxel_size {
-            for vx in 0..voxel_size {
-                let pos = [vx, vy, vz];
+    /// Conservative density range over the box `min..=max`. The default is
+    /// unbounded, so every region is treated as possibly containing the surface.
+    fn bounds(&self, min: [f32; 3], max: [f32; 3]) -> Interval {
+        let _ = (min, max);
+        Interval::UNBOUNDED
+    }
 
-                let world_pos = [
-                    pos[0] as f32 + chunk_offset[0] as f32,
-                    pos[1] as f32 + chunk_offset[1] as f32,
-                    pos[2] as f32 + chunk_offset[2] as f32,
-                ];
+    /// Samples `(x, y, z)` for every `x` in `xs` and appends the results to
+    /// `field`. Implementations may evaluate several points per call, the
+    /// default samples them one at a time.
+    fn sample_row(&self, xs: &[f32], y: f32, z: f32, field: &mut DensityField) {
+        for x in xs {
+            let (value, gradient) = self.sample([*x, y, z]);
+            field.densities.push(value);
+            field.gradients.push(gradient);
+        }
+    }
 
-                let density = generate_sin_noise(world_pos);
-                let color = generate_sin_color(world_pos);
+    /// Material colour of a surface vertex at `pos`, given the colour the
+    /// terrain shading picked for it. The default keeps that colour.
+    fn paint(&self, pos: [f32; 3], color: u32) -> u32 {
+        let _ = pos;
+        color
+    }
+}
 
-                // Store density as f32
-                result.push(density);
-                // Store color (u32) reinterpreted as f32 for buffer transfer
-                result.push(f32::from_bits(color));
+impl Density for TerrainParams {
+    fn sample(&self, pos: [f32; 3]) -> (f32, [f32; 3]) {
+        generate_sin_noise(self, pos)
+    }
+
+    fn bounds(&self, min: [f32; 3], max: [f32; 3]) -> Interval {
+        terrain_bounds(self, min, max)
+    }
+
+    fn sample_row(&self, xs: &[f32], y: f32, z: f32, field: &mut DensityField) {
+        // The SIMD path matches generate_sin_noise bit for bit, rows it cannot handle stay scalar
+        if !terrain_row(self, xs, y, z, field) {
+            for x in xs {
+                let (value, gradient) = generate_sin_noise(self, [*x, y, z]);
+                field.densities.push(value);
+                field.gradients.push(gradient);
             }
         }
     }
+}
 
-    result.into_boxed_slice()
+/// Densities and analytic gradients for a block of voxels, stored in the
+/// same z * size² + y * size + x order
+pub struct DensityField {
+    pub densities: Vec<f32>,
+    pub gradients: Vec<[f32; 3]>,
 }
 
-pub fn only_noise_for_chunk(x: i32, y: i32, z: i32, resolution: u32, scale: f32) -> Vec<f32> {
+/// Samples the full (resolution + 1)³ voxel grid of a chunk
+pub fn only_noise_for_chunk<D: Density + ?Sized>(
+    density: &D,
+    x: i32,
+    y: i32,
+    z: i32,
+    resolution: u32,
+    scale: f32,
+) -> DensityField {
     let voxel_size = resolution + 1; // 257 for gridSize 256
+    noise_for_region(
+        density,
+        x,
+        y,
+        z,
+        scale,
+        [0, 0, 0],
+        [voxel_size, voxel_size, voxel_size],
+    )
+}
+
+/// Samples the `size` voxels starting at voxel `min` of a chunk
+pub fn noise_for_region<D: Density + ?Sized>(
+    density: &D,
+    x: i32,
+    y: i32,
+    z: i32,
+    scale: f32,
+    min: [u32; 3],
+    size: [u32; 3],
+) -> DensityField {
     let chunk_offset = [x * SIZE, y * SIZE, z * SIZE];
 
-    let total_voxels = (voxel_size * voxel_size * voxel_size) as usize;
-    let mut result = Vec::with_capacity(total_voxels);
-
-    for vz in 0..voxel_size {
-        for vy in 0..voxel_size {
-            for vx in 0..voxel_size {
-                let pos = [vx, vy, vz];
-                let world_pos = [
-                    pos[0] as f32 * scale + chunk_offset[0] as f32,
-                    pos[1] as f32 * scale + chunk_offset[1] as f32,
-                    pos[2] as f32 * scale + chunk_offset[2] as f32,
-                ];
-                let density = generate_sin_noise(world_pos);
-
-                result.push(density);
-            }
+    let total_voxels = (size[0] * size[1] * size[2]) as usize;
+    let mut field = DensityField {
+        densities: Vec::with_capacity(total_voxels),
+        gradients: Vec::with_capacity(total_voxels),
+    };
+
+    // One call per X row, so densities can evaluate several voxels at once
+    let xs: Vec<f32> = (min[0]..min[0] + size[0])
+        .map(|vx| vx as f32 * scale + chunk_offset[0] as f32)
+        .collect();
+    for vz in min[2]..min[2] + size[2] {
+        for vy in min[1]..min[1] + size[1] {
+            let y = vy as f32 * scale + chunk_offset[1] as f32;
+            let z = vz as f32 * scale + chunk_offset[2] as f32;
+            density.sample_row(&xs, y, z, &mut field);
         }
     }
 
-    result
+    field
 }
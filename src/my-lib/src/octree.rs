@@ -0,0 +1,555 @@
+use crate::interval::Region;
+use crate::mesh::gradient_to_normal;
+use crate::noise::{noise_for_region, Density, DensityField, SIZE};
+use crate::parallel::map_init;
+
+/// Cells per brick edge, matching the mesher's meshlet size
+pub const BRICK_SIZE: u32 = 8;
+
+// Samples per brick edge (bricks share their border samples with neighbours)
+const BRICK_POINTS: usize = BRICK_SIZE as usize + 1;
+
+/// Densities and surface normals of one 8³ cell brick that the isosurface passes through
+pub struct Brick {
+    densities: Vec<f32>,
+    normals: Vec<[f32; 3]>,
+}
+
+impl Brick {
+    fn index(pos: [u32; 3]) -> usize {
+        (pos[2] as usize * BRICK_POINTS + pos[1] as usize) * BRICK_POINTS + pos[0] as usize
+    }
+
+    /// Density at a brick-local sample position (0..=8 on each axis)
+    pub fn density(&self, pos: [u32; 3]) -> f32 {
+        self.densities[Self::index(pos)]
+    }
+
+    /// Normal at a brick-local sample position (0..=8 on each axis)
+    pub fn normal(&self, pos: [u32; 3]) -> [f32; 3] {
+        self.normals[Self::index(pos)]
+    }
+}
+
+enum Node {
+    Air,
+    Solid,
+    Brick(Box<Brick>),
+    Branch(Box<[Node; 8]>),
+}
+
+/// What the octree holds for one brick of the chunk
+pub enum BrickRef<'a> {
+    Air,
+    Solid,
+    Surface(&'a Brick),
+}
+
+/// Sparse voxel octree over a chunk's (resolution + 1)³ samples.
+///
+/// Leaves are 8³ cell bricks. A brick whose samples all have the same sign
+/// produces no triangles, so it is stored as plain air or solid, and eight
+/// uniform siblings of the same kind collapse into their parent. Only bricks
+/// crossing the isosurface keep their densities and normals.
+pub struct VoxelOctree {
+    root: Node,
+    bricks_per_axis: u32,
+}
+
+impl VoxelOctree {
+    /// Classifies every brick from the density bounds first, then samples
+    /// each slab of bricks only over the rectangle around its surface bricks.
+    /// Uniform bricks inside that rectangle are sampled along with it, those
+    /// outside never are, and the dense grid is never held in memory.
+    pub fn from_density<D: Density + ?Sized>(
+        density: &D,
+        x: i32,
+        y: i32,
+        z: i32,
+        resolution: u32,
+        scale: f32,
+    ) -> Self {
+        let bricks_per_axis = resolution / BRICK_SIZE;
+        if bricks_per_axis == 0 {
+            return Self::empty();
+        }
+
+        // Slabs of bricks along z are independent, each one samples its surface bricks at once
+        let slabs: Vec<u32> = (0..bricks_per_axis).collect();
+        let mut leaves: Vec<Node> = map_init(
+            &slabs,
+            || (),
+            |_, bz| slab_leaves(density, [x, y, z], scale, bricks_per_axis, *bz),
+        )
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let root = build(&mut leaves, bricks_per_axis, [0, 0, 0], bricks_per_axis);
+        Self {
+            root,
+            bricks_per_axis,
+        }
+    }
+
+    /// Same octree as `from_density` at a coarser LOD, taken from the octree
+    /// of the same chunk at a finer one. Every coarse sample is a fine sample
+    /// of the same point, so it is copied from the fine surface bricks and
+    /// only the samples those dropped as uniform are evaluated again.
+    pub(crate) fn from_finer<D: Density + ?Sized>(
+        density: &D,
+        finer: &VoxelOctree,
+        x: i32,
+        y: i32,
+        z: i32,
+        resolution: u32,
+        scale: f32,
+    ) -> Self {
+        let bricks_per_axis = resolution / BRICK_SIZE;
+        if bricks_per_axis == 0 {
+            return Self::empty();
+        }
+        let factor = finer.bricks_per_axis / bricks_per_axis;
+        debug_assert!(factor > 1 && factor * bricks_per_axis == finer.bricks_per_axis);
+
+        let slabs: Vec<u32> = (0..bricks_per_axis).collect();
+        let mut leaves: Vec<Node> = map_init(
+            &slabs,
+            || DensityField {
+                densities: Vec::new(),
+                gradients: Vec::new(),
+            },
+            |field, bz| {
+                let mut leaves = Vec::with_capacity((bricks_per_axis * bricks_per_axis) as usize);
+                for by in 0..bricks_per_axis {
+                    for bx in 0..bricks_per_axis {
+                        leaves.push(derived_leaf(
+                            density,
+                            finer,
+                            factor,
+                            [x, y, z],
+                            scale,
+                            [bx, by, *bz],
+                            field,
+                        ));
+                    }
+                }
+                leaves
+            },
+        )
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let root = build(&mut leaves, bricks_per_axis, [0, 0, 0], bricks_per_axis);
+        Self {
+            root,
+            bricks_per_axis,
+        }
+    }
+
+    // Octree of a chunk coarser than one brick (LOD 6 and up), which has no cells to mesh
+    fn empty() -> Self {
+        Self {
+            root: Node::Air,
+            bricks_per_axis: 0,
+        }
+    }
+
+    /// Bricks along each axis of the chunk
+    pub(crate) fn bricks_per_axis(&self) -> u32 {
+        self.bricks_per_axis
+    }
+
+    /// Approximate heap and inline size in bytes
+    pub(crate) fn bytes(&self) -> usize {
+        fn node_bytes(node: &Node) -> usize {
+            match node {
+                Node::Air | Node::Solid => 0,
+                Node::Brick(brick) => {
+                    std::mem::size_of::<Brick>()
+                        + brick.densities.len() * std::mem::size_of::<f32>()
+                        + brick.normals.len() * std::mem::size_of::<[f32; 3]>()
+                }
+                Node::Branch(children) => {
+                    std::mem::size_of::<[Node; 8]>()
+                        + children.iter().map(node_bytes).sum::<usize>()
+                }
+            }
+        }
+        std::mem::size_of::<Self>() + node_bytes(&self.root)
+    }
+
+    /// Appends the nodes in depth-first order as u32 words:
+    /// 0 air, 1 solid, 2 a surface brick followed by its 729 densities and
+    /// 729 normals as float bits, 3 a branch followed by its eight children
+    pub(crate) fn write_words(&self, words: &mut Vec<u32>) {
+        fn write_node(node: &Node, words: &mut Vec<u32>) {
+            match node {
+                Node::Air => words.push(0),
+                Node::Solid => words.push(1),
+                Node::Brick(brick) => {
+                    words.push(2);
+                    words.extend(brick.densities.iter().map(|d| d.to_bits()));
+                    words.extend(brick.normals.iter().flatten().map(|n| n.to_bits()));
+                }
+                Node::Branch(children) => {
+                    words.push(3);
+                    for child in children.iter() {
+                        write_node(child, words);
+                    }
+                }
+            }
+        }
+        write_node(&self.root, words);
+    }
+
+    /// Reads the nodes `write_words` wrote for a chunk of `bricks_per_axis`
+    /// bricks, None when they do not form such an octree
+    pub(crate) fn read_words(
+        words: &mut impl Iterator<Item = u32>,
+        bricks_per_axis: u32,
+    ) -> Option<Self> {
+        fn read_node(words: &mut impl Iterator<Item = u32>, size: u32) -> Option<Node> {
+            Some(match words.next()? {
+                0 => Node::Air,
+                1 => Node::Solid,
+                2 if size == 1 => {
+                    let count = BRICK_POINTS.pow(3);
+                    let densities: Vec<f32> = words.take(count).map(f32::from_bits).collect();
+                    let mut normals = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        normals.push([
+                            f32::from_bits(words.next()?),
+                            f32::from_bits(words.next()?),
+                            f32::from_bits(words.next()?),
+                        ]);
+                    }
+                    if densities.len() != count {
+                        return None;
+                    }
+                    Node::Brick(Box::new(Brick { densities, normals }))
+                }
+                3 if size > 1 => {
+                    let mut children = Vec::with_capacity(8);
+                    for _ in 0..8 {
+                        children.push(read_node(words, size / 2)?);
+                    }
+                    Node::Branch(Box::new(children.try_into().ok()?))
+                }
+                _ => return None,
+            })
+        }
+        if bricks_per_axis != 0 && !bricks_per_axis.is_power_of_two() {
+            return None;
+        }
+        let root = read_node(words, bricks_per_axis)?;
+        Some(Self {
+            root,
+            bricks_per_axis,
+        })
+    }
+
+    /// Looks up the brick at brick coordinates `pos`
+    pub fn brick(&self, pos: [u32; 3]) -> BrickRef<'_> {
+        let mut node = &self.root;
+        let mut size = self.bricks_per_axis;
+        let mut pos = pos;
+        loop {
+            match node {
+                Node::Air => return BrickRef::Air,
+                Node::Solid => return BrickRef::Solid,
+                Node::Brick(brick) => return BrickRef::Surface(brick),
+                Node::Branch(children) => {
+                    size /= 2;
+                    let mut child = 0;
+                    for (axis, p) in pos.iter_mut().enumerate() {
+                        if *p >= size {
+                            child |= 1 << axis;
+                            *p -= size;
+                        }
+                    }
+                    node = &children[child];
+                }
+            }
+        }
+    }
+}
+
+// Leaves of the bricks with z index `bz`, in y then x order
+fn slab_leaves<D: Density + ?Sized>(
+    density: &D,
+    chunk: [i32; 3],
+    scale: f32,
+    bricks_per_axis: u32,
+    bz: u32,
+) -> Vec<Node> {
+    let [x, y, z] = chunk;
+    let chunk_offset = [x * SIZE, y * SIZE, z * SIZE];
+    let brick_extent = BRICK_SIZE as f32 * scale;
+    let mut regions = Vec::with_capacity((bricks_per_axis * bricks_per_axis) as usize);
+    // Brick range of this slab that needs sampling, as [min, max] per axis
+    let mut surface: Option<[[u32; 2]; 2]> = None;
+    for by in 0..bricks_per_axis {
+        for bx in 0..bricks_per_axis {
+            let min = [
+                (bx * BRICK_SIZE) as f32 * scale + chunk_offset[0] as f32,
+                (by * BRICK_SIZE) as f32 * scale + chunk_offset[1] as f32,
+                (bz * BRICK_SIZE) as f32 * scale + chunk_offset[2] as f32,
+            ];
+            let max = [
+                min[0] + brick_extent,
+                min[1] + brick_extent,
+                min[2] + brick_extent,
+            ];
+            let region = density.bounds(min, max).region();
+            if region == Region::Surface {
+                let range = surface.get_or_insert([[bx, bx], [by, by]]);
+                range[0] = [range[0][0].min(bx), range[0][1].max(bx)];
+                range[1] = [range[1][0].min(by), range[1][1].max(by)];
+            }
+            regions.push(region);
+        }
+    }
+
+    let slab = surface.map(|range| {
+        let min = [
+            range[0][0] * BRICK_SIZE,
+            range[1][0] * BRICK_SIZE,
+            bz * BRICK_SIZE,
+        ];
+        let size = [
+            (range[0][1] - range[0][0] + 1) * BRICK_SIZE + 1,
+            (range[1][1] - range[1][0] + 1) * BRICK_SIZE + 1,
+            BRICK_SIZE + 1,
+        ];
+        (
+            min,
+            size,
+            noise_for_region(density, x, y, z, scale, min, size),
+        )
+    });
+
+    let mut leaves = Vec::with_capacity(regions.len());
+    for by in 0..bricks_per_axis {
+        for bx in 0..bricks_per_axis {
+            let leaf = match (regions[(by * bricks_per_axis + bx) as usize], &slab) {
+                (Region::Empty, _) => Node::Air,
+                (Region::Full, _) => Node::Solid,
+                (Region::Surface, Some((min, size, field))) => brick_leaf(
+                    field,
+                    [
+                        (bx * BRICK_SIZE - min[0]) as usize,
+                        (by * BRICK_SIZE - min[1]) as usize,
+                    ],
+                    [size[0] as usize, size[1] as usize],
+                ),
+                (Region::Surface, None) => {
+                    unreachable!("surface bricks are always sampled")
+                }
+            };
+            leaves.push(leaf);
+        }
+    }
+    leaves
+}
+
+// Leaf of coarse brick `pos`, classified like `slab_leaves` with its samples
+// copied from the fine bricks it covers. Samples only uniform fine bricks
+// hold are evaluated again, one `sample_row` per row of the brick.
+fn derived_leaf<D: Density + ?Sized>(
+    density: &D,
+    finer: &VoxelOctree,
+    factor: u32,
+    chunk: [i32; 3],
+    scale: f32,
+    pos: [u32; 3],
+    field: &mut DensityField,
+) -> Node {
+    let chunk_offset = [0, 1, 2].map(|a| (chunk[a] * SIZE) as f32);
+    let min = [0, 1, 2].map(|a| (pos[a] * BRICK_SIZE) as f32 * scale + chunk_offset[a]);
+    let max = min.map(|v| v + BRICK_SIZE as f32 * scale);
+    match density.bounds(min, max).region() {
+        Region::Empty => return Node::Air,
+        Region::Full => return Node::Solid,
+        Region::Surface => {}
+    }
+
+    // The fine bricks this brick covers, x fastest
+    let side = factor as usize;
+    let fine: Vec<BrickRef> = (0..side.pow(3))
+        .map(|i| {
+            let offset = [i % side, i / side % side, i / (side * side)];
+            finer.brick([0, 1, 2].map(|a| pos[a] * factor + offset[a] as u32))
+        })
+        .collect();
+    if !fine.iter().any(|b| matches!(b, BrickRef::Surface(_))) {
+        // Neighbouring uniform bricks share samples, so they are all of one kind
+        return match fine[0] {
+            BrickRef::Solid => Node::Solid,
+            BrickRef::Air | BrickRef::Surface(_) => Node::Air,
+        };
+    }
+
+    // Fine bricks holding local sample `l` on one axis with its position in
+    // them, a sample on a fine brick border is in two of them
+    let holders = |l: usize| {
+        let fine = l * side;
+        let index = (fine / BRICK_SIZE as usize).min(side - 1);
+        let local = fine - index * BRICK_SIZE as usize;
+        let other = (local == 0 && index > 0).then(|| (index - 1, BRICK_SIZE as usize));
+        [Some((index, local)), other]
+    };
+    let copied = |l: [usize; 3]| {
+        let [hx, hy, hz] = l.map(holders);
+        for (bz, lz) in hz.into_iter().flatten() {
+            for (by, ly) in hy.into_iter().flatten() {
+                for (bx, lx) in hx.into_iter().flatten() {
+                    if let BrickRef::Surface(brick) = fine[(bz * side + by) * side + bx] {
+                        let local = [lx as u32, ly as u32, lz as u32];
+                        return Some((brick.density(local), brick.normal(local)));
+                    }
+                }
+            }
+        }
+        None
+    };
+
+    let count = BRICK_POINTS.pow(3);
+    let mut densities = Vec::with_capacity(count);
+    let mut normals = Vec::with_capacity(count);
+    let mut missing = Vec::with_capacity(BRICK_POINTS);
+    let mut xs = Vec::with_capacity(BRICK_POINTS);
+    for lz in 0..BRICK_POINTS {
+        for ly in 0..BRICK_POINTS {
+            missing.clear();
+            xs.clear();
+            for lx in 0..BRICK_POINTS {
+                match copied([lx, ly, lz]) {
+                    Some((value, normal)) => {
+                        densities.push(value);
+                        normals.push(normal);
+                    }
+                    None => {
+                        missing.push(densities.len());
+                        xs.push((pos[0] * BRICK_SIZE + lx as u32) as f32 * scale + chunk_offset[0]);
+                        densities.push(0.0);
+                        normals.push([0.0; 3]);
+                    }
+                }
+            }
+            if missing.is_empty() {
+                continue;
+            }
+            let y = (pos[1] * BRICK_SIZE + ly as u32) as f32 * scale + chunk_offset[1];
+            let z = (pos[2] * BRICK_SIZE + lz as u32) as f32 * scale + chunk_offset[2];
+            field.densities.clear();
+            field.gradients.clear();
+            density.sample_row(&xs, y, z, field);
+            for (i, index) in missing.iter().enumerate() {
+                densities[*index] = field.densities[i];
+                normals[*index] = gradient_to_normal(field.gradients[i]);
+            }
+        }
+    }
+
+    let solid = densities.iter().filter(|d| **d < 0.0).count();
+    if solid == 0 {
+        Node::Air
+    } else if solid == densities.len() {
+        Node::Solid
+    } else {
+        Node::Brick(Box::new(Brick { densities, normals }))
+    }
+}
+
+/// Samples brick `pos` of a chunk on its own, exactly as `VoxelOctree`
+/// would, for remeshing part of a chunk. None when it has no surface.
+pub(crate) fn sample_brick<D: Density + ?Sized>(
+    density: &D,
+    chunk: [i32; 3],
+    pos: [u32; 3],
+    scale: f32,
+) -> Option<Brick> {
+    let min = [0, 1, 2].map(|a| (pos[a] * BRICK_SIZE) as f32 * scale + (chunk[a] * SIZE) as f32);
+    let max = min.map(|v| v + BRICK_SIZE as f32 * scale);
+    if density.bounds(min, max).region() != Region::Surface {
+        return None;
+    }
+    let field = noise_for_region(
+        density,
+        chunk[0],
+        chunk[1],
+        chunk[2],
+        scale,
+        pos.map(|p| p * BRICK_SIZE),
+        [BRICK_POINTS as u32; 3],
+    );
+    match brick_leaf(&field, [0, 0], [BRICK_POINTS; 2]) {
+        Node::Brick(brick) => Some(*brick),
+        Node::Air | Node::Solid | Node::Branch(_) => None,
+    }
+}
+
+// Copies one brick out of a sampled slab, collapsing it when all samples share a sign
+fn brick_leaf(field: &DensityField, origin: [usize; 2], size: [usize; 2]) -> Node {
+    let rows = || {
+        (0..BRICK_POINTS).flat_map(move |lz| {
+            (0..BRICK_POINTS).map(move |ly| (lz * size[1] + origin[1] + ly) * size[0] + origin[0])
+        })
+    };
+
+    let mut densities = Vec::with_capacity(BRICK_POINTS.pow(3));
+    for row in rows() {
+        densities.extend_from_slice(&field.densities[row..row + BRICK_POINTS]);
+    }
+
+    let solid = densities.iter().filter(|d| **d < 0.0).count();
+    if solid == 0 {
+        return Node::Air;
+    }
+    if solid == densities.len() {
+        return Node::Solid;
+    }
+
+    let mut normals = Vec::with_capacity(densities.len());
+    for row in rows() {
+        normals.extend(
+            field.gradients[row..row + BRICK_POINTS]
+                .iter()
+                .map(|g| gradient_to_normal(*g)),
+        );
+    }
+    Node::Brick(Box::new(Brick { densities, normals }))
+}
+
+// Builds the subtree covering `size`³ bricks starting at `origin`, collapsing uniform children
+fn build(leaves: &mut [Node], bricks_per_axis: u32, origin: [u32; 3], size: u32) -> Node {
+    if size == 1 {
+        let index = (origin[2] * bricks_per_axis + origin[1]) * bricks_per_axis + origin[0];
+        return std::mem::replace(&mut leaves[index as usize], Node::Air);
+    }
+
+    let half = size / 2;
+    let children: [Node; 8] = std::array::from_fn(|child| {
+        let offset = [child & 1, (child >> 1) & 1, (child >> 2) & 1].map(|o| o as u32 * half);
+        build(
+            leaves,
+            bricks_per_axis,
+            [
+                origin[0] + offset[0],
+                origin[1] + offset[1],
+                origin[2] + offset[2],
+            ],
+            half,
+        )
+    });
+
+    if children.iter().all(|c| matches!(c, Node::Air)) {
+        Node::Air
+    } else if children.iter().all(|c| matches!(c, Node::Solid)) {
+        Node::Solid
+    } else {
+        Node::Branch(Box::new(children))
+    }
+}
@@ -0,0 +1,81 @@
+use crate::noise::SIZE;
+
+/// u32 words per packed vertex, one vec4<u32> in WGSL
+pub(crate) const PACKED_WORDS: usize = 4;
+
+/// Quantization steps per world unit, 1/128 of a voxel at LOD 0
+pub(crate) const POSITION_STEPS: f32 = 128.0;
+
+// The quantized range starts this far below the chunk and ends as far above
+// it: skirts, dual vertices and transition fills all stay within a few cells
+// of the chunk, 65536 steps cover 512 units for a 256 unit chunk
+const POSITION_MARGIN: f32 = (u16::MAX as f32 + 1.0) / POSITION_STEPS / 2.0 - SIZE as f32 / 2.0;
+
+/// World position of the quantized value 0, decode with `origin + q / POSITION_STEPS`
+pub(crate) fn position_origin(chunk: [i32; 3]) -> [f32; 3] {
+    chunk.map(|c| (c * SIZE) as f32 - POSITION_MARGIN)
+}
+
+/// Packs one vertex into four words:
+/// 0: x | y << 16, 1: z (upper half zero), 2: octahedral normal as two
+/// snorm16 (`unpack2x16snorm`), 3: material colour 0xAABBGGRR
+pub(crate) fn pack_vertex(
+    origin: [f32; 3],
+    position: [f32; 3],
+    normal: [f32; 3],
+    color: u32,
+) -> [u32; PACKED_WORDS] {
+    let q = [0, 1, 2].map(|a| {
+        ((position[a] - origin[a]) * POSITION_STEPS)
+            .round()
+            .clamp(0.0, u16::MAX as f32) as u32
+    });
+    [q[0] | q[1] << 16, q[2], encode_octahedral(normal), color]
+}
+
+// Projects the unit normal onto an octahedron and unfolds its lower half, so
+// two coordinates in [-1, 1] cover the sphere with an almost uniform error
+fn encode_octahedral(normal: [f32; 3]) -> u32 {
+    let l1 = normal[0].abs() + normal[1].abs() + normal[2].abs();
+    if l1 == 0.0 {
+        return 0;
+    }
+    let [x, y, z] = normal.map(|n| n / l1);
+    let (u, v) = if z >= 0.0 {
+        (x, y)
+    } else {
+        ((1.0 - y.abs()) * x.signum(), (1.0 - x.abs()) * y.signum())
+    };
+    snorm16(u) | snorm16(v) << 16
+}
+
+fn snorm16(v: f32) -> u32 {
+    ((v.clamp(-1.0, 1.0) * 32767.0).round() as i16) as u16 as u32
+}
+
+/// Inverse of `pack_vertex` up to quantization: position, unit normal and colour
+pub(crate) fn unpack_vertex(
+    origin: [f32; 3],
+    words: [u32; PACKED_WORDS],
+) -> ([f32; 3], [f32; 3], u32) {
+    let q = [words[0] & 0xFFFF, words[0] >> 16, words[1]];
+    let position = [0, 1, 2].map(|a| origin[a] + q[a] as f32 / POSITION_STEPS);
+    (position, decode_octahedral(words[2]), words[3])
+}
+
+fn decode_octahedral(packed: u32) -> [f32; 3] {
+    let u = from_snorm16(packed & 0xFFFF);
+    let v = from_snorm16(packed >> 16);
+    let z = 1.0 - u.abs() - v.abs();
+    let (x, y) = if z >= 0.0 {
+        (u, v)
+    } else {
+        ((1.0 - v.abs()) * u.signum(), (1.0 - u.abs()) * v.signum())
+    };
+    let length = (x * x + y * y + z * z).sqrt();
+    [x / length, y / length, z / length]
+}
+
+fn from_snorm16(bits: u32) -> f32 {
+    (bits as u16 as i16 as f32 / 32767.0).max(-1.0)
+}
@@ -0,0 +1,41 @@
+//! Optional data parallelism. With the `parallel` feature the meshing passes
+//! run on the global rayon pool (native threads, or wasm threads once JS has
+//! awaited `initThreadPool`, see `wasm`), otherwise the same code runs inline on one thread.
+//! Results always come back in input order, so the output does not depend on
+//! scheduling.
+
+/// Densities are sampled from several threads with the `parallel` feature
+#[cfg(feature = "parallel")]
+pub trait MaybeSync: Sync {}
+#[cfg(feature = "parallel")]
+impl<T: Sync + ?Sized> MaybeSync for T {}
+
+#[cfg(not(feature = "parallel"))]
+pub trait MaybeSync {}
+#[cfg(not(feature = "parallel"))]
+impl<T: ?Sized> MaybeSync for T {}
+
+/// Maps `items` in order. `init` creates scratch state, once per worker thread
+#[cfg(feature = "parallel")]
+pub(crate) fn map_init<T, S, R>(
+    items: &[T],
+    init: impl Fn() -> S + Sync + Send,
+    f: impl Fn(&mut S, &T) -> R + Sync + Send,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    use rayon::prelude::*;
+    items.par_iter().map_init(init, f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn map_init<T, S, R>(
+    items: &[T],
+    init: impl Fn() -> S,
+    f: impl Fn(&mut S, &T) -> R,
+) -> Vec<R> {
+    let mut scratch = init();
+    items.iter().map(|item| f(&mut scratch, item)).collect()
+}
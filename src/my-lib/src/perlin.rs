@@ -0,0 +1,159 @@
+use crate::noise::{trilinear_derivative, Permutation};
+
+// Gradient directions for improved Perlin noise (12 cube edges, padded to 16 so a 4-bit hash can index directly)
+const GRADIENTS: [[f32; 3]; 16] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+    [1.0, 1.0, 0.0],
+    [0.0, -1.0, 1.0],
+    [-1.0, 1.0, 0.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// Quintic fade curve (6t^5 - 15t^4 + 10t^3), C2 continuous at lattice cells
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn fade_derivative(t: f32) -> f32 {
+    30.0 * t * t * (t * (t - 2.0) + 1.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + t * (b - a)
+}
+
+fn lerp3(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        lerp(a[0], b[0], t),
+        lerp(a[1], b[1], t),
+        lerp(a[2], b[2], t),
+    ]
+}
+
+/// Returns the corner gradient and its dot product with the offset `d`
+fn grad(perm: &Permutation, x: i32, y: i32, z: i32, d: [f32; 3]) -> (f32, [f32; 3]) {
+    let h =
+        perm.get(perm.get(perm.get((x & 255) as usize) + (y & 255) as usize) + (z & 255) as usize);
+    let g = GRADIENTS[h & 15];
+    (g[0] * d[0] + g[1] * d[1] + g[2] * d[2], g)
+}
+
+/// Improved Perlin gradient noise, roughly in -1.0 to 1.0, with its analytic gradient
+pub fn perlin(perm: &Permutation, x: f32, y: f32, z: f32) -> (f32, [f32; 3]) {
+    let x0 = x.floor() as i32;
+    let y0 = y.floor() as i32;
+    let z0 = z.floor() as i32;
+
+    let fx = x - x0 as f32;
+    let fy = y - y0 as f32;
+    let fz = z - z0 as f32;
+
+    let u = fade(fx);
+    let v = fade(fy);
+    let w = fade(fz);
+
+    // Dot products between corner gradients and offsets to the sample point
+    let (n000, g000) = grad(perm, x0, y0, z0, [fx, fy, fz]);
+    let (n100, g100) = grad(perm, x0 + 1, y0, z0, [fx - 1.0, fy, fz]);
+    let (n010, g010) = grad(perm, x0, y0 + 1, z0, [fx, fy - 1.0, fz]);
+    let (n110, g110) = grad(perm, x0 + 1, y0 + 1, z0, [fx - 1.0, fy - 1.0, fz]);
+    let (n001, g001) = grad(perm, x0, y0, z0 + 1, [fx, fy, fz - 1.0]);
+    let (n101, g101) = grad(perm, x0 + 1, y0, z0 + 1, [fx - 1.0, fy, fz - 1.0]);
+    let (n011, g011) = grad(perm, x0, y0 + 1, z0 + 1, [fx, fy - 1.0, fz - 1.0]);
+    let (n111, g111) = grad(perm, x0 + 1, y0 + 1, z0 + 1, [fx - 1.0, fy - 1.0, fz - 1.0]);
+
+    let nx00 = lerp(n000, n100, u);
+    let nx10 = lerp(n010, n110, u);
+    let nx01 = lerp(n001, n101, u);
+    let nx11 = lerp(n011, n111, u);
+
+    let nxy0 = lerp(nx00, nx10, v);
+    let nxy1 = lerp(nx01, nx11, v);
+
+    let value = lerp(nxy0, nxy1, w);
+
+    // Gradient = blended corner gradients + change of the blend weights
+    let blended = lerp3(
+        lerp3(lerp3(g000, g100, u), lerp3(g010, g110, u), v),
+        lerp3(lerp3(g001, g101, u), lerp3(g011, g111, u), v),
+        w,
+    );
+    let weights = trilinear_derivative(
+        [n000, n100, n010, n110, n001, n101, n011, n111],
+        [u, v, w],
+        [
+            fade_derivative(fx),
+            fade_derivative(fy),
+            fade_derivative(fz),
+        ],
+    );
+
+    (
+        value,
+        [
+            blended[0] + weights[0],
+            blended[1] + weights[1],
+            blended[2] + weights[2],
+        ],
+    )
+}
+
+fn mod289(x: f32) -> f32 {
+    x - (x * (1.0 / 289.0)).floor() * 289.0
+}
+
+fn perm4(x: [f32; 4]) -> [f32; 4] {
+    x.map(|v| mod289(((v * 34.0) + 1.0) * v))
+}
+
+/// CPU port of `noise3` from perlin.wgsl, operation for operation in f32 so the
+/// GPU shader and the mesher agree. Remapped from 0.0..1.0 to -1.0..1.0.
+pub fn shader_noise(x: f32, y: f32, z: f32) -> (f32, [f32; 3]) {
+    let a = [x.floor(), y.floor(), z.floor()];
+    let f = [x - a[0], y - a[1], z - a[2]];
+    let d = f.map(|t| t * t * (3.0 - 2.0 * t));
+
+    // b = a.xxyy + vec4f(0., 1., 0., 1.)
+    let b = [a[0], a[0] + 1.0, a[1], a[1] + 1.0];
+    let k1 = perm4([b[0], b[1], b[0], b[1]]);
+    let k2 = perm4([k1[0] + b[2], k1[1] + b[2], k1[0] + b[3], k1[1] + b[3]]);
+
+    let c = k2.map(|v| v + a[2]);
+    let k3 = perm4(c);
+    let k4 = perm4(c.map(|v| v + 1.0));
+
+    let o1 = k3.map(|v| (v * (1.0 / 41.0)).fract());
+    let o2 = k4.map(|v| (v * (1.0 / 41.0)).fract());
+
+    let mut o3 = [0.0f32; 4];
+    for i in 0..4 {
+        o3[i] = o2[i] * d[2] + o1[i] * (1.0 - d[2]);
+    }
+    // o4 = o3.yw * d.x + o3.xz * (1. - d.x)
+    let o4 = [
+        o3[1] * d[0] + o3[0] * (1.0 - d[0]),
+        o3[3] * d[0] + o3[2] * (1.0 - d[0]),
+    ];
+
+    let n = o4[1] * d[1] + o4[0] * (1.0 - d[1]);
+
+    // Corner order of the vec4 lanes: x = (0, 0), y = (1, 0), z = (0, 1), w = (1, 1)
+    let gradient = trilinear_derivative(
+        [o1[0], o1[1], o1[2], o1[3], o2[0], o2[1], o2[2], o2[3]],
+        d,
+        f.map(|t| 6.0 * t * (1.0 - t)),
+    );
+
+    (n * 2.0 - 1.0, gradient.map(|g| g * 2.0))
+}
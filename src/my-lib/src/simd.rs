@@ -0,0 +1,718 @@
+//! SIMD evaluation of the built-in terrain along an X row. The kernel repeats
+//! `generate_sin_noise` operation for operation on several lanes at once (no
+//! fused multiply-add, same association), so every lane is bit-identical to
+//! the scalar result. Lattice lookups stay scalar per lane, everything else is
+//! vectorized: 4 lanes with simd128 on wasm or SSE2, 8 with AVX when the CPU
+//! has it.
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::noise::{
+    noise_lookup, trilinear_derivative, DensityField, NoiseKind, Permutation, TerrainParams,
+};
+
+// Widest lane count, lane arrays are padded to it
+const MAX_LANES: usize = 8;
+
+/// A vector of f32 lanes with the operations the terrain kernel needs
+trait Lanes:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Neg<Output = Self>
+{
+    const LANES: usize;
+
+    fn splat(value: f32) -> Self;
+
+    /// Loads the first `LANES` values
+    fn load(values: &[f32; MAX_LANES]) -> Self;
+
+    /// Stores into the first `LANES` values, the rest are zero
+    fn store(self) -> [f32; MAX_LANES];
+
+    fn abs(self) -> Self;
+
+    /// `f32::signum`: 1 or -1 by the sign bit, including for zeros
+    fn signum(self) -> Self;
+
+    /// Rounds down, returning the integers and the integers converted back to
+    /// f32, like `x.floor() as i32` and `as f32` in the scalar path
+    fn floor(self) -> ([i32; MAX_LANES], Self);
+}
+
+/// Samples `(x, y, z)` for every `x` in `xs` into `field` with SIMD. Returns
+/// false, leaving `field` untouched, when there is no SIMD path for this
+/// noise kind or target.
+pub(crate) fn terrain_row(
+    params: &TerrainParams,
+    xs: &[f32],
+    y: f32,
+    z: f32,
+    field: &mut DensityField,
+) -> bool {
+    params.noise == NoiseKind::Value && arch_row(params, xs, y, z, field)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn arch_row(params: &TerrainParams, xs: &[f32], y: f32, z: f32, field: &mut DensityField) -> bool {
+    if is_x86_feature_detected!("avx") {
+        // SAFETY: AVX support was checked just above
+        unsafe { avx::row(params, xs, y, z, field) };
+        return true;
+    }
+    sse_row(params, xs, y, z, field)
+}
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+fn sse_row(params: &TerrainParams, xs: &[f32], y: f32, z: f32, field: &mut DensityField) -> bool {
+    row::<sse::Sse>(params, xs, y, z, field);
+    true
+}
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    not(target_feature = "sse2")
+))]
+fn sse_row(_: &TerrainParams, _: &[f32], _: f32, _: f32, _: &mut DensityField) -> bool {
+    false
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+fn arch_row(params: &TerrainParams, xs: &[f32], y: f32, z: f32, field: &mut DensityField) -> bool {
+    row::<wasm::Wasm>(params, xs, y, z, field);
+    true
+}
+
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    all(target_arch = "wasm32", target_feature = "simd128")
+)))]
+fn arch_row(_: &TerrainParams, _: &[f32], _: f32, _: f32, _: &mut DensityField) -> bool {
+    false
+}
+
+// Walks the row `L::LANES` points at a time, the last group is padded with
+// its final point and the padding lanes are dropped
+#[inline(always)]
+fn row<L: Lanes>(params: &TerrainParams, xs: &[f32], y: f32, z: f32, field: &mut DensityField) {
+    let (y, z) = (L::splat(y), L::splat(z));
+    for group in xs.chunks(L::LANES) {
+        let mut x = [group[group.len() - 1]; MAX_LANES];
+        x[..group.len()].copy_from_slice(group);
+
+        let (value, gradient) = terrain(params, [L::load(&x), y, z]);
+        let value = value.store();
+        let gradient = gradient.map(L::store);
+        for lane in 0..group.len() {
+            field.densities.push(value[lane]);
+            field
+                .gradients
+                .push([gradient[0][lane], gradient[1][lane], gradient[2][lane]]);
+        }
+    }
+}
+
+// Lane version of `value_noise`
+#[inline(always)]
+fn value_noise<L: Lanes>(perm: &Permutation, x: L, y: L, z: L) -> (L, [L; 3]) {
+    let (x0, x0f) = x.floor();
+    let (y0, y0f) = y.floor();
+    let (z0, z0f) = z.floor();
+
+    let fx = x - x0f;
+    let fy = y - y0f;
+    let fz = z - z0f;
+
+    let (two, three) = (L::splat(2.0), L::splat(3.0));
+    let sx = fx * fx * (three - two * fx);
+    let sy = fy * fy * (three - two * fy);
+    let sz = fz * fz * (three - two * fz);
+
+    // Corners in trilinear order, gathered per lane
+    let mut corners = [[0.0f32; MAX_LANES]; 8];
+    for lane in 0..L::LANES {
+        let (x, y, z) = (x0[lane], y0[lane], z0[lane]);
+        corners[0][lane] = noise_lookup(perm, x, y, z);
+        corners[1][lane] = noise_lookup(perm, x + 1, y, z);
+        corners[2][lane] = noise_lookup(perm, x, y + 1, z);
+        corners[3][lane] = noise_lookup(perm, x + 1, y + 1, z);
+        corners[4][lane] = noise_lookup(perm, x, y, z + 1);
+        corners[5][lane] = noise_lookup(perm, x + 1, y, z + 1);
+        corners[6][lane] = noise_lookup(perm, x, y + 1, z + 1);
+        corners[7][lane] = noise_lookup(perm, x + 1, y + 1, z + 1);
+    }
+    let n = corners.map(|corner| L::load(&corner));
+
+    let one = L::splat(1.0);
+    let nx00 = n[0] * (one - sx) + n[1] * sx;
+    let nx10 = n[2] * (one - sx) + n[3] * sx;
+    let nx01 = n[4] * (one - sx) + n[5] * sx;
+    let nx11 = n[6] * (one - sx) + n[7] * sx;
+
+    let nxy0 = nx00 * (one - sy) + nx10 * sy;
+    let nxy1 = nx01 * (one - sy) + nx11 * sy;
+
+    let value = nxy0 * (one - sz) + nxy1 * sz;
+
+    let six = L::splat(6.0);
+    let ds = [
+        six * fx * (one - fx),
+        six * fy * (one - fy),
+        six * fz * (one - fz),
+    ];
+    (value, trilinear_derivative(n, [sx, sy, sz], ds))
+}
+
+// Lane version of `generate_sin_noise` for value noise
+#[inline(always)]
+fn terrain<L: Lanes>(params: &TerrainParams, pos: [L; 3]) -> (L, [L; 3]) {
+    let perm = params.permutation();
+    let (zero, one, two) = (L::splat(0.0), L::splat(1.0), L::splat(2.0));
+
+    let warp_scale = L::splat(params.warp_scale);
+    let warp_amount = L::splat(params.warp_amount);
+    let (warp_x, warp_x_grad) = value_noise(
+        perm,
+        pos[0] * warp_scale,
+        pos[1] * warp_scale,
+        pos[2] * warp_scale,
+    );
+    let (warp_z, warp_z_grad) = value_noise(
+        perm,
+        (pos[0] + L::splat(73.2)) * warp_scale,
+        (pos[1] + L::splat(39.7)) * warp_scale,
+        (pos[2] + L::splat(127.1)) * warp_scale,
+    );
+
+    let warped_pos = [
+        pos[0] + warp_x * warp_amount,
+        pos[1],
+        pos[2] + warp_z * warp_amount,
+    ];
+
+    let warp_factor = L::splat(params.warp_amount * params.warp_scale);
+    let warp_jacobian = [
+        [
+            one + warp_x_grad[0] * warp_factor,
+            warp_x_grad[1] * warp_factor,
+            warp_x_grad[2] * warp_factor,
+        ],
+        [zero, one, zero],
+        [
+            warp_z_grad[0] * warp_factor,
+            warp_z_grad[1] * warp_factor,
+            one + warp_z_grad[2] * warp_factor,
+        ],
+    ];
+
+    let mut height = zero;
+    let mut height_grad = [zero; 3];
+    let mut amplitude = params.amplitude;
+    let mut frequency = params.frequency;
+    let billow_end = params.ridged_octaves + params.billow_octaves;
+
+    for i in 0..params.octaves {
+        let f = L::splat(frequency);
+        let (mut noise_val, noise_grad) = value_noise(
+            perm,
+            warped_pos[0] * f,
+            warped_pos[1] * f,
+            warped_pos[2] * f,
+        );
+        let mut slope = one;
+
+        if i < params.ridged_octaves {
+            let sign = noise_val.signum();
+            noise_val = one - two * noise_val.abs();
+            slope = L::splat(-4.0) * noise_val.abs() * sign;
+            noise_val = noise_val * noise_val * noise_val.signum();
+        } else if i < billow_end {
+            slope = two * noise_val.signum();
+            noise_val = noise_val.abs() * two - one;
+        }
+
+        let a = L::splat(amplitude);
+        height = height + noise_val * a;
+        let factor = slope * a * f;
+        for (g, n) in height_grad.iter_mut().zip(noise_grad) {
+            *g = *g + n * factor;
+        }
+
+        amplitude *= params.persistence;
+        frequency *= params.lacunarity;
+    }
+
+    let surface_height = L::splat(params.base_height) + height;
+
+    let mut gradient = [zero; 3];
+    for (j, g) in gradient.iter_mut().enumerate() {
+        *g = -(height_grad[0] * warp_jacobian[0][j]
+            + height_grad[1] * warp_jacobian[1][j]
+            + height_grad[2] * warp_jacobian[2][j]);
+    }
+    gradient[1] = gradient[1] + one;
+
+    (pos[1] - surface_height, gradient)
+}
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+mod sse {
+    use std::ops::{Add, Mul, Neg, Sub};
+
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    use super::{Lanes, MAX_LANES};
+
+    /// Four lanes in an SSE2 register
+    #[derive(Clone, Copy)]
+    pub(super) struct Sse(__m128);
+
+    // SAFETY (all impls below): this module is only compiled with SSE2
+    // enabled, and the stores write four lanes into eight-lane arrays
+
+    impl Add for Sse {
+        type Output = Sse;
+        #[inline(always)]
+        fn add(self, rhs: Sse) -> Sse {
+            Sse(unsafe { _mm_add_ps(self.0, rhs.0) })
+        }
+    }
+
+    impl Sub for Sse {
+        type Output = Sse;
+        #[inline(always)]
+        fn sub(self, rhs: Sse) -> Sse {
+            Sse(unsafe { _mm_sub_ps(self.0, rhs.0) })
+        }
+    }
+
+    impl Mul for Sse {
+        type Output = Sse;
+        #[inline(always)]
+        fn mul(self, rhs: Sse) -> Sse {
+            Sse(unsafe { _mm_mul_ps(self.0, rhs.0) })
+        }
+    }
+
+    impl Neg for Sse {
+        type Output = Sse;
+        #[inline(always)]
+        fn neg(self) -> Sse {
+            Sse(unsafe { _mm_xor_ps(self.0, _mm_set1_ps(-0.0)) })
+        }
+    }
+
+    impl Lanes for Sse {
+        const LANES: usize = 4;
+
+        #[inline(always)]
+        fn splat(value: f32) -> Sse {
+            Sse(unsafe { _mm_set1_ps(value) })
+        }
+
+        #[inline(always)]
+        fn load(values: &[f32; MAX_LANES]) -> Sse {
+            Sse(unsafe { _mm_setr_ps(values[0], values[1], values[2], values[3]) })
+        }
+
+        #[inline(always)]
+        fn store(self) -> [f32; MAX_LANES] {
+            let mut values = [0.0; MAX_LANES];
+            unsafe { _mm_storeu_ps(values.as_mut_ptr(), self.0) };
+            values
+        }
+
+        #[inline(always)]
+        fn abs(self) -> Sse {
+            Sse(unsafe { _mm_andnot_ps(_mm_set1_ps(-0.0), self.0) })
+        }
+
+        #[inline(always)]
+        fn signum(self) -> Sse {
+            unsafe {
+                let sign = _mm_and_ps(self.0, _mm_set1_ps(-0.0));
+                Sse(_mm_or_ps(sign, _mm_set1_ps(1.0)))
+            }
+        }
+
+        #[inline(always)]
+        fn floor(self) -> ([i32; MAX_LANES], Sse) {
+            // SSE2 has no floor: truncate, then step down where that rounded up
+            unsafe {
+                let truncated = _mm_cvttps_epi32(self.0);
+                let above = _mm_cmpgt_ps(_mm_cvtepi32_ps(truncated), self.0);
+                let floor = _mm_add_epi32(truncated, _mm_castps_si128(above));
+
+                let mut ints = [0; MAX_LANES];
+                _mm_storeu_si128(ints.as_mut_ptr().cast(), floor);
+                (ints, Sse(_mm_cvtepi32_ps(floor)))
+            }
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod avx {
+    use std::ops::{Add, Mul, Neg, Sub};
+
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    use super::{Lanes, MAX_LANES};
+    use crate::noise::{DensityField, TerrainParams};
+
+    /// Eight lanes in an AVX register. Only used inside `row`, which is
+    /// compiled for AVX and only called after detecting it.
+    #[derive(Clone, Copy)]
+    pub(super) struct Avx(__m256);
+
+    /// # Safety
+    /// The CPU must support AVX.
+    #[target_feature(enable = "avx")]
+    pub(super) unsafe fn row(
+        params: &TerrainParams,
+        xs: &[f32],
+        y: f32,
+        z: f32,
+        field: &mut DensityField,
+    ) {
+        super::row::<Avx>(params, xs, y, z, field)
+    }
+
+    // SAFETY (all impls below): `Avx` values only exist inside `row`, so AVX
+    // is available, and the stores fill exactly the eight-lane arrays
+
+    impl Add for Avx {
+        type Output = Avx;
+        #[inline(always)]
+        fn add(self, rhs: Avx) -> Avx {
+            Avx(unsafe { _mm256_add_ps(self.0, rhs.0) })
+        }
+    }
+
+    impl Sub for Avx {
+        type Output = Avx;
+        #[inline(always)]
+        fn sub(self, rhs: Avx) -> Avx {
+            Avx(unsafe { _mm256_sub_ps(self.0, rhs.0) })
+        }
+    }
+
+    impl Mul for Avx {
+        type Output = Avx;
+        #[inline(always)]
+        fn mul(self, rhs: Avx) -> Avx {
+            Avx(unsafe { _mm256_mul_ps(self.0, rhs.0) })
+        }
+    }
+
+    impl Neg for Avx {
+        type Output = Avx;
+        #[inline(always)]
+        fn neg(self) -> Avx {
+            Avx(unsafe { _mm256_xor_ps(self.0, _mm256_set1_ps(-0.0)) })
+        }
+    }
+
+    impl Lanes for Avx {
+        const LANES: usize = 8;
+
+        #[inline(always)]
+        fn splat(value: f32) -> Avx {
+            Avx(unsafe { _mm256_set1_ps(value) })
+        }
+
+        #[inline(always)]
+        fn load(values: &[f32; MAX_LANES]) -> Avx {
+            Avx(unsafe { _mm256_loadu_ps(values.as_ptr()) })
+        }
+
+        #[inline(always)]
+        fn store(self) -> [f32; MAX_LANES] {
+            let mut values = [0.0; MAX_LANES];
+            unsafe { _mm256_storeu_ps(values.as_mut_ptr(), self.0) };
+            values
+        }
+
+        #[inline(always)]
+        fn abs(self) -> Avx {
+            Avx(unsafe { _mm256_andnot_ps(_mm256_set1_ps(-0.0), self.0) })
+        }
+
+        #[inline(always)]
+        fn signum(self) -> Avx {
+            unsafe {
+                let sign = _mm256_and_ps(self.0, _mm256_set1_ps(-0.0));
+                Avx(_mm256_or_ps(sign, _mm256_set1_ps(1.0)))
+            }
+        }
+
+        #[inline(always)]
+        fn floor(self) -> ([i32; MAX_LANES], Avx) {
+            unsafe {
+                let floor = _mm256_cvttps_epi32(_mm256_floor_ps(self.0));
+                let mut ints = [0; MAX_LANES];
+                _mm256_storeu_si256(ints.as_mut_ptr().cast(), floor);
+                // Converted back from the integers so -0.0 floors to +0.0 like `as f32`
+                (ints, Avx(_mm256_cvtepi32_ps(floor)))
+            }
+        }
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod wasm {
+    use std::arch::wasm32::*;
+    use std::ops::{Add, Mul, Neg, Sub};
+
+    use super::{Lanes, MAX_LANES};
+
+    /// Four lanes in a simd128 register
+    #[derive(Clone, Copy)]
+    pub(super) struct Wasm(v128);
+
+    impl Add for Wasm {
+        type Output = Wasm;
+        #[inline(always)]
+        fn add(self, rhs: Wasm) -> Wasm {
+            Wasm(f32x4_add(self.0, rhs.0))
+        }
+    }
+
+    impl Sub for Wasm {
+        type Output = Wasm;
+        #[inline(always)]
+        fn sub(self, rhs: Wasm) -> Wasm {
+            Wasm(f32x4_sub(self.0, rhs.0))
+        }
+    }
+
+    impl Mul for Wasm {
+        type Output = Wasm;
+        #[inline(always)]
+        fn mul(self, rhs: Wasm) -> Wasm {
+            Wasm(f32x4_mul(self.0, rhs.0))
+        }
+    }
+
+    impl Neg for Wasm {
+        type Output = Wasm;
+        #[inline(always)]
+        fn neg(self) -> Wasm {
+            Wasm(f32x4_neg(self.0))
+        }
+    }
+
+    impl Lanes for Wasm {
+        const LANES: usize = 4;
+
+        #[inline(always)]
+        fn splat(value: f32) -> Wasm {
+            Wasm(f32x4_splat(value))
+        }
+
+        #[inline(always)]
+        fn load(values: &[f32; MAX_LANES]) -> Wasm {
+            Wasm(f32x4(values[0], values[1], values[2], values[3]))
+        }
+
+        #[inline(always)]
+        fn store(self) -> [f32; MAX_LANES] {
+            let v = self.0;
+            [
+                f32x4_extract_lane::<0>(v),
+                f32x4_extract_lane::<1>(v),
+                f32x4_extract_lane::<2>(v),
+                f32x4_extract_lane::<3>(v),
+                0.0,
+                0.0,
+                0.0,
+                0.0,
+            ]
+        }
+
+        #[inline(always)]
+        fn abs(self) -> Wasm {
+            Wasm(f32x4_abs(self.0))
+        }
+
+        #[inline(always)]
+        fn signum(self) -> Wasm {
+            let sign = v128_and(self.0, f32x4_splat(-0.0));
+            Wasm(v128_or(sign, f32x4_splat(1.0)))
+        }
+
+        #[inline(always)]
+        fn floor(self) -> ([i32; MAX_LANES], Wasm) {
+            let floor = i32x4_trunc_sat_f32x4(f32x4_floor(self.0));
+            let ints = [
+                i32x4_extract_lane::<0>(floor),
+                i32x4_extract_lane::<1>(floor),
+                i32x4_extract_lane::<2>(floor),
+                i32x4_extract_lane::<3>(floor),
+                0,
+                0,
+                0,
+                0,
+            ];
+            (ints, Wasm(f32x4_convert_i32x4(floor)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::{noise_for_region, Density, SIZE};
+
+    fn params() -> Vec<TerrainParams> {
+        let mut all = Vec::new();
+        for seed in [0, 1, 12345, u32::MAX] {
+            all.push(TerrainParams::new(seed));
+        }
+        let mut ridged = TerrainParams::new(7);
+        ridged.ridged_octaves = 2;
+        ridged.billow_octaves = 2;
+        ridged.octaves = 6;
+        all.push(ridged);
+        let mut fine = TerrainParams::new(99);
+        fine.frequency = 0.37;
+        fine.warp_scale = 0.11;
+        fine.billow_octaves = 3;
+        all.push(fine);
+        all
+    }
+
+    // Rows of odd lengths so every lane width gets a padded tail, crossing
+    // lattice cells, zero and negative coordinates
+    fn rows() -> Vec<(Vec<f32>, f32, f32)> {
+        let mut rows = Vec::new();
+        for (i, (y, z)) in [(0.0, 0.0), (-0.0, -17.25), (64.5, -300.0), (-95.3, 1234.75)]
+            .into_iter()
+            .enumerate()
+        {
+            let start = -40.0 - 31.7 * i as f32;
+            let xs = (0..37 + i).map(|x| start + x as f32 * 2.3).collect();
+            rows.push((xs, y, z));
+        }
+        rows.push((
+            vec![-0.0, 0.0, -1e-7, 255.99, 256.0, -256.0, 65536.5],
+            3.0,
+            -0.5,
+        ));
+        rows
+    }
+
+    fn assert_matches_scalar(
+        params: &TerrainParams,
+        field: &DensityField,
+        xs: &[f32],
+        y: f32,
+        z: f32,
+    ) {
+        assert_eq!(field.densities.len(), xs.len());
+        for (i, x) in xs.iter().enumerate() {
+            let (value, gradient) = params.sample([*x, y, z]);
+            assert_eq!(
+                field.densities[i].to_bits(),
+                value.to_bits(),
+                "density at ({}, {}, {})",
+                x,
+                y,
+                z
+            );
+            assert_eq!(
+                field.gradients[i].map(f32::to_bits),
+                gradient.map(f32::to_bits),
+                "gradient at ({}, {}, {})",
+                x,
+                y,
+                z
+            );
+        }
+    }
+
+    fn check<F: Fn(&TerrainParams, &[f32], f32, f32, &mut DensityField)>(row: F) {
+        for params in params() {
+            for (xs, y, z) in rows() {
+                let mut field = DensityField {
+                    densities: Vec::new(),
+                    gradients: Vec::new(),
+                };
+                row(&params, &xs, y, z, &mut field);
+                assert_matches_scalar(&params, &field, &xs, y, z);
+            }
+        }
+    }
+
+    #[cfg(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse2"
+    ))]
+    #[test]
+    fn sse_matches_scalar() {
+        check(row::<sse::Sse>);
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn avx_matches_scalar() {
+        if !is_x86_feature_detected!("avx") {
+            return;
+        }
+        // SAFETY: AVX support was checked above
+        check(|params, xs, y, z, field| unsafe { avx::row(params, xs, y, z, field) });
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    #[test]
+    fn wasm_matches_scalar() {
+        check(row::<wasm::Wasm>);
+    }
+
+    #[test]
+    fn region_matches_point_sampling() {
+        let (chunk, scale) = ([-1, 0, 2], 2.0);
+        let (min, size) = ([1, 3, 0], [13, 2, 3]);
+        for params in params() {
+            let field = noise_for_region(&params, chunk[0], chunk[1], chunk[2], scale, min, size);
+            let mut rows = field.densities.chunks(13).zip(field.gradients.chunks(13));
+            for vz in 0..3 {
+                for vy in 3..5 {
+                    let offset = chunk.map(|c| (c * SIZE) as f32);
+                    let xs: Vec<f32> = (1..14).map(|vx| vx as f32 * scale + offset[0]).collect();
+                    let y = vy as f32 * scale + offset[1];
+                    let z = vz as f32 * scale + offset[2];
+                    let (densities, gradients) = rows.next().unwrap();
+                    let row = DensityField {
+                        densities: densities.to_vec(),
+                        gradients: gradients.to_vec(),
+                    };
+                    assert_matches_scalar(&params, &row, &xs, y, z);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn other_noise_kinds_stay_scalar() {
+        let mut params = TerrainParams::new(3);
+        params.noise = NoiseKind::Perlin;
+        let mut field = DensityField {
+            densities: Vec::new(),
+            gradients: Vec::new(),
+        };
+        assert!(!terrain_row(&params, &[1.0, 2.0], 0.0, 0.0, &mut field));
+        assert!(field.densities.is_empty());
+    }
+}
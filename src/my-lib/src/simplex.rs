@@ -0,0 +1,196 @@
+use crate::noise::Permutation;
+
+// Components of the reference OpenSimplex2 gradients
+const A: f32 = 2.224_744_8;
+const B: f32 = 3.086_266_5;
+const C: f32 = 1.172_151_3;
+
+// The reference OpenSimplex2 gradient set, indexed by lattice hash
+const GRADIENTS: [[f32; 3]; 48] = [
+    [A, A, -1.0],
+    [A, A, 1.0],
+    [B, C, 0.0],
+    [C, B, 0.0],
+    [-A, A, -1.0],
+    [-A, A, 1.0],
+    [-C, B, 0.0],
+    [-B, C, 0.0],
+    [-1.0, -A, -A],
+    [1.0, -A, -A],
+    [0.0, -B, -C],
+    [0.0, -C, -B],
+    [-1.0, -A, A],
+    [1.0, -A, A],
+    [0.0, -C, B],
+    [0.0, -B, C],
+    [-A, -A, -1.0],
+    [-A, -A, 1.0],
+    [-B, -C, 0.0],
+    [-C, -B, 0.0],
+    [-A, -1.0, -A],
+    [-A, 1.0, -A],
+    [-C, 0.0, -B],
+    [-B, 0.0, -C],
+    [-A, -1.0, A],
+    [-A, 1.0, A],
+    [-B, 0.0, C],
+    [-C, 0.0, B],
+    [-1.0, A, -A],
+    [1.0, A, -A],
+    [0.0, C, -B],
+    [0.0, B, -C],
+    [-1.0, A, A],
+    [1.0, A, A],
+    [0.0, B, C],
+    [0.0, C, B],
+    [A, -A, -1.0],
+    [A, -A, 1.0],
+    [C, -B, 0.0],
+    [B, -C, 0.0],
+    [A, -1.0, -A],
+    [A, 1.0, -A],
+    [B, 0.0, -C],
+    [C, 0.0, -B],
+    [A, -1.0, A],
+    [A, 1.0, A],
+    [C, 0.0, B],
+    [B, 0.0, C],
+];
+
+// The reference normalizer, scales the summed contributions to roughly -1.0 to 1.0
+const NORMALIZER: f32 = 1.0 / 0.079_698_38;
+
+// Squared radius of each lattice point's falloff, as in the reference
+const RADIUS_SQUARED: f32 = 0.6;
+
+// Offsets the hash of the second (half-shifted) cubic lattice
+const LATTICE_B: usize = 131;
+
+fn grad(perm: &Permutation, lattice: usize, x: i32, y: i32, z: i32) -> [f32; 3] {
+    let h = perm.get(
+        perm.get(perm.get((x & 255) as usize + lattice) + (y & 255) as usize) + (z & 255) as usize,
+    );
+    GRADIENTS[h % GRADIENTS.len()]
+}
+
+/// Adds one lattice point's t⁴ * dot(g, d) falloff and its derivative with respect to `d`
+fn contribute(t: f32, g: [f32; 3], d: [f32; 3], value: &mut f32, derivative: &mut [f32; 3]) {
+    let dot = g[0] * d[0] + g[1] * d[1] + g[2] * d[2];
+    let t2 = t * t;
+    let t4 = t2 * t2;
+    *value += t4 * dot;
+
+    // d/dd (t⁴ dot) with t = r² - |d|²
+    let falloff = -8.0 * t2 * t * dot;
+    for i in 0..3 {
+        derivative[i] += falloff * d[i] + t4 * g[i];
+    }
+}
+
+/// OpenSimplex2 (fast variant) gradient noise, roughly in -1.0 to 1.0, with its analytic gradient.
+///
+/// The input is rotated onto a body-centred cubic lattice, evaluated as two
+/// interleaved cubic grids. Each grid contributes its closest point and the
+/// neighbour along the dominant axis, giving 4 contributions per sample. The
+/// falloff radius, gradients and normalizer are the reference ones. As in the
+/// reference, a point left out can still be inside its falloff radius, so the
+/// value jumps slightly where the dominant axis changes.
+pub fn opensimplex2(perm: &Permutation, x: f32, y: f32, z: f32) -> (f32, [f32; 3]) {
+    // Rotate so the lattice's main diagonal points along (1, 1, 1)
+    let r = (2.0 / 3.0) * (x + y + z);
+    let xr = r - x;
+    let yr = r - y;
+    let zr = r - z;
+
+    let mut xi = xr.round() as i32;
+    let mut yi = yr.round() as i32;
+    let mut zi = zr.round() as i32;
+    let mut dx = xr - xi as f32;
+    let mut dy = yr - yi as f32;
+    let mut dz = zr - zi as f32;
+
+    // -1 when the offset is non-negative, +1 otherwise
+    let mut x_sign = if dx >= 0.0 { -1 } else { 1 };
+    let mut y_sign = if dy >= 0.0 { -1 } else { 1 };
+    let mut z_sign = if dz >= 0.0 { -1 } else { 1 };
+
+    let mut ax = dx.abs();
+    let mut ay = dy.abs();
+    let mut az = dz.abs();
+
+    let mut lattice = 0;
+    let mut value = 0.0;
+    let mut derivative = [0.0f32; 3]; // With respect to the rotated coordinates
+    let mut a = (RADIUS_SQUARED - dx * dx) - (dy * dy + dz * dz);
+
+    for l in 0..2 {
+        // Closest lattice point
+        if a > 0.0 {
+            let g = grad(perm, lattice, xi, yi, zi);
+            contribute(a, g, [dx, dy, dz], &mut value, &mut derivative);
+        }
+
+        // Second closest point, along the axis with the largest offset
+        if ax >= ay && ax >= az {
+            let b = a + ax + ax;
+            if b > 1.0 {
+                let b = b - 1.0;
+                let d = [dx + x_sign as f32, dy, dz];
+                let g = grad(perm, lattice, xi - x_sign, yi, zi);
+                contribute(b, g, d, &mut value, &mut derivative);
+            }
+        } else if ay > ax && ay >= az {
+            let b = a + ay + ay;
+            if b > 1.0 {
+                let b = b - 1.0;
+                let d = [dx, dy + y_sign as f32, dz];
+                let g = grad(perm, lattice, xi, yi - y_sign, zi);
+                contribute(b, g, d, &mut value, &mut derivative);
+            }
+        } else {
+            let b = a + az + az;
+            if b > 1.0 {
+                let b = b - 1.0;
+                let d = [dx, dy, dz + z_sign as f32];
+                let g = grad(perm, lattice, xi, yi, zi - z_sign);
+                contribute(b, g, d, &mut value, &mut derivative);
+            }
+        }
+
+        if l == 1 {
+            break;
+        }
+
+        // Move to the half-shifted lattice
+        ax = 0.5 - ax;
+        ay = 0.5 - ay;
+        az = 0.5 - az;
+
+        dx = x_sign as f32 * ax;
+        dy = y_sign as f32 * ay;
+        dz = z_sign as f32 * az;
+
+        a += (0.75 - ax) - (ay + az);
+
+        xi -= (x_sign - 1) / 2;
+        yi -= (y_sign - 1) / 2;
+        zi -= (z_sign - 1) / 2;
+
+        x_sign = -x_sign;
+        y_sign = -y_sign;
+        z_sign = -z_sign;
+
+        lattice = LATTICE_B;
+    }
+
+    // The rotation r = (2/3)(x + y + z) - p is symmetric, so the gradient maps back the same way
+    let sum = (2.0 / 3.0) * (derivative[0] + derivative[1] + derivative[2]);
+    (
+        value * NORMALIZER,
+        [
+            (sum - derivative[0]) * NORMALIZER,
+            (sum - derivative[1]) * NORMALIZER,
+            (sum - derivative[2]) * NORMALIZER,
+        ],
+    )
+}
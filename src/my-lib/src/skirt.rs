@@ -0,0 +1,93 @@
+use rustc_hash::FxHashMap;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::mesh::{face_edges, EdgeKey, Meshlet, Vertex};
+use crate::noise::SIZE;
+
+// Skirt length in cells of the chunk's own LOD, enough to cover the gap to a neighbour one or two LODs away
+const SKIRT_CELLS: f32 = 2.0;
+
+/// Direction skirts hang from the chunk boundary
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Skirt {
+    /// Straight down along -Y, good enough for heightfield-like terrain
+    Down = 0,
+    /// Into the rock along each vertex normal, also works for cliffs and overhangs
+    Inward = 1,
+}
+
+/// Skirt geometry along the six chunk faces.
+///
+/// Cheaper than transition geometry and independent of the neighbour: every
+/// boundary edge of the chunk's own mesh is extruded into a quad that hides
+/// cracks behind it. Each face ends up in its own meshlet so the renderer can
+/// draw only the faces whose neighbour has a different LOD.
+pub(crate) struct Skirts {
+    skirt: Skirt,
+    depth: f32,
+    planes: [i32; 6], // World coordinate of each face along its axis
+    edges: [Vec<(Vertex, Vertex)>; 6],
+}
+
+impl Skirts {
+    pub(crate) fn new(skirt: Skirt, chunk: [i32; 3], scale: f32) -> Skirts {
+        Skirts {
+            skirt,
+            depth: SKIRT_CELLS * scale,
+            planes: std::array::from_fn(|face| (chunk[face / 2] + (face % 2) as i32) * SIZE),
+            edges: Default::default(),
+        }
+    }
+
+    /// Records the edges of a triangle that lie on one of the chunk faces
+    pub(crate) fn record(&mut self, triangle: &[Vertex; 3]) {
+        for (face, edges) in self.edges.iter_mut().enumerate() {
+            edges.extend(face_edges(triangle, face / 2, self.planes[face]));
+        }
+    }
+
+    /// One meshlet per face, in neighbor_lods order: -X, +X, -Y, +Y, -Z, +Z
+    pub(crate) fn build(self) -> Vec<Meshlet> {
+        self.edges
+            .iter()
+            .map(|edges| {
+                let mut meshlet = Meshlet::default();
+                for (u, v) in open_edges(edges) {
+                    let (lower_u, lower_v) = (self.extrude(&u), self.extrude(&v));
+                    // Reversed edge, so the skirt keeps the winding of the triangle it hangs from
+                    meshlet.add_triangle(&[v, u, lower_u]);
+                    meshlet.add_triangle(&[v, lower_u, lower_v]);
+                }
+                meshlet
+            })
+            .collect()
+    }
+
+    fn extrude(&self, vertex: &Vertex) -> Vertex {
+        // Normals point against the density gradient, i.e. into the rock
+        let offset = match self.skirt {
+            Skirt::Down => [0.0, -self.depth, 0.0],
+            Skirt::Inward => vertex.normal.map(|n| n * self.depth),
+        };
+        Vertex {
+            position: [0, 1, 2].map(|axis| vertex.position[axis] + offset[axis]),
+            ..*vertex
+        }
+    }
+}
+
+// Drops edges two triangles share in opposite directions. Marching cubes never
+// has those on a face, but dual meshes have whole quads in the first cell layer.
+fn open_edges(edges: &[(Vertex, Vertex)]) -> Vec<(Vertex, Vertex)> {
+    let mut open: FxHashMap<(EdgeKey, EdgeKey), usize> = FxHashMap::default();
+    for (i, (u, v)) in edges.iter().enumerate() {
+        if open.remove(&(v.edge, u.edge)).is_none() {
+            open.insert((u.edge, v.edge), i);
+        }
+    }
+    let mut remaining: Vec<usize> = open.into_values().collect();
+    remaining.sort_unstable();
+    remaining.into_iter().map(|i| edges[i]).collect()
+}
@@ -0,0 +1,421 @@
+use std::collections::hash_map::Entry;
+
+use rustc_hash::FxHashMap;
+
+use crate::interval::Region;
+use crate::mesh::{
+    calculate_terrain_color, face_edges, gradient_to_normal, interpolate_normal,
+    interpolate_vertex, march_cell, EdgeKey, Meshlet, Vertex, CUBE_VERTICES,
+};
+use crate::noise::{noise_for_region, Density, SIZE};
+use crate::octree::BRICK_SIZE;
+
+// LODs with at least one brick per chunk, coarser ones mesh to nothing
+const MESHED_LODS: u32 = (SIZE as u32 / BRICK_SIZE).ilog2() + 1;
+
+/// Transition geometry between a chunk and finer neighbours.
+///
+/// A chunk's mesh ends on each of its faces in a contour, and two chunks at
+/// different LODs produce different contours on the face they share. Like
+/// Transvoxel, only the coarser chunk adds transition triangles: it rebuilds
+/// the finer neighbour's contour by marching that neighbour's first layer of
+/// cells, then triangulates the loops formed by both contours. As a chain,
+/// the fill's boundary is minus both contours plus short segments along the
+/// face border. Those border segments cover the same stretch of a chunk edge
+/// as the fills of the other chunks around it, so seams stay closed at edges
+/// and corners too. Chunks at other LODs end their meshes at other points of
+/// an edge, so border segments are split at the edge's crossings at every
+/// meshed LOD. Every chunk splits the same stretch at the same points, so no
+/// T-junctions are left where LODs differ along an edge.
+pub(crate) struct Seams {
+    faces: Vec<FaceSeam>,
+}
+
+struct FaceSeam {
+    face: usize,
+    neighbor_lod: u32,
+    plane: i32, // World coordinate of the face along its axis
+    edges: Vec<(Vertex, Vertex)>,
+}
+
+impl Seams {
+    /// Seams for every face whose neighbour is finer. `neighbor_lods` order:
+    /// -X, +X, -Y, +Y, -Z, +Z (255 means no neighbor).
+    pub(crate) fn new(chunk: [i32; 3], scale: f32, neighbor_lods: [u32; 6]) -> Seams {
+        let lod = scale.log2() as u32;
+        let faces = (0..6)
+            .filter(|face| neighbor_lods[*face] != 255 && neighbor_lods[*face] < lod)
+            .map(|face| {
+                let axis = face / 2;
+                FaceSeam {
+                    face,
+                    neighbor_lod: neighbor_lods[face],
+                    plane: (chunk[axis] + (face % 2) as i32) * SIZE,
+                    edges: Vec::new(),
+                }
+            })
+            .collect();
+        Seams { faces }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+
+    /// Which of the six faces get transition geometry
+    pub(crate) fn faces(&self) -> [bool; 6] {
+        let mut faces = [false; 6];
+        for seam in &self.faces {
+            faces[seam.face] = true;
+        }
+        faces
+    }
+
+    /// Drops the seams of the faces not in `keep`
+    pub(crate) fn retain(&mut self, keep: [bool; 6]) {
+        self.faces.retain(|seam| keep[seam.face]);
+    }
+
+    /// Records the edges of a triangle of this chunk that lie on a transition face
+    pub(crate) fn record(&mut self, triangle: &[Vertex; 3]) {
+        for seam in &mut self.faces {
+            seam.record(triangle);
+        }
+    }
+
+    /// Builds the transition triangles and adds each loop to the meshlet it starts in
+    pub(crate) fn fill<D: Density + ?Sized>(
+        self,
+        density: &D,
+        chunk: [i32; 3],
+        scale: f32,
+        meshlets: &mut [Meshlet],
+        meshlet_size: u32,
+    ) {
+        let s_size = (SIZE as f32 / (meshlet_size as f32 * scale)) as i32;
+        let meshlet_extent = meshlet_size as f32 * scale;
+
+        for mut seam in self.faces {
+            seam.march_neighbor(density, chunk);
+            for triangle in seam.triangulate(density, chunk) {
+                let meshlet = [0, 1, 2].map(|axis| {
+                    let local = triangle[0].position[axis] - (chunk[axis] * SIZE) as f32;
+                    ((local / meshlet_extent) as i32).clamp(0, s_size - 1)
+                });
+                let index = (meshlet[2] * s_size + meshlet[1]) * s_size + meshlet[0];
+                meshlets[index as usize].add_triangle(&triangle);
+            }
+        }
+    }
+}
+
+impl FaceSeam {
+    fn axis(&self) -> usize {
+        self.face / 2
+    }
+
+    fn record(&mut self, triangle: &[Vertex; 3]) {
+        self.edges
+            .extend(face_edges(triangle, self.axis(), self.plane));
+    }
+
+    // Marches the neighbour's layer of cells touching this face at its own resolution
+    fn march_neighbor<D: Density + ?Sized>(&mut self, density: &D, chunk: [i32; 3]) {
+        let axis = self.axis();
+        let positive = self.face % 2 == 1;
+        let mut neighbor = chunk;
+        neighbor[axis] += if positive { 1 } else { -1 };
+
+        let scale = 2_u32.pow(self.neighbor_lod) as f32;
+        let resolution = (SIZE as f32 / scale) as u32;
+        let layer = if positive { 0 } else { resolution - 1 };
+
+        // Nothing to do when the face is nowhere near the surface
+        let mut min = [0, 1, 2].map(|a| (chunk[a] * SIZE) as f32);
+        let mut max = min.map(|v| v + SIZE as f32);
+        min[axis] = self.plane as f32 - scale;
+        max[axis] = self.plane as f32 + scale;
+        if density.bounds(min, max).region() != Region::Surface {
+            return;
+        }
+
+        let mut region_min = [0; 3];
+        let mut size = [resolution + 1; 3];
+        region_min[axis] = layer;
+        size[axis] = 2;
+        let field = noise_for_region(
+            density,
+            neighbor[0],
+            neighbor[1],
+            neighbor[2],
+            scale,
+            region_min,
+            size,
+        );
+
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let index = |mut pos: [u32; 3]| {
+            pos[axis] -= layer;
+            ((pos[2] * size[1] + pos[1]) * size[0] + pos[0]) as usize
+        };
+
+        let mut triangles = Vec::new();
+        for u in 0..resolution {
+            for v in 0..resolution {
+                let mut voxel = [0; 3];
+                voxel[axis] = layer;
+                voxel[a] = u;
+                voxel[b] = v;
+
+                let mut values = [0.0; 8];
+                let mut normals = [[0.0; 3]; 8];
+                for (i, corner) in CUBE_VERTICES.iter().enumerate() {
+                    let pos = [0, 1, 2].map(|c| voxel[c] + corner[c] as u32);
+                    values[i] = field.densities[index(pos)];
+                    normals[i] = gradient_to_normal(field.gradients[index(pos)]);
+                }
+
+                triangles.clear();
+                march_cell(&values, &normals, voxel, scale, neighbor, &mut triangles);
+                for triangle in &triangles {
+                    self.record(triangle);
+                }
+            }
+        }
+    }
+
+    // Turns the recorded contours into closed loops and triangulates them
+    fn triangulate<D: Density + ?Sized>(&self, density: &D, chunk: [i32; 3]) -> Vec<[Vertex; 3]> {
+        let axis = self.axis();
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        // Minus both contours, with edges shared in opposite directions cancelled
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut ids: FxHashMap<EdgeKey, usize> = FxHashMap::default();
+        let mut id = |vertex: &Vertex| -> usize {
+            *ids.entry(vertex.edge).or_insert_with(|| {
+                vertices.push(*vertex);
+                vertices.len() - 1
+            })
+        };
+        let mut chain: FxHashMap<(usize, usize), i32> = FxHashMap::default();
+        for (u, v) in &self.edges {
+            let (u, v) = (id(u), id(v));
+            if u == v {
+                continue;
+            }
+            match chain.get_mut(&(u, v)) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => *chain.entry((v, u)).or_insert(0) += 1,
+            }
+        }
+
+        // Out minus in for every vertex, only non-zero on the face border
+        let mut need = vec![0i32; vertices.len()];
+        for (&(u, v), &count) in &chain {
+            need[u] += count;
+            need[v] -= count;
+        }
+
+        // Close the chain along the face border, walking its perimeter
+        let origin = [0, 1, 2].map(|c| (chunk[c] * SIZE) as f32);
+        let extent = SIZE as f32;
+        let perimeter = |p: [f32; 3]| -> f32 {
+            let (u, v) = (p[a] - origin[a], p[b] - origin[b]);
+            if v <= 0.0 {
+                u
+            } else if u >= extent {
+                extent + v
+            } else if v >= extent {
+                3.0 * extent - u
+            } else {
+                4.0 * extent - v
+            }
+        };
+        let mut border: Vec<usize> = (0..vertices.len()).filter(|v| need[*v] != 0).collect();
+
+        // Split the segments at the crossings of every LOD on the border lines they run along
+        let mut lines: Vec<(usize, i32)> = Vec::new();
+        for v in &border {
+            let (low, high) = vertices[*v].edge;
+            let along = if low[a] != high[a] { a } else { b };
+            let across = if along == a { b } else { a };
+            if !lines.contains(&(along, low[across])) {
+                lines.push((along, low[across]));
+            }
+        }
+        for (along, at) in lines {
+            for crossing in self.line_crossings(density, chunk, along, at) {
+                if let Entry::Vacant(entry) = ids.entry(crossing.edge) {
+                    entry.insert(vertices.len());
+                    border.push(vertices.len());
+                    vertices.push(crossing);
+                    need.push(0);
+                }
+            }
+        }
+        border.sort_by(|x, y| {
+            perimeter(vertices[*x].position).total_cmp(&perimeter(vertices[*y].position))
+        });
+        let mut flow = 0;
+        for pair in border.windows(2) {
+            flow -= need[pair[0]];
+            let (from, to) = if flow > 0 {
+                (pair[0], pair[1])
+            } else {
+                (pair[1], pair[0])
+            };
+            for _ in 0..flow.abs() {
+                *chain.entry((from, to)).or_insert(0) += 1;
+            }
+        }
+
+        // Split the balanced chain into closed loops
+        let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+        for (&(u, v), &count) in &chain {
+            for _ in 0..count.max(0) {
+                outgoing[u].push(v);
+            }
+        }
+        let mut triangles = Vec::new();
+        for start in 0..vertices.len() {
+            while let Some(mut next) = outgoing[start].pop() {
+                let mut polygon = vec![start];
+                while next != start {
+                    polygon.push(next);
+                    match outgoing[next].pop() {
+                        Some(v) => next = v,
+                        None => break, // Unbalanced, should not happen
+                    }
+                }
+                if polygon.len() < 3 {
+                    continue;
+                }
+                let points: Vec<[f32; 2]> = polygon
+                    .iter()
+                    .map(|v| [vertices[*v].position[a], vertices[*v].position[b]])
+                    .collect();
+                for [i, j, k] in ear_clip(&points) {
+                    triangles.push([
+                        vertices[polygon[i]],
+                        vertices[polygon[j]],
+                        vertices[polygon[k]],
+                    ]);
+                }
+            }
+        }
+        triangles
+    }
+
+    // Surface crossings at every meshed LOD along the border line of this face
+    // running along `along`, at world coordinate `at` on the other face axis
+    fn line_crossings<D: Density + ?Sized>(
+        &self,
+        density: &D,
+        chunk: [i32; 3],
+        along: usize,
+        at: i32,
+    ) -> Vec<Vertex> {
+        let origin = [0, 1, 2].map(|c| chunk[c] * SIZE);
+        let mut region_min = [0; 3];
+        let mut size = [1; 3];
+        region_min[self.axis()] = (self.plane - origin[self.axis()]) as u32;
+        let across = 3 - self.axis() - along;
+        region_min[across] = (at - origin[across]) as u32;
+        size[along] = SIZE as u32 + 1;
+        let field = noise_for_region(density, chunk[0], chunk[1], chunk[2], 1.0, region_min, size);
+
+        let sample = |i: usize| {
+            let mut pos = [
+                region_min[0] as i32,
+                region_min[1] as i32,
+                region_min[2] as i32,
+            ];
+            pos[along] = i as i32;
+            pos
+        };
+        let mut crossings = Vec::new();
+        for lod in 0..MESHED_LODS {
+            let step = 1 << lod;
+            for i in (0..SIZE as usize).step_by(step) {
+                let (d1, d2) = (field.densities[i], field.densities[i + step]);
+                if (d1 < 0.0) == (d2 < 0.0) {
+                    continue;
+                }
+                let (p1, p2) = (sample(i), sample(i + step));
+                let local = interpolate_vertex(p1.map(|c| c as f32), p2.map(|c| c as f32), d1, d2);
+                let position = [0, 1, 2].map(|c| local[c] + origin[c] as f32);
+                let normal = interpolate_normal(
+                    gradient_to_normal(field.gradients[i]),
+                    gradient_to_normal(field.gradients[i + step]),
+                    d1,
+                    d2,
+                );
+                crossings.push(Vertex {
+                    edge: (
+                        [0, 1, 2].map(|c| p1[c] + origin[c]),
+                        [0, 1, 2].map(|c| p2[c] + origin[c]),
+                    ),
+                    position,
+                    normal,
+                    color: calculate_terrain_color(position, normal),
+                });
+            }
+        }
+        crossings
+    }
+}
+
+/// Triangulates a polygon keeping its winding, falling back to a fan when no ear is found
+fn ear_clip(points: &[[f32; 2]]) -> Vec<[usize; 3]> {
+    let cross = |o: [f32; 2], p: [f32; 2], q: [f32; 2]| {
+        (p[0] - o[0]) * (q[1] - o[1]) - (p[1] - o[1]) * (q[0] - o[0])
+    };
+    let area: f32 = (0..points.len())
+        .map(|i| {
+            let (p, q) = (points[i], points[(i + 1) % points.len()]);
+            p[0] * q[1] - q[0] * p[1]
+        })
+        .sum();
+    let winding = if area >= 0.0 { 1.0 } else { -1.0 };
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (p, c, q) = (
+                remaining[(i + n - 1) % n],
+                remaining[i],
+                remaining[(i + 1) % n],
+            );
+            if cross(points[p], points[c], points[q]) * winding <= 0.0 {
+                return false;
+            }
+            // No other vertex may lie inside the ear
+            remaining.iter().all(|&o| {
+                o == p
+                    || o == c
+                    || o == q
+                    || cross(points[p], points[c], points[o]) * winding < 0.0
+                    || cross(points[c], points[q], points[o]) * winding < 0.0
+                    || cross(points[q], points[p], points[o]) * winding < 0.0
+            })
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + n - 1) % n],
+                    remaining[i],
+                    remaining[(i + 1) % n],
+                ]);
+                remaining.remove(i);
+            }
+            None => break,
+        }
+    }
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
@@ -0,0 +1,1059 @@
+//! The wasm-bindgen API used by the browser client. The generator and the
+//! meshes kept for JS live in thread-locals, calls copy into JS typed arrays
+//! unless they hand out views into wasm memory.
+
+use std::cell::{Cell, RefCell};
+
+use js_sys::{Float32Array, Uint16Array, Uint32Array, Uint8Array};
+use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::JsValue;
+
+use crate::arena::MeshArena;
+use crate::brush::{BrushOperation, BrushShape};
+use crate::cache::VoxelCache;
+use crate::edit::{Edit, Edited};
+use crate::graph::{DensityGraph, FractalMode, Node, NodeId};
+use crate::journal::{read_journal, write_journal, Journal};
+use crate::mesh::{Chunk, Command, IndexFormat, MeshOptions, Mesher, VertexFormat};
+use crate::noise::{NoiseKind, TerrainParams};
+use crate::skirt::Skirt;
+use crate::{
+    blob, buffer_sizes, default_generator, format, graph, mesh_chunk, mesh_chunk_cached, metadata,
+    remesh_chunk,
+};
+use crate::{write_glb, write_obj};
+use crate::{BufferSizes, MeshMetadata};
+
+/// `initThreadPool(threads)` in JS. Threaded builds must await it once before
+/// meshing, the rayon pool used by `parallel` runs on the workers it starts.
+#[cfg(all(feature = "parallel", target_arch = "wasm32"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+
+#[wasm_bindgen]
+pub fn get_memory() -> JsValue {
+    wasm_bindgen::memory()
+}
+
+/// Byte offsets of a mesh's buffers in wasm memory (`get_memory().buffer`),
+/// with element counts in `MeshMetadata`. Views over them are only valid
+/// until the next call that may grow the memory, create them right before use.
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub struct MeshViews {
+    pub vertices: usize,
+    pub normals: usize,
+    pub colors: usize,
+    pub material_colors: usize,
+    pub packed_vertices: usize,
+    pub commands: usize,
+    pub split_from: usize,
+    pub bounds: usize,
+    pub densities: usize,
+    pub vertex_counts: usize,
+    pub indices: usize, // Whichever of the u16 and u32 index buffers is filled
+}
+
+// Generate mesh and return typed arrays directly (wasm-bindgen handles efficient transfer)
+#[wasm_bindgen]
+pub struct MeshResult {
+    vertices: Float32Array,
+    normals: Float32Array,
+    colors: Uint32Array,
+    material_colors: Uint32Array,
+    packed_vertices: Uint32Array,
+    commands: Uint32Array,
+    split_from: Uint32Array,
+    bounds: Float32Array,
+    densities: Uint32Array,
+    vertex_counts: Uint32Array,
+    index_format: IndexFormat,
+    indices: Uint16Array,
+    indices_u32: Uint32Array,
+    metadata: MeshMetadata,
+    buffer_sizes: BufferSizes,
+}
+
+#[wasm_bindgen]
+impl MeshResult {
+    #[wasm_bindgen(getter)]
+    pub fn vertices(&self) -> Float32Array {
+        self.vertices.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn normals(&self) -> Float32Array {
+        self.normals.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn colors(&self) -> Uint32Array {
+        self.colors.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn material_colors(&self) -> Uint32Array {
+        self.material_colors.clone()
+    }
+    /// Four words per vertex with `VertexFormat::Packed`: x | y << 16, z,
+    /// octahedral normal as two snorm16 and the material colour
+    #[wasm_bindgen(getter)]
+    pub fn packed_vertices(&self) -> Uint32Array {
+        self.packed_vertices.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn commands(&self) -> Uint32Array {
+        self.commands.clone()
+    }
+    /// For each draw after the meshlets and skirts, the draw it was split from
+    #[wasm_bindgen(getter)]
+    pub fn split_from(&self) -> Uint32Array {
+        self.split_from.clone()
+    }
+    /// 16 floats per draw: AABB min and max, bounding sphere (center, radius)
+    /// and normal cone (axis, cutoff), each padded to a vec4
+    #[wasm_bindgen(getter)]
+    pub fn bounds(&self) -> Float32Array {
+        self.bounds.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn densities(&self) -> Uint32Array {
+        self.densities.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn vertex_counts(&self) -> Uint32Array {
+        self.vertex_counts.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn index_format(&self) -> IndexFormat {
+        self.index_format
+    }
+    /// Empty unless `index_format` is Uint16
+    #[wasm_bindgen(getter)]
+    pub fn indices(&self) -> Uint16Array {
+        self.indices.clone()
+    }
+    /// Empty unless `index_format` is Uint32
+    #[wasm_bindgen(getter)]
+    pub fn indices_u32(&self) -> Uint32Array {
+        self.indices_u32.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn metadata(&self) -> MeshMetadata {
+        self.metadata
+    }
+    #[wasm_bindgen(getter)]
+    pub fn buffer_sizes(&self) -> BufferSizes {
+        self.buffer_sizes
+    }
+}
+
+// neighbor_lods order: -X, +X, -Y, +Y, -Z, +Z (use 255 for no neighbor)
+fn neighbor_lods_array(neighbor_lods: &[u32]) -> [u32; 6] {
+    if neighbor_lods.len() >= 6 {
+        [
+            neighbor_lods[0],
+            neighbor_lods[1],
+            neighbor_lods[2],
+            neighbor_lods[3],
+            neighbor_lods[4],
+            neighbor_lods[5],
+        ]
+    } else {
+        [255, 255, 255, 255, 255, 255] // Default: no neighbors
+    }
+}
+
+thread_local! {
+    // World generator used by generate_mesh, replaced by load_generator
+    static GENERATOR: RefCell<DensityGraph> = RefCell::new(default_generator());
+    // format::generator_hash of GENERATOR, which keys cached voxels and journals
+    static GENERATOR_HASH: Cell<u64> = Cell::new(format::generator_hash(&default_generator()));
+    // Chunks meshed with generate_mesh_handle, alive until free_mesh
+    static MESHES: RefCell<MeshArena> = RefCell::new(MeshArena::default());
+    // Terrain edits on top of the generator, kept across remeshes and LOD
+    // changes, with their undo history
+    static JOURNAL: RefCell<Journal> = RefCell::new(Journal::default());
+    // Voxels of recently meshed unedited chunks, for LOD changes and revisits.
+    // Off until set_voxel_cache_budget, every worker would hold its own.
+    static CACHE: RefCell<VoxelCache> = RefCell::new(VoxelCache::new(0));
+}
+
+// Runs `f` on the active generator with the world's edits of chunk (x, y, z) applied
+fn with_world<T>(x: i32, y: i32, z: i32, f: impl FnOnce(&Edited<'_, DensityGraph>) -> T) -> T {
+    GENERATOR.with(|generator| {
+        JOURNAL.with(|journal| {
+            let generator = generator.borrow();
+            let journal = journal.borrow();
+            f(&Edited::new(&*generator, journal.layer(), x, y, z))
+        })
+    })
+}
+
+fn mesh_world(x: i32, y: i32, z: i32, lod: u32, options: MeshOptions) -> Chunk {
+    let edited = JOURNAL.with(|journal| {
+        let journal = journal.borrow();
+        let edited = journal.layer().chunk_edits(x, y, z).next().is_some();
+        edited
+    });
+    let cached = CACHE.with(|cache| cache.borrow().budget() > 0);
+    if edited || !cached {
+        return with_world(x, y, z, |density| {
+            mesh_chunk(density, x, y, z, lod, options)
+        });
+    }
+    GENERATOR.with(|generator| {
+        CACHE.with(|cache| {
+            mesh_chunk_cached(
+                &mut cache.borrow_mut(),
+                &*generator.borrow(),
+                GENERATOR_HASH.get(),
+                x,
+                y,
+                z,
+                lod,
+                options,
+            )
+        })
+    })
+}
+
+/// Replaces the world generator with a JSON world definition (see format.rs).
+/// Errors name the offending node, e.g. "nodes[3] (sphere): radius must be positive".
+#[wasm_bindgen]
+pub fn load_generator(json: &str) -> Result<(), JsValue> {
+    let graph = format::parse_graph(json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    GENERATOR_HASH.set(format::generator_hash(&graph));
+    GENERATOR.with(|generator| *generator.borrow_mut() = graph);
+    // Voxels of the old world would never be looked up again
+    CACHE.with(|cache| cache.borrow_mut().clear());
+    Ok(())
+}
+
+/// Serializes the active world generator
+#[wasm_bindgen]
+pub fn save_generator() -> String {
+    GENERATOR.with(|generator| format::write_graph(&generator.borrow()))
+}
+
+/// Sets how many bytes of sampled voxels are kept to mesh chunks again
+/// without the generator, e.g. after a LOD change. 0, the default, turns the
+/// cache off; each worker holds its own, so split one budget between them.
+/// Edited chunks are never cached.
+#[wasm_bindgen]
+pub fn set_voxel_cache_budget(bytes: usize) {
+    CACHE.with(|cache| cache.borrow_mut().set_budget(bytes));
+}
+
+/// Bytes of voxels the cache currently holds
+#[wasm_bindgen]
+pub fn voxel_cache_bytes() -> usize {
+    CACHE.with(|cache| cache.borrow().used())
+}
+
+/// Shape for the `brush_*` edit functions. Edits live in the wasm instance
+/// that made them, so a client meshing in workers sends each brush to every
+/// worker. Shapes wider than 1024 units along any axis and blends over 64
+/// are rejected.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct Brush {
+    shape: BrushShape,
+}
+
+#[wasm_bindgen]
+impl Brush {
+    pub fn sphere(x: f32, y: f32, z: f32, radius: f32) -> Brush {
+        Brush {
+            shape: BrushShape::Sphere {
+                center: [x, y, z],
+                radius,
+            },
+        }
+    }
+
+    #[wasm_bindgen(js_name = box)]
+    pub fn cuboid(x: f32, y: f32, z: f32, half_x: f32, half_y: f32, half_z: f32) -> Brush {
+        Brush {
+            shape: BrushShape::Box {
+                center: [x, y, z],
+                half_extents: [half_x, half_y, half_z],
+            },
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn cylinder(ax: f32, ay: f32, az: f32, bx: f32, by: f32, bz: f32, radius: f32) -> Brush {
+        Brush {
+            shape: BrushShape::Cylinder {
+                a: [ax, ay, az],
+                b: [bx, by, bz],
+                radius,
+            },
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn capsule(ax: f32, ay: f32, az: f32, bx: f32, by: f32, bz: f32, radius: f32) -> Brush {
+        Brush {
+            shape: BrushShape::Capsule {
+                a: [ax, ay, az],
+                b: [bx, by, bz],
+                radius,
+            },
+        }
+    }
+
+    /// Cone from a disc of `radius` around the base point to the tip
+    #[allow(clippy::too_many_arguments)]
+    pub fn cone(
+        base_x: f32,
+        base_y: f32,
+        base_z: f32,
+        tip_x: f32,
+        tip_y: f32,
+        tip_z: f32,
+        radius: f32,
+    ) -> Brush {
+        Brush {
+            shape: BrushShape::Cone {
+                base: [base_x, base_y, base_z],
+                tip: [tip_x, tip_y, tip_z],
+                radius,
+            },
+        }
+    }
+
+    /// Any density graph as the shape (negative inside). The box must contain
+    /// the whole inside, the brush does nothing beyond it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sdf(
+        graph: &TerrainGraph,
+        min_x: f32,
+        min_y: f32,
+        min_z: f32,
+        max_x: f32,
+        max_y: f32,
+        max_z: f32,
+    ) -> Brush {
+        Brush {
+            shape: BrushShape::Sdf {
+                graph: graph.graph.clone(),
+                min: [min_x, min_y, min_z],
+                max: [max_x, max_y, max_z],
+            },
+        }
+    }
+}
+
+// Adds an edit to the world, returning the chunks to remesh as flat [x0, y0, z0, x1, ...]
+fn push_edit(brush: &Brush, operation: BrushOperation) -> Result<Vec<i32>, JsValue> {
+    let edit = Edit::new(brush.shape.clone(), operation);
+    JOURNAL
+        .with(|journal| journal.borrow_mut().push(edit))
+        .map(|chunks| chunks.concat())
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Fills the brush with rock. Edits are honoured by every later
+/// `generate_mesh*` call at any LOD. `blend` (default 0) rounds the join with
+/// the terrain over that distance. Returns the chunks to remesh as flat
+/// [x0, y0, z0, x1, ...].
+#[wasm_bindgen]
+pub fn brush_add(brush: &Brush, blend: Option<f32>) -> Result<Vec<i32>, JsValue> {
+    let blend = blend.unwrap_or(0.0);
+    push_edit(brush, BrushOperation::Add { blend })
+}
+
+/// Carves the brush out of the terrain, like `brush_add`
+#[wasm_bindgen]
+pub fn brush_subtract(brush: &Brush, blend: Option<f32>) -> Result<Vec<i32>, JsValue> {
+    let blend = blend.unwrap_or(0.0);
+    push_edit(brush, BrushOperation::Subtract { blend })
+}
+
+/// Moves the surface inside the brush onto the plane `dot(p, n) = height`.
+/// `blend` (default 0) fades the effect in over that distance from the
+/// brush's edge.
+#[wasm_bindgen]
+pub fn brush_flatten(
+    brush: &Brush,
+    nx: f32,
+    ny: f32,
+    nz: f32,
+    height: f32,
+    blend: Option<f32>,
+) -> Result<Vec<i32>, JsValue> {
+    push_edit(
+        brush,
+        BrushOperation::Flatten {
+            normal: [nx, ny, nz],
+            height,
+            blend: blend.unwrap_or(0.0),
+        },
+    )
+}
+
+/// Averages the terrain inside the brush over `radius` (at most 32), blended
+/// in like `brush_flatten`
+#[wasm_bindgen]
+pub fn brush_smooth(brush: &Brush, radius: f32, blend: Option<f32>) -> Result<Vec<i32>, JsValue> {
+    let blend = blend.unwrap_or(0.0);
+    push_edit(brush, BrushOperation::Smooth { radius, blend })
+}
+
+/// Recolours the surface inside the brush with `color` (0xAABBGGRR) without
+/// changing its shape, blended in like `brush_flatten`
+#[wasm_bindgen]
+pub fn brush_paint(brush: &Brush, color: u32, blend: Option<f32>) -> Result<Vec<i32>, JsValue> {
+    let blend = blend.unwrap_or(0.0);
+    push_edit(brush, BrushOperation::Paint { color, blend })
+}
+
+/// Removes every edit and the undo history, returning the chunks to remesh
+/// like `brush_add`
+#[wasm_bindgen]
+pub fn clear_edits() -> Vec<i32> {
+    JOURNAL.with(|journal| journal.borrow_mut().clear().concat())
+}
+
+/// Number of edits currently applied, undone ones excluded
+#[wasm_bindgen]
+pub fn edit_count() -> usize {
+    JOURNAL.with(|journal| journal.borrow().applied())
+}
+
+/// Reverts the latest edit, returning the chunks to remesh like `brush_add`
+/// (empty when there is nothing to undo)
+#[wasm_bindgen]
+pub fn undo_edit() -> Vec<i32> {
+    JOURNAL.with(|journal| journal.borrow_mut().undo().unwrap_or_default().concat())
+}
+
+/// Reapplies the latest undone edit, like `undo_edit`. A new edit discards
+/// everything that could be redone.
+#[wasm_bindgen]
+pub fn redo_edit() -> Vec<i32> {
+    JOURNAL.with(|journal| journal.borrow_mut().redo().unwrap_or_default().concat())
+}
+
+/// Box outside of which entry `index` of the edit history leaves the terrain
+/// untouched, as [min x, y, z, max x, y, z], for `remesh_mesh`. The latest
+/// edit is `edit_count() - 1`, the one just undone `edit_count()`.
+#[wasm_bindgen]
+pub fn edit_bounds(index: usize) -> Result<Vec<f32>, JsValue> {
+    JOURNAL.with(|journal| {
+        let journal = journal.borrow();
+        let entry = journal
+            .entries()
+            .get(index)
+            .ok_or_else(|| JsValue::from_str(&format!("no edit {}", index)))?;
+        let (min, max) = entry.edit.reach();
+        Ok([min, max].concat())
+    })
+}
+
+#[wasm_bindgen]
+pub fn can_undo() -> bool {
+    JOURNAL.with(|journal| journal.borrow().can_undo())
+}
+
+#[wasm_bindgen]
+pub fn can_redo() -> bool {
+    JOURNAL.with(|journal| journal.borrow().can_redo())
+}
+
+/// Serializes the edits and their undo history (see journal.rs), tied to the
+/// active generator
+#[wasm_bindgen]
+pub fn save_journal() -> Uint8Array {
+    let hash = GENERATOR_HASH.get();
+    JOURNAL.with(|journal| Uint8Array::from(&write_journal(&journal.borrow(), hash)[..]))
+}
+
+/// Replaces the edits with a journal from `save_journal`, which must have been
+/// recorded on the active generator. Returns the chunks to remesh like
+/// `brush_add`: those with edits before or after.
+#[wasm_bindgen]
+pub fn load_journal(bytes: &[u8]) -> Result<Vec<i32>, JsValue> {
+    let hash = GENERATOR_HASH.get();
+    let loaded = read_journal(bytes, hash).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    JOURNAL.with(|journal| {
+        let mut journal = journal.borrow_mut();
+        let mut chunks = journal.clear();
+        chunks.extend(loaded.layer().edited_chunks());
+        *journal = loaded;
+        chunks.sort_unstable();
+        chunks.dedup();
+        Ok(chunks.concat())
+    })
+}
+
+/// Meshes a chunk with the active generator and edits. With `skirt` set, six
+/// skirt draws (-X, +X, -Y, +Y, -Z, +Z) follow the per-meshlet commands and
+/// vertex counts.
+/// `mesher` defaults to marching cubes, every mesher keeps the same output layout.
+/// `index_format` defaults to u16, which splits draws over 65536 vertices.
+/// `vertex_format` defaults to float, `Packed` only fills `packed_vertices`.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn generate_mesh(
+    x: i32,
+    y: i32,
+    z: i32,
+    lod: u32,
+    neighbor_lods: Vec<u32>,
+    skirt: Option<Skirt>,
+    mesher: Option<Mesher>,
+    index_format: Option<IndexFormat>,
+    vertex_format: Option<VertexFormat>,
+) -> MeshResult {
+    let options = mesh_options(&neighbor_lods, skirt, mesher, index_format, vertex_format);
+    let chunk = mesh_world(x, y, z, lod, options);
+    mesh_result(&chunk)
+}
+
+/// Same as `generate_mesh`, but the terrain comes from a designer-built density
+/// graph, without the world's edits
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn generate_mesh_from_graph(
+    graph: &TerrainGraph,
+    x: i32,
+    y: i32,
+    z: i32,
+    lod: u32,
+    neighbor_lods: Vec<u32>,
+    skirt: Option<Skirt>,
+    mesher: Option<Mesher>,
+    index_format: Option<IndexFormat>,
+    vertex_format: Option<VertexFormat>,
+) -> MeshResult {
+    mesh_result(&mesh_chunk(
+        &graph.graph,
+        x,
+        y,
+        z,
+        lod,
+        mesh_options(&neighbor_lods, skirt, mesher, index_format, vertex_format),
+    ))
+}
+
+/// Same as `generate_mesh`, but the chunk stays in wasm memory: read it
+/// through `mesh_views` and `mesh_metadata` without copies, then release it
+/// with `free_mesh`.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn generate_mesh_handle(
+    x: i32,
+    y: i32,
+    z: i32,
+    lod: u32,
+    neighbor_lods: Vec<u32>,
+    skirt: Option<Skirt>,
+    mesher: Option<Mesher>,
+    index_format: Option<IndexFormat>,
+    vertex_format: Option<VertexFormat>,
+) -> u32 {
+    let options = mesh_options(&neighbor_lods, skirt, mesher, index_format, vertex_format);
+    let chunk = mesh_world(x, y, z, lod, options);
+    MESHES.with(|meshes| meshes.borrow_mut().insert(chunk))
+}
+
+/// Draws `remesh_mesh` replaced, with the mesh's buffer lengths afterwards
+#[wasm_bindgen]
+pub struct MeshPatchResult {
+    draws: Uint32Array,
+    vertex_counts: Uint32Array,
+    commands: Uint32Array,
+    metadata: MeshMetadata,
+    buffer_sizes: BufferSizes,
+}
+
+#[wasm_bindgen]
+impl MeshPatchResult {
+    /// Indices of the replaced draws into the mesh's commands, vertex counts
+    /// and bounds; meshlet draws also have a new density
+    #[wasm_bindgen(getter)]
+    pub fn draws(&self) -> Uint32Array {
+        self.draws.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn vertex_counts(&self) -> Uint32Array {
+        self.vertex_counts.clone()
+    }
+    /// The new command of each replaced draw, 5 words each. A draw's
+    /// vertices start at its `base_vertex`, its indices at `first_index`.
+    #[wasm_bindgen(getter)]
+    pub fn commands(&self) -> Uint32Array {
+        self.commands.clone()
+    }
+    /// Grows past the GPU buffers' size when a draw outgrew its old ranges
+    #[wasm_bindgen(getter)]
+    pub fn metadata(&self) -> MeshMetadata {
+        self.metadata
+    }
+    #[wasm_bindgen(getter)]
+    pub fn buffer_sizes(&self) -> BufferSizes {
+        self.buffer_sizes
+    }
+}
+
+/// Remeshes the meshlets of mesh `handle` near the box `dirty` ([min x, y, z,
+/// max x, y, z], e.g. from `edit_bounds`) with the active generator and
+/// edits, in place. The other arguments must match the
+/// `generate_mesh_handle` call. Upload the returned draws' ranges from
+/// `mesh_views`. Returns undefined when the whole chunk has to be meshed again.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn remesh_mesh(
+    handle: u32,
+    x: i32,
+    y: i32,
+    z: i32,
+    lod: u32,
+    dirty: Vec<f32>,
+    neighbor_lods: Vec<u32>,
+    skirt: Option<Skirt>,
+    mesher: Option<Mesher>,
+    index_format: Option<IndexFormat>,
+    vertex_format: Option<VertexFormat>,
+) -> Result<Option<MeshPatchResult>, JsValue> {
+    if dirty.len() != 6 {
+        return Err(JsValue::from_str("dirty box needs 6 values"));
+    }
+    let options = mesh_options(&neighbor_lods, skirt, mesher, index_format, vertex_format);
+    let min = [dirty[0], dirty[1], dirty[2]];
+    let max = [dirty[3], dirty[4], dirty[5]];
+    MESHES.with(|meshes| {
+        let mut meshes = meshes.borrow_mut();
+        let chunk = meshes
+            .get_mut(handle)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown mesh handle {}", handle)))?;
+        let patch = with_world(x, y, z, |density| {
+            remesh_chunk(chunk, density, x, y, z, lod, options, min, max)
+        });
+        Ok(patch.map(|patch| {
+            let draws: Vec<u32> = patch.draws.iter().map(|d| d.draw).collect();
+            let vertex_counts: Vec<u32> = patch.draws.iter().map(|d| d.vertex_count).collect();
+            let commands: Vec<u32> = patch
+                .draws
+                .iter()
+                .flat_map(|d| d.command.to_words())
+                .collect();
+            let metadata = metadata(chunk);
+            MeshPatchResult {
+                draws: Uint32Array::from(&draws[..]),
+                vertex_counts: Uint32Array::from(&vertex_counts[..]),
+                commands: Uint32Array::from(&commands[..]),
+                metadata,
+                buffer_sizes: buffer_sizes(&metadata),
+            }
+        }))
+    })
+}
+
+/// Same as `generate_mesh_handle` with a designer-built density graph
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn generate_mesh_handle_from_graph(
+    graph: &TerrainGraph,
+    x: i32,
+    y: i32,
+    z: i32,
+    lod: u32,
+    neighbor_lods: Vec<u32>,
+    skirt: Option<Skirt>,
+    mesher: Option<Mesher>,
+    index_format: Option<IndexFormat>,
+    vertex_format: Option<VertexFormat>,
+) -> u32 {
+    let chunk = mesh_chunk(
+        &graph.graph,
+        x,
+        y,
+        z,
+        lod,
+        mesh_options(&neighbor_lods, skirt, mesher, index_format, vertex_format),
+    );
+    MESHES.with(|meshes| meshes.borrow_mut().insert(chunk))
+}
+
+#[wasm_bindgen]
+pub fn mesh_metadata(handle: u32) -> Result<MeshMetadata, JsValue> {
+    with_mesh(handle, metadata)
+}
+
+#[wasm_bindgen]
+pub fn mesh_buffer_sizes(handle: u32) -> Result<BufferSizes, JsValue> {
+    with_mesh(handle, |chunk| buffer_sizes(&metadata(chunk)))
+}
+
+#[wasm_bindgen]
+pub fn mesh_views(handle: u32) -> Result<MeshViews, JsValue> {
+    with_mesh(handle, |chunk| MeshViews {
+        vertices: chunk.vertices().as_ptr() as usize,
+        normals: chunk.normals().as_ptr() as usize,
+        colors: chunk.colors().as_ptr() as usize,
+        material_colors: chunk.material_colors().as_ptr() as usize,
+        packed_vertices: chunk.packed_vertices().as_ptr() as usize,
+        commands: chunk.commands().as_ptr() as usize,
+        split_from: chunk.split_from().as_ptr() as usize,
+        bounds: chunk.bounds().as_ptr() as usize,
+        densities: chunk.densities().as_ptr() as usize,
+        vertex_counts: chunk.vertex_counts().as_ptr() as usize,
+        indices: match chunk.index_format() {
+            IndexFormat::Uint16 => chunk.indices().as_ptr() as usize,
+            IndexFormat::Uint32 => chunk.indices_u32().as_ptr() as usize,
+        },
+    })
+}
+
+/// Releases a mesh from `generate_mesh_handle`, returns false for unknown handles
+#[wasm_bindgen]
+pub fn free_mesh(handle: u32) -> bool {
+    MESHES.with(|meshes| meshes.borrow_mut().remove(handle).is_some())
+}
+
+/// Meshes not yet released with `free_mesh`, to spot leaks
+#[wasm_bindgen]
+pub fn live_meshes() -> usize {
+    MESHES.with(|meshes| meshes.borrow().len())
+}
+
+/// Same as `generate_mesh`, but every buffer is packed into one blob (see
+/// blob.rs) whose ArrayBuffer can be transferred to another thread or saved
+/// as a cache file and read back with `load_mesh_blob`
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn generate_mesh_blob(
+    x: i32,
+    y: i32,
+    z: i32,
+    lod: u32,
+    neighbor_lods: Vec<u32>,
+    skirt: Option<Skirt>,
+    mesher: Option<Mesher>,
+    index_format: Option<IndexFormat>,
+    vertex_format: Option<VertexFormat>,
+) -> Uint8Array {
+    let options = mesh_options(&neighbor_lods, skirt, mesher, index_format, vertex_format);
+    let chunk = mesh_world(x, y, z, lod, options);
+    Uint8Array::from(&blob::write_blob(&chunk)[..])
+}
+
+/// Blob of a mesh from `generate_mesh_handle`
+#[wasm_bindgen]
+pub fn mesh_blob(handle: u32) -> Result<Uint8Array, JsValue> {
+    with_mesh(handle, |chunk| {
+        Uint8Array::from(&blob::write_blob(chunk)[..])
+    })
+}
+
+/// Binary glTF of a mesh from `generate_mesh_handle`, for inspection in
+/// external tools. `per_meshlet` (default true) makes every meshlet and skirt
+/// face its own primitive.
+#[wasm_bindgen]
+pub fn export_glb(handle: u32, per_meshlet: Option<bool>) -> Result<Uint8Array, JsValue> {
+    with_mesh(handle, |chunk| {
+        Uint8Array::from(&write_glb(chunk, per_meshlet.unwrap_or(true))[..])
+    })
+}
+
+/// Wavefront OBJ text of a mesh from `generate_mesh_handle`, grouped like `export_glb`
+#[wasm_bindgen]
+pub fn export_obj(handle: u32, per_meshlet: Option<bool>) -> Result<Uint8Array, JsValue> {
+    with_mesh(handle, |chunk| {
+        Uint8Array::from(write_obj(chunk, per_meshlet.unwrap_or(true)).as_bytes())
+    })
+}
+
+/// Validates a blob, e.g. from the disk cache, and keeps it as a mesh handle
+#[wasm_bindgen]
+pub fn load_mesh_blob(bytes: &[u8]) -> Result<u32, JsValue> {
+    let chunk = blob::read_blob(bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(MESHES.with(|meshes| meshes.borrow_mut().insert(chunk)))
+}
+
+fn with_mesh<T>(handle: u32, f: impl FnOnce(&Chunk) -> T) -> Result<T, JsValue> {
+    MESHES.with(|meshes| {
+        meshes
+            .borrow()
+            .get(handle)
+            .map(f)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown mesh handle {}", handle)))
+    })
+}
+
+fn mesh_options(
+    neighbor_lods: &[u32],
+    skirt: Option<Skirt>,
+    mesher: Option<Mesher>,
+    index_format: Option<IndexFormat>,
+    vertex_format: Option<VertexFormat>,
+) -> MeshOptions {
+    MeshOptions {
+        mesher: mesher.unwrap_or(Mesher::MarchingCubes),
+        neighbor_lods: neighbor_lods_array(neighbor_lods),
+        skirt,
+        index_format: index_format.unwrap_or(IndexFormat::Uint16),
+        vertex_format: vertex_format.unwrap_or(VertexFormat::Float),
+    }
+}
+
+fn mesh_result(chunk: &Chunk) -> MeshResult {
+    // JS-owned copies of the data (not views into WASM memory)
+    let commands: Vec<u32> = chunk
+        .commands()
+        .iter()
+        .flat_map(Command::to_words)
+        .collect();
+    let metadata = metadata(chunk);
+
+    MeshResult {
+        vertices: Float32Array::from(chunk.vertices()),
+        normals: Float32Array::from(chunk.normals()),
+        colors: Uint32Array::from(chunk.colors()),
+        material_colors: Uint32Array::from(chunk.material_colors()),
+        packed_vertices: Uint32Array::from(chunk.packed_vertices()),
+        commands: Uint32Array::from(&commands[..]), // 5 u32s per command (DrawIndexedIndirect)
+        split_from: Uint32Array::from(chunk.split_from()),
+        bounds: Float32Array::from(chunk.bounds()),
+        densities: Uint32Array::from(chunk.densities()),
+        vertex_counts: Uint32Array::from(chunk.vertex_counts()),
+        index_format: chunk.index_format(),
+        indices: Uint16Array::from(chunk.indices()),
+        indices_u32: Uint32Array::from(chunk.indices_u32()),
+        buffer_sizes: buffer_sizes(&metadata),
+        metadata,
+    }
+}
+
+fn graph_error(error: graph::GraphError) -> JsValue {
+    JsValue::from_str(&error.to_string())
+}
+
+/// JS-side builder for a density graph. Every method returns the new node id,
+/// which later nodes use as their inputs.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct TerrainGraph {
+    graph: DensityGraph,
+}
+
+#[wasm_bindgen]
+impl TerrainGraph {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> TerrainGraph {
+        TerrainGraph::default()
+    }
+
+    pub fn from_json(json: &str) -> Result<TerrainGraph, JsValue> {
+        let graph = format::parse_graph(json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(TerrainGraph { graph })
+    }
+
+    pub fn to_json(&self) -> String {
+        format::write_graph(&self.graph)
+    }
+
+    fn push(&mut self, node: Node) -> Result<NodeId, JsValue> {
+        self.graph.push(node).map_err(graph_error)
+    }
+
+    pub fn set_root(&mut self, root: NodeId) -> Result<(), JsValue> {
+        self.graph.set_root(root).map_err(graph_error)
+    }
+
+    pub fn constant(&mut self, value: f32) -> Result<NodeId, JsValue> {
+        self.push(Node::Constant(value))
+    }
+
+    /// World-space coordinate, 0 = X, 1 = Y, 2 = Z
+    pub fn coordinate(&mut self, axis: u32) -> Result<NodeId, JsValue> {
+        self.push(Node::Coordinate(axis as usize))
+    }
+
+    pub fn noise(&mut self, kind: NoiseKind, seed: u32, frequency: f32) -> Result<NodeId, JsValue> {
+        self.graph.noise(kind, seed, frequency).map_err(graph_error)
+    }
+
+    pub fn fbm(
+        &mut self,
+        input: NodeId,
+        octaves: u32,
+        lacunarity: f32,
+        persistence: f32,
+    ) -> Result<NodeId, JsValue> {
+        self.fractal(input, FractalMode::Fbm, octaves, lacunarity, persistence)
+    }
+
+    pub fn ridged(
+        &mut self,
+        input: NodeId,
+        octaves: u32,
+        lacunarity: f32,
+        persistence: f32,
+    ) -> Result<NodeId, JsValue> {
+        self.fractal(input, FractalMode::Ridged, octaves, lacunarity, persistence)
+    }
+
+    pub fn billow(
+        &mut self,
+        input: NodeId,
+        octaves: u32,
+        lacunarity: f32,
+        persistence: f32,
+    ) -> Result<NodeId, JsValue> {
+        self.fractal(input, FractalMode::Billow, octaves, lacunarity, persistence)
+    }
+
+    fn fractal(
+        &mut self,
+        input: NodeId,
+        mode: FractalMode,
+        octaves: u32,
+        lacunarity: f32,
+        persistence: f32,
+    ) -> Result<NodeId, JsValue> {
+        self.push(Node::Fractal {
+            input,
+            mode,
+            octaves,
+            lacunarity,
+            persistence,
+        })
+    }
+
+    /// Domain warp, pass undefined for axes that should not be displaced
+    pub fn warp(
+        &mut self,
+        input: NodeId,
+        warp_x: Option<NodeId>,
+        warp_y: Option<NodeId>,
+        warp_z: Option<NodeId>,
+        amount: f32,
+    ) -> Result<NodeId, JsValue> {
+        self.push(Node::Warp {
+            input,
+            warp: [warp_x, warp_y, warp_z],
+            amount,
+        })
+    }
+
+    pub fn sphere(&mut self, x: f32, y: f32, z: f32, radius: f32) -> Result<NodeId, JsValue> {
+        self.push(Node::Sphere {
+            center: [x, y, z],
+            radius,
+        })
+    }
+
+    #[wasm_bindgen(js_name = box)]
+    pub fn cuboid(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        half_x: f32,
+        half_y: f32,
+        half_z: f32,
+    ) -> Result<NodeId, JsValue> {
+        self.push(Node::Box {
+            center: [x, y, z],
+            half_extents: [half_x, half_y, half_z],
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn capsule(
+        &mut self,
+        ax: f32,
+        ay: f32,
+        az: f32,
+        bx: f32,
+        by: f32,
+        bz: f32,
+        radius: f32,
+    ) -> Result<NodeId, JsValue> {
+        self.push(Node::Capsule {
+            a: [ax, ay, az],
+            b: [bx, by, bz],
+            radius,
+        })
+    }
+
+    /// Solid below the plane; the normal is normalized here
+    pub fn plane(&mut self, nx: f32, ny: f32, nz: f32, height: f32) -> Result<NodeId, JsValue> {
+        let length = (nx * nx + ny * ny + nz * nz).sqrt();
+        if length == 0.0 {
+            return Err(JsValue::from_str("plane normal must not be zero"));
+        }
+        self.push(Node::Plane {
+            normal: [nx / length, ny / length, nz / length],
+            height,
+        })
+    }
+
+    pub fn add(&mut self, a: NodeId, b: NodeId) -> Result<NodeId, JsValue> {
+        self.push(Node::Add(a, b))
+    }
+
+    pub fn subtract(&mut self, a: NodeId, b: NodeId) -> Result<NodeId, JsValue> {
+        self.push(Node::Subtract(a, b))
+    }
+
+    pub fn multiply(&mut self, a: NodeId, b: NodeId) -> Result<NodeId, JsValue> {
+        self.push(Node::Multiply(a, b))
+    }
+
+    pub fn smooth_union(&mut self, a: NodeId, b: NodeId, blend: f32) -> Result<NodeId, JsValue> {
+        self.push(Node::SmoothUnion(a, b, blend))
+    }
+
+    pub fn smooth_subtraction(
+        &mut self,
+        a: NodeId,
+        b: NodeId,
+        blend: f32,
+    ) -> Result<NodeId, JsValue> {
+        self.push(Node::SmoothSubtraction(a, b, blend))
+    }
+
+    pub fn smooth_intersection(
+        &mut self,
+        a: NodeId,
+        b: NodeId,
+        blend: f32,
+    ) -> Result<NodeId, JsValue> {
+        self.push(Node::SmoothIntersection(a, b, blend))
+    }
+
+    pub fn clamp(&mut self, input: NodeId, min: f32, max: f32) -> Result<NodeId, JsValue> {
+        self.push(Node::Clamp { input, min, max })
+    }
+
+    pub fn remap(
+        &mut self,
+        input: NodeId,
+        from_min: f32,
+        from_max: f32,
+        to_min: f32,
+        to_max: f32,
+    ) -> Result<NodeId, JsValue> {
+        self.push(Node::Remap {
+            input,
+            from: [from_min, from_max],
+            to: [to_min, to_max],
+        })
+    }
+
+    /// Curve points as flat [input0, output0, input1, output1, ...] pairs
+    pub fn height_curve(&mut self, input: NodeId, points: Vec<f32>) -> Result<NodeId, JsValue> {
+        if !points.len().is_multiple_of(2) {
+            return Err(JsValue::from_str("height curve points must come in pairs"));
+        }
+        let points = points.chunks_exact(2).map(|p| [p[0], p[1]]).collect();
+        self.push(Node::HeightCurve { input, points })
+    }
+
+    /// The built-in heightfield generator, so graphs can carve into the default world
+    pub fn terrain(&mut self, params: &TerrainParams) -> Result<NodeId, JsValue> {
+        self.push(Node::Terrain(*params))
+    }
+}
@@ -0,0 +1,9 @@
+// Cargo runner for wasm32-wasip1 test binaries (see .cargo/config.toml).
+import { readFileSync } from "node:fs";
+import { WASI } from "node:wasi";
+
+const [binary, ...args] = process.argv.slice(2);
+const wasi = new WASI({ version: "preview1", args: [binary, ...args] });
+const module = await WebAssembly.compile(readFileSync(binary));
+const instance = await WebAssembly.instantiate(module, wasi.getImportObject());
+process.exitCode = wasi.start(instance);
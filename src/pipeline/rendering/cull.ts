@@ -145,10 +145,13 @@ export class Cull {
 			usage: GPUBufferUsage.COPY_DST | GPUBufferUsage.MAP_READ,
 		});
 
+		// Room for every draw, skirt and split draws follow the meshlet grid
+		const drawCount = Math.max(Math.pow(gridSize / compression, 3), chunk.vertexCounts.size / 4);
+
 		// Create per-chunk indices buffer
 		const indicesBuffer = device.createBuffer({
 			label: `${chunkLabel} Cull Indices`,
-			size: Math.pow(gridSize / compression, 3) * 4,
+			size: drawCount * 4,
 			usage:
 				GPUBufferUsage.STORAGE |
 				GPUBufferUsage.COPY_SRC |
@@ -157,7 +160,7 @@ export class Cull {
 
 		const indicesReadback = device.createBuffer({
 			label: `${chunkLabel} Cull Indices Readback`,
-			size: Math.pow(gridSize / compression, 3) * 4,
+			size: drawCount * 4,
 			usage: GPUBufferUsage.COPY_DST | GPUBufferUsage.MAP_READ,
 		});
 
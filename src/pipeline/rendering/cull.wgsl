@@ -160,8 +160,25 @@ fn test_density_occlusion(block_pos: vec3<u32>) -> bool {
 	return true;
 }
 
+fn emit(index: u32) {
+	let pointer = atomicAdd(&counter, 1u);
+	if (pointer < arrayLength(&indices)) {
+		indices[pointer] = index;
+	}
+}
+
 @compute @workgroup_size(4, 4, 4)
 fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+	// Skirt and split draws come after the meshlet grid and have no density
+	// cell or block of their own, the first thread emits them when not empty
+	if (all(id == vec3<u32>(0u))) {
+		for (var draw = cells_per_chunk(); draw < arrayLength(&vertexCounts); draw++) {
+			if (vertexCounts[draw] > 0u) {
+				emit(draw);
+			}
+		}
+	}
+
 	// Early exit if this thread is beyond the chunk's meshlet grid
 	let meshlet_count = chunk_block_count();
 	if (id.x >= meshlet_count || id.y >= meshlet_count || id.z >= meshlet_count) {
@@ -181,8 +198,7 @@ fn main(@builtin(global_invocation_id) id: vec3<u32>) {
 		if (test_aabb_frustum(aabb, context.view_projection)) {
 			// Density occlusion culling: check if path to camera is blocked
 			if (!test_density_occlusion(block_pos)) {
-				let pointer = atomicAdd(&counter, 1u);
-				indices[pointer] = index;
+				emit(index);
 			}
 		}
 	}
@@ -1,6 +1,9 @@
 import {Request, Result} from "./generation/message";
 
-import init, {generate_mesh} from "src/my-lib/pkg"
+import init, * as pkg from "src/my-lib/pkg"
+import {generate_mesh, load_generator, set_voxel_cache_budget} from "src/my-lib/pkg"
+import world from "./generation/world.json";
+import {THREADS_PER_WORKER, VOXEL_CACHE_BYTES, WORKERS} from "./generation/scheduler";
 
 let initialized = false;
 
@@ -8,6 +11,14 @@ onmessage = async (e: MessageEvent<Request>) => {
 
 	if (!initialized) {
 		await init();
+		if (process.env.WASM_THREADS === 'true') {
+			// Only threaded builds export it, see build:threads
+			const {initThreadPool} = pkg as unknown as {initThreadPool: (threads: number) => Promise<void>};
+			await initThreadPool(THREADS_PER_WORKER);
+		}
+		// World definition must be loaded before any generate_mesh call
+		load_generator(JSON.stringify(world));
+		set_voxel_cache_budget(Math.floor(VOXEL_CACHE_BYTES / WORKERS));
 		initialized = true;
 	}
 